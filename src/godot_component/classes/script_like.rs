@@ -1,20 +1,29 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use anyhow::Result as AnyResult;
 use godot::prelude::*;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
-use wasmtime::component::{Linker, Resource as WasmResource};
-use wasmtime::Store;
+use slab::Slab;
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{
+    Component, Func, Linker, Resource as WasmResource, ResourceAny, Type as CompType, Val,
+};
+use wasmtime::{AsContextMut, Engine, Store, StoreContextMut};
 
 use crate::godot_component::filter::Filter;
 use crate::godot_component::{add_to_linker, GodotCtx};
-use crate::godot_util::PhantomProperty;
+use crate::godot_util::{from_var_any, PhantomProperty, SendSyncWrapper};
 use crate::wasm_config::Config;
-use crate::wasm_engine::WasmModule;
+use crate::wasm_engine::{LiveInstanceGuard, WasmModule};
 #[cfg(feature = "memory-limiter")]
 use crate::wasm_instance::MemoryLimit;
 use crate::wasm_instance::{InnerLock, InstanceData, InstanceType};
 #[cfg(feature = "epoch-timeout")]
 use crate::wasm_util::config_store_epoch;
+#[cfg(feature = "fuel")]
+use crate::wasm_util::config_store_fuel;
 use crate::{bail_with_site, site_context};
 
 pub mod bindgen {
@@ -80,6 +89,25 @@ pub struct WasmScriptLike {
 pub struct WasmScriptLikeData {
     instance: InstanceData<WasmScriptLikeStore>,
     bindings: bindgen::Script,
+    /// Call plans of resource methods, keyed by (resource, method).
+    method_cache: Mutex<HashMap<(String, String), MethodPlan>>,
+}
+
+/// Returns `true` if type is a list or record, which cannot be nested.
+fn is_compound(ty: &CompType) -> bool {
+    matches!(ty, CompType::List(_) | CompType::Record(_))
+}
+
+/// Resolved function and its parameter types.
+///
+/// Resource methods are resolved once and reused, other exports are resolved on every call.
+/// Values are still converted on every call.
+#[derive(Clone)]
+struct MethodPlan {
+    func: Func,
+    params: Arc<[CompType]>,
+    n_results: usize,
+    has_self: bool,
 }
 
 pub struct WasmScriptLikeStore {
@@ -92,6 +120,9 @@ pub struct WasmScriptLikeStore {
     memory_limits: MemoryLimit,
 
     godot_ctx: GodotCtx,
+
+    /// Guest resources handed to host.
+    resources: Slab<ResourceAny>,
 }
 
 impl AsRef<InnerLock> for WasmScriptLikeStore {
@@ -119,9 +150,11 @@ impl WasmScriptLike {
         module: Gd<WasmModule>,
     ) -> AnyResult<WasmScriptLikeData> {
         let comp = site_context!(module.bind().get_data()?.module.get_component())?.clone();
+        let live_guard = LiveInstanceGuard::new(&module.bind(), config.max_instances)?;
 
         let mut godot_ctx = GodotCtx::new(inst_id);
         godot_ctx.filter = filter;
+        godot_ctx.config = config.component_config.clone().map(SendSyncWrapper::new);
         let mut store = Store::new(
             comp.engine(),
             WasmScriptLikeStore {
@@ -138,27 +171,289 @@ impl WasmScriptLike {
                 memory_limits: MemoryLimit::from_config(&config),
 
                 godot_ctx,
+                resources: Slab::new(),
             },
         );
         #[cfg(feature = "epoch-timeout")]
         config_store_epoch(&mut store, &config)?;
+        #[cfg(feature = "fuel")]
+        config_store_fuel(&mut store)?;
         #[cfg(feature = "memory-limiter")]
         store.limiter(|data| &mut data.memory_limits);
 
         let mut linker = <Linker<WasmScriptLikeStore>>::new(store.engine());
         site_context!(add_to_linker(&mut linker, |v| v))?;
 
-        let bindings = site_context!(bindgen::Script::instantiate(&mut store, &comp, &linker))?;
+        let instance = site_context!(linker.instantiate(&mut store, &comp))?;
+        let bindings = site_context!(bindgen::Script::new(&mut store, &instance))?;
 
         Ok(WasmScriptLikeData {
             instance: InstanceData {
                 store: Mutex::new(store),
-                instance: InstanceType::NoInstance,
+                instance: InstanceType::Component(instance),
                 module,
+                export_cache: None,
+                memory_imports: HashMap::new(),
+                import_bindings: Vec::new(),
+                live_guard,
+                memory_exhausted: Arc::default(),
+                #[cfg(feature = "epoch-timeout")]
+                interrupt: None,
 
+                #[cfg(feature = "wasi")]
                 wasi_stdin: None,
+                #[cfg(feature = "wasi")]
+                wasi_buffers: Vec::new(),
             },
             bindings,
+            method_cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Finds all exported functions of a resource.
+    ///
+    /// Returns list of (instance name, function name, method name).
+    fn resource_funcs(
+        engine: &Engine,
+        comp: &Component,
+        resource: &str,
+    ) -> Vec<(Option<String>, String, String)> {
+        fn method_name(resource: &str, name: &str) -> Option<String> {
+            if let Some(v) = name.strip_prefix("[constructor]") {
+                return (v == resource).then(|| "constructor".to_string());
+            }
+            let v = name
+                .strip_prefix("[method]")
+                .or_else(|| name.strip_prefix("[static]"))?;
+            match v.split_once('.') {
+                Some((r, m)) if r == resource => Some(m.to_string()),
+                _ => None,
+            }
+        }
+
+        let mut ret = Vec::new();
+        for (name, item) in comp.component_type().exports(engine) {
+            match item {
+                ComponentItem::ComponentFunc(_) => {
+                    if let Some(m) = method_name(resource, name) {
+                        ret.push((None, name.to_string(), m));
+                    }
+                }
+                ComponentItem::ComponentInstance(inst) => {
+                    for (n, item) in inst.exports(engine) {
+                        if let ComponentItem::ComponentFunc(_) = item {
+                            if let Some(m) = method_name(resource, n) {
+                                ret.push((Some(name.to_string()), n.to_string(), m));
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        ret
+    }
+
+    fn method_plan(
+        m: &WasmScriptLikeData,
+        mut store: StoreContextMut<'_, WasmScriptLikeStore>,
+        resource: &str,
+        method: &str,
+    ) -> AnyResult<MethodPlan> {
+        let comp = m
+            .instance
+            .module
+            .bind()
+            .get_data()?
+            .module
+            .get_component()?
+            .clone();
+        let instance = *m.instance.instance.get_component()?;
+        let Some((inst_name, name, _)) = Self::resource_funcs(comp.engine(), &comp, resource)
+            .into_iter()
+            .find(|(_, _, m)| m == method)
+        else {
+            bail_with_site!("Method {resource}.{method} not found")
+        };
+
+        let ix = match inst_name {
+            Some(n) => match instance.get_export(&mut store, None, &n) {
+                Some(v) => Some(v),
+                None => bail_with_site!("Instance {n} not found"),
+            },
+            None => None,
+        };
+        let Some(func) = instance
+            .get_export(&mut store, ix.as_ref(), &name)
+            .and_then(|ix| instance.get_func(&mut store, ix))
+        else {
+            bail_with_site!("Function {name} not found")
+        };
+
+        Ok(MethodPlan {
+            params: func.params(&store).into(),
+            n_results: func.results(&store).len(),
+            has_self: name.starts_with("[method]"),
+            func,
+        })
+    }
+
+    fn call_plan(
+        mut store: StoreContextMut<'_, WasmScriptLikeStore>,
+        plan: &MethodPlan,
+        resource_handle: i64,
+        args: VariantArray,
+    ) -> AnyResult<Variant> {
+        let f = plan.func;
+
+        let mut args = args.iter_shared();
+        let mut vals = Vec::with_capacity(plan.params.len());
+        for (i, t) in plan.params.iter().enumerate() {
+            let v = if i == 0 && plan.has_self {
+                resource_handle.to_variant()
+            } else {
+                match args.next() {
+                    Some(v) => v,
+                    None => bail_with_site!("Too few arguments"),
+                }
+            };
+            vals.push(Self::var_to_val(store.data(), t, &v)?);
+        }
+
+        let mut results = vec![Val::Bool(false); plan.n_results];
+        site_context!(f.call(&mut store, &vals, &mut results))?;
+        site_context!(f.post_return(&mut store))?;
+
+        let data = store.data_mut();
+        let mut ret = results
+            .into_iter()
+            .map(|v| Self::val_to_var(data, v))
+            .collect::<AnyResult<Vec<_>>>()?;
+        Ok(match ret.len() {
+            0 => Variant::nil(),
+            1 => ret.pop().unwrap(),
+            _ => VariantArray::from(&*ret).to_variant(),
+        })
+    }
+
+    fn var_to_val(store: &WasmScriptLikeStore, ty: &CompType, v: &Variant) -> AnyResult<Val> {
+        Ok(match ty {
+            CompType::Bool => Val::Bool(from_var_any(v)?),
+            CompType::S8 => Val::S8(from_var_any(v)?),
+            CompType::U8 => Val::U8(from_var_any(v)?),
+            CompType::S16 => Val::S16(from_var_any(v)?),
+            CompType::U16 => Val::U16(from_var_any(v)?),
+            CompType::S32 => Val::S32(from_var_any(v)?),
+            CompType::U32 => Val::U32(from_var_any(v)?),
+            CompType::S64 => Val::S64(from_var_any(v)?),
+            CompType::U64 => Val::U64(from_var_any::<i64>(v)? as _),
+            CompType::Float32 => Val::Float32(from_var_any(v)?),
+            CompType::Float64 => Val::Float64(from_var_any(v)?),
+            CompType::Char => {
+                let s = from_var_any::<GString>(v)?.to_string();
+                let mut it = s.chars();
+                match (it.next(), it.next()) {
+                    (Some(c), None) => Val::Char(c),
+                    _ => bail_with_site!("Value {s:?} is not a single character"),
+                }
+            }
+            CompType::String => Val::String(from_var_any::<GString>(v)?.to_string()),
+            CompType::Own(_) | CompType::Borrow(_) => {
+                let i = from_var_any::<i64>(v)?;
+                match usize::try_from(i).ok().and_then(|i| store.resources.get(i)) {
+                    Some(r) => Val::Resource(*r),
+                    None => bail_with_site!("Invalid resource handle {i}"),
+                }
+            }
+            CompType::List(l) => {
+                let ty = l.ty();
+                if is_compound(&ty) {
+                    bail_with_site!("Nested parameter type {ty:?} is not supported");
+                }
+                if let (CompType::String, Ok(v)) = (&ty, v.try_to::<PackedStringArray>()) {
+                    Val::List(
+                        v.as_slice()
+                            .iter()
+                            .map(|v| Val::String(v.to_string()))
+                            .collect(),
+                    )
+                } else {
+                    Val::List(
+                        from_var_any::<VariantArray>(v)?
+                            .iter_shared()
+                            .map(|v| Self::var_to_val(store, &ty, &v))
+                            .collect::<AnyResult<_>>()?,
+                    )
+                }
+            }
+            CompType::Record(r) => {
+                let d = from_var_any::<Dictionary>(v)?;
+                Val::Record(
+                    r.fields()
+                        .map(|f| {
+                            if is_compound(&f.ty) {
+                                bail_with_site!(
+                                    "Nested parameter type {:?} is not supported",
+                                    f.ty
+                                );
+                            }
+                            let Some(v) = d.get(GString::from(f.name)) else {
+                                bail_with_site!("Missing record field {:?}", f.name);
+                            };
+                            Ok((f.name.to_string(), Self::var_to_val(store, &f.ty, &v)?))
+                        })
+                        .collect::<AnyResult<_>>()?,
+                )
+            }
+            t => bail_with_site!("Unsupported parameter type {t:?}"),
+        })
+    }
+
+    fn val_to_var(store: &mut WasmScriptLikeStore, v: Val) -> AnyResult<Variant> {
+        Ok(match v {
+            Val::Bool(v) => v.to_variant(),
+            Val::S8(v) => v.to_variant(),
+            Val::U8(v) => v.to_variant(),
+            Val::S16(v) => v.to_variant(),
+            Val::U16(v) => v.to_variant(),
+            Val::S32(v) => v.to_variant(),
+            Val::U32(v) => v.to_variant(),
+            Val::S64(v) => v.to_variant(),
+            Val::U64(v) => (v as i64).to_variant(),
+            Val::Float32(v) => v.to_variant(),
+            Val::Float64(v) => v.to_variant(),
+            Val::Char(v) => GString::from(v.to_string()).to_variant(),
+            Val::String(v) => GString::from(v).to_variant(),
+            Val::Resource(v) => (store.resources.insert(v) as i64).to_variant(),
+            Val::List(v) if v.iter().all(|v| matches!(v, Val::String(_))) => v
+                .into_iter()
+                .map(|v| match v {
+                    Val::String(v) => GString::from(v),
+                    _ => unreachable!(),
+                })
+                .collect::<PackedStringArray>()
+                .to_variant(),
+            Val::List(v) => v
+                .into_iter()
+                .map(|v| match v {
+                    Val::List(_) | Val::Record(_) => {
+                        bail_with_site!("Nested result value {v:?} is not supported")
+                    }
+                    v => Self::val_to_var(store, v),
+                })
+                .collect::<AnyResult<VariantArray>>()?
+                .to_variant(),
+            Val::Record(v) => {
+                let mut d = Dictionary::new();
+                for (k, v) in v {
+                    if matches!(v, Val::List(_) | Val::Record(_)) {
+                        bail_with_site!("Nested result value {v:?} is not supported");
+                    }
+                    d.set(k, Self::val_to_var(store, v)?);
+                }
+                d.to_variant()
+            }
+            v => bail_with_site!("Unsupported result value {v:?}"),
         })
     }
 
@@ -270,4 +565,130 @@ impl WasmScriptLike {
         })
         .unwrap_or_default()
     }
+
+    /// Lists all exported methods of a resource type.
+    ///
+    /// Constructor is listed as `"constructor"`.
+    #[func]
+    fn component_list_methods(&self, resource_type: GString) -> PackedStringArray {
+        self.unwrap_data(|m| {
+            let comp = m
+                .instance
+                .module
+                .bind()
+                .get_data()?
+                .module
+                .get_component()?
+                .clone();
+            Ok(
+                Self::resource_funcs(comp.engine(), &comp, &resource_type.to_string())
+                    .into_iter()
+                    .map(|(_, _, m)| GString::from(m))
+                    .collect(),
+            )
+        })
+        .unwrap_or_default()
+    }
+
+    /// Calls exported method of a resource.
+    ///
+    /// Resource handles are integers returned from previous calls.
+    /// For static methods and constructor, `resource_handle` is ignored.
+    /// Only scalar, string, and resource types are supported.
+    #[func]
+    fn component_call_method(
+        &self,
+        resource_handle: i64,
+        resource_type: GString,
+        method: GString,
+        args: VariantArray,
+    ) -> Variant {
+        self.unwrap_data(move |m| {
+            m.instance.acquire_store(move |_, mut store| {
+                #[cfg(feature = "epoch-timeout")]
+                if let v @ 1.. = store.data().epoch_timeout {
+                    store.set_epoch_deadline(v);
+                }
+
+                let key = (resource_type.to_string(), method.to_string());
+                let plan = m.method_cache.lock().get(&key).cloned();
+                let plan = match plan {
+                    Some(v) => v,
+                    None => {
+                        let plan = Self::method_plan(m, store.as_context_mut(), &key.0, &key.1)?;
+                        m.method_cache.lock().insert(key, plan.clone());
+                        plan
+                    }
+                };
+
+                Self::call_plan(store, &plan, resource_handle, args)
+            })
+        })
+        .unwrap_or_default()
+    }
+
+    /// Calls exported function inside nested instances.
+    ///
+    /// `module_path` is list of instance names, from outermost to innermost.
+    /// Empty path calls top-level export.
+    /// Only scalar, string, and resource types are supported.
+    #[func]
+    fn call_wasm_qualified(
+        &self,
+        module_path: PackedStringArray,
+        name: GString,
+        args: VariantArray,
+    ) -> Variant {
+        self.unwrap_data(move |m| {
+            m.instance.acquire_store(move |_, mut store| {
+                #[cfg(feature = "epoch-timeout")]
+                if let v @ 1.. = store.data().epoch_timeout {
+                    store.set_epoch_deadline(v);
+                }
+
+                let instance = *m.instance.instance.get_component()?;
+                let mut ix = None;
+                for p in module_path.as_slice() {
+                    let p = p.to_string();
+                    ix = match instance.get_export(&mut store, ix.as_ref(), &p) {
+                        Some(v) => Some(v),
+                        None => bail_with_site!("Instance {p} not found"),
+                    };
+                }
+                let name = name.to_string();
+                let Some(func) = instance
+                    .get_export(&mut store, ix.as_ref(), &name)
+                    .and_then(|ix| instance.get_func(&mut store, ix))
+                else {
+                    bail_with_site!("Function {name} not found")
+                };
+
+                let plan = MethodPlan {
+                    params: func.params(&store).into(),
+                    n_results: func.results(&store).len(),
+                    has_self: false,
+                    func,
+                };
+                Self::call_plan(store, &plan, 0, args)
+            })
+        })
+        .unwrap_or_default()
+    }
+
+    /// Drops resource handle.
+    #[func]
+    fn component_drop_resource(&self, resource_handle: i64) {
+        self.unwrap_data(move |m| {
+            m.instance.acquire_store(move |_, mut store| {
+                let r = match usize::try_from(resource_handle)
+                    .ok()
+                    .and_then(|i| store.data_mut().resources.try_remove(i))
+                {
+                    Some(r) => r,
+                    None => bail_with_site!("Invalid resource handle {resource_handle}"),
+                };
+                site_context!(r.resource_drop(&mut store))
+            })
+        });
+    }
 }
@@ -14,6 +14,7 @@ macro_rules! impl_packed_array {
                 from -> "from",
                 to -> "to",
                 slice -> "slice",
+                read_chunk -> "read-chunk",
                 len -> "len",
                 is_empty -> "is-empty",
                 get -> "get",
@@ -50,6 +51,22 @@ macro_rules! impl_packed_array {
                 Ok(v.to_owned())
             }
 
+            fn read_chunk(
+                &mut self,
+                var: WasmResource<Variant>,
+                offset: u32,
+                len: u32,
+            ) -> AnyResult<Vec<$m::Elem>> {
+                filter_macro!(filter self.filter.as_ref(), godot_core, $m, read_chunk)?;
+                let v: $t = self.get_value(var)?;
+                let v = v.as_slice();
+                let Some(v) = v.get(offset as usize..) else {
+                    bail!("offset {offset} out of bound")
+                };
+                let v = &v[..v.len().min(len as usize)];
+                Ok(v.to_owned())
+            }
+
             fn len(&mut self, var: WasmResource<Variant>) -> AnyResult<u32> {
                 filter_macro!(filter self.filter.as_ref(), godot_core, $m, len)?;
                 Ok(self.get_value::<$t>(var)?.len() as _)
@@ -119,6 +136,7 @@ macro_rules! impl_packed_array {
                 from -> "from",
                 to -> "to",
                 slice -> "slice",
+                read_chunk -> "read-chunk",
                 len -> "len",
                 is_empty -> "is-empty",
                 get -> "get",
@@ -156,6 +174,22 @@ macro_rules! impl_packed_array {
                 Ok(v.iter().map(|$v| $e2).collect())
             }
 
+            fn read_chunk(
+                &mut self,
+                var: WasmResource<Variant>,
+                offset: u32,
+                len: u32,
+            ) -> AnyResult<Vec<$m::Elem>> {
+                filter_macro!(filter self.filter.as_ref(), godot_core, $m, read_chunk)?;
+                let v: $t = self.get_value(var)?;
+                let v = v.as_slice();
+                let Some(v) = v.get(offset as usize..) else {
+                    bail!("offset {offset} out of bound")
+                };
+                let v = &v[..v.len().min(len as usize)];
+                Ok(v.iter().map(|$v| $e2).collect())
+            }
+
             fn len(&mut self, var: WasmResource<Variant>) -> AnyResult<u32> {
                 filter_macro!(filter self.filter.as_ref(), godot_core, $m, len)?;
                 Ok(self.get_value::<$t>(var)?.len() as _)
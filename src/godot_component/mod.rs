@@ -23,6 +23,7 @@ filter_macro! {module [
 mod reflection_filter {
     crate::filter_macro! {interface [
         this <this_filter> -> "this",
+        config <config_filter> -> "config",
     ]}
 
     mod this_filter {
@@ -30,6 +31,12 @@ mod reflection_filter {
             get_this -> "get-this",
         ]}
     }
+
+    mod config_filter {
+        crate::filter_macro! {method [
+            get -> "get",
+        ]}
+    }
 }
 
 #[derive(Default)]
@@ -37,6 +44,8 @@ pub struct GodotCtx {
     table: Slab<SendSyncWrapper<Variant>>,
     pub inst_id: Option<InstanceId>,
     pub filter: filter::Filter,
+    /// Host-provided configuration, readable through `godot:reflection/config`.
+    pub config: Option<SendSyncWrapper<Dictionary>>,
 }
 
 impl AsMut<GodotCtx> for GodotCtx {
@@ -244,6 +253,17 @@ impl bindgen::godot::reflection::this::Host for GodotCtx {
     }
 }
 
+impl bindgen::godot::reflection::config::Host for GodotCtx {
+    fn get(&mut self, key: String) -> AnyResult<Option<WasmResource<Variant>>> {
+        filter_macro!(filter self.filter.as_ref(), godot_reflection, config, get)?;
+        let v = match &self.config {
+            Some(d) => d.get(GString::from(key)).unwrap_or_default(),
+            None => Variant::nil(),
+        };
+        self.set_var(v)
+    }
+}
+
 pub fn add_to_linker<T, U: AsMut<GodotCtx> + 'static>(
     linker: &mut Linker<T>,
     f: impl Fn(&mut T) -> &mut U + Send + Sync + Copy + 'static,
@@ -284,5 +304,6 @@ pub fn add_to_linker<T, U: AsMut<GodotCtx> + 'static>(
     bindgen::godot::global::input_map::add_to_linker(&mut *linker, f)?;
     bindgen::godot::global::ip::add_to_linker(&mut *linker, f)?;
 
-    bindgen::godot::reflection::this::add_to_linker(&mut *linker, f)
+    bindgen::godot::reflection::this::add_to_linker(&mut *linker, f)?;
+    bindgen::godot::reflection::config::add_to_linker(&mut *linker, f)
 }
@@ -9,6 +9,8 @@ use godot::global::Error as GError;
 use godot::prelude::*;
 use godot::register::property::PropertyHintInfo;
 
+use crate::bail_with_site;
+
 /// WARNING: Incredibly unsafe.
 /// It's just used as workaround to pass Godot objects across closure.
 /// (At least until it supports multi-threading)
@@ -55,6 +57,66 @@ pub fn gstring_from_maybe_utf8(buf: &[u8]) -> GString {
     }
 }
 
+/// Decodes little-endian UTF-16 string, replacing invalid sequences.
+pub fn string_from_utf16_le(buf: &[u8]) -> String {
+    let it = buf
+        .chunks_exact(2)
+        .map(|v| u16::from_le_bytes([v[0], v[1]]));
+    char::decode_utf16(it)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Encodes string as little-endian UTF-16.
+pub fn utf16_le_from_str(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Decodes little-endian UTF-16 string, replacing invalid sequences.
+pub fn gstring_from_utf16_le(buf: &[u8]) -> GString {
+    string_from_utf16_le(buf).into()
+}
+
+/// Decodes base64 string (standard alphabet).
+///
+/// Padding is optional and whitespaces are ignored.
+pub fn decode_base64(s: &str) -> AnyResult<Vec<u8>> {
+    let mut ret = Vec::with_capacity(s.len() / 4 * 3);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    let mut pad = false;
+    for (i, c) in s.char_indices() {
+        let v = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            '=' => {
+                pad = true;
+                continue;
+            }
+            c if c.is_ascii_whitespace() => continue,
+            c => bail_with_site!("Invalid base64 character {c:?} at {i}"),
+        };
+        if pad {
+            bail_with_site!("Base64 data after padding at {i}");
+        }
+
+        acc = (acc << 6) | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            ret.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    if bits >= 6 {
+        bail_with_site!("Truncated base64 data");
+    }
+
+    Ok(ret)
+}
+
 pub fn option_to_variant<T: ToGodot>(t: Option<T>) -> Variant {
     match t {
         Some(v) => v.to_variant(),
@@ -153,6 +215,7 @@ macro_rules! variant_dispatch {
     (#el $v:ident PACKED_VECTOR2_ARRAY $e:expr) => {{ let $v: godot::builtin::PackedVector2Array = $v.to(); $e }};
     (#el $v:ident PACKED_VECTOR3_ARRAY $e:expr) => {{ let $v: godot::builtin::PackedVector3Array = $v.to(); $e }};
     (#el $v:ident PACKED_COLOR_ARRAY $e:expr) => {{ let $v: godot::builtin::PackedColorArray = $v.to(); $e }};
+    (#el $v:ident PACKED_VECTOR4_ARRAY $e:expr) => {{ let $v: godot::builtin::PackedVector4Array = $v.to(); $e }};
 }
 
 // Keep until gdext implement this
@@ -280,6 +343,11 @@ impl_packed_array! {
     PackedColorArray : Color,
 }
 
+#[cfg(feature = "packed-vector4")]
+impl_packed_array! {
+    PackedVector4Array : Vector4,
+}
+
 pub struct ErrorWrapper {
     error: GError,
     msg: Option<String>,
@@ -1005,3 +1073,50 @@ impl StructPacking<f64> for Transform3D {
         <_ as StructPacking<f64>>::write_array(&self.origin, (&mut a[72..]).try_into().unwrap());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_base64() {
+        for (s, v) in [
+            ("", ""),
+            ("Zg==", "f"),
+            ("Zm8=", "fo"),
+            ("Zm9v", "foo"),
+            ("Zm9vYmFy", "foobar"),
+            ("Zg", "f"),
+            ("Zm9v\n YmE=\r\n", "fooba"),
+        ] {
+            assert_eq!(decode_base64(s).unwrap(), v.as_bytes(), "{s:?}");
+        }
+        assert_eq!(decode_base64("+/8=").unwrap(), [0xfb, 0xff]);
+    }
+
+    #[test]
+    fn test_utf16_le() {
+        for s in ["", "abc", "héllo", "日本語", "a\u{1f600}b"] {
+            let v = utf16_le_from_str(s);
+            assert_eq!(v.len(), s.encode_utf16().count() * 2);
+            assert_eq!(string_from_utf16_le(&v), s);
+        }
+        assert_eq!(utf16_le_from_str("a\u{1f600}"), b"a\0\x3d\xd8\x00\xde");
+    }
+
+    #[test]
+    fn test_utf16_le_invalid() {
+        // Lone surrogates.
+        assert_eq!(string_from_utf16_le(b"\x3d\xd8a\0"), "\u{fffd}a");
+        assert_eq!(string_from_utf16_le(b"a\0\x00\xde"), "a\u{fffd}");
+        // Trailing odd byte is ignored.
+        assert_eq!(string_from_utf16_le(b"a\0b"), "a");
+    }
+
+    #[test]
+    fn test_decode_base64_error() {
+        for s in ["Z", "Zm9vY", "Zg==Zg", "Zm9*", "Zm9v-_"] {
+            assert!(decode_base64(s).is_err(), "{s:?}");
+        }
+    }
+}
@@ -11,8 +11,10 @@ mod wasm_engine;
 #[cfg(feature = "object-registry-extern")]
 mod wasm_externref;
 mod wasm_instance;
+mod wasm_metadata;
 #[cfg(feature = "object-registry-compat")]
 mod wasm_objregistry;
+mod wasm_resource;
 mod wasm_util;
 
 use godot::prelude::*;
@@ -29,12 +31,16 @@ unsafe impl ExtensionLibrary for GodotWasm {
     fn on_level_init(level: InitLevel) {
         if level == InitLevel::Servers {
             wasm_engine::init_engine();
+        } else if level == InitLevel::Scene {
+            wasm_resource::register_loader();
         }
     }
 
     fn on_level_deinit(level: InitLevel) {
         if level == InitLevel::Servers {
             wasm_engine::deinit_engine();
+        } else if level == InitLevel::Scene {
+            wasm_resource::unregister_loader();
         }
     }
 }
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use anyhow::Error;
 use godot::prelude::*;
 use once_cell::sync::OnceCell;
@@ -11,14 +14,18 @@ use wasmtime_wasi::{add_to_linker_sync, WasiCtx, WasiCtxBuilder, WasiView};
 use crate::godot_component::filter::Filter;
 #[cfg(feature = "godot-component")]
 use crate::godot_component::{add_to_linker as godot_add_to_linker, GodotCtx};
+#[cfg(feature = "godot-component")]
+use crate::godot_util::SendSyncWrapper;
 use crate::wasi_ctx::WasiContext;
-use crate::wasm_config::Config;
-use crate::wasm_engine::WasmModule;
+use crate::wasm_config::{Config, WasiVersion};
+use crate::wasm_engine::{LiveInstanceGuard, ModuleType, WasmModule};
 #[cfg(feature = "memory-limiter")]
 use crate::wasm_instance::MemoryLimit;
 use crate::wasm_instance::{InnerLock, InstanceData, InstanceType};
 #[cfg(feature = "epoch-timeout")]
 use crate::wasm_util::config_store_epoch;
+#[cfg(feature = "fuel")]
+use crate::wasm_util::config_store_fuel;
 use crate::{bail_with_site, site_context};
 
 #[derive(Default)]
@@ -132,7 +139,21 @@ fn instantiate(
         #[cfg(feature = "godot-component")]
         filter,
     } = config;
-    let comp = site_context!(module.bind().get_data()?.module.get_component())?.clone();
+    if let Some(msg) = config.wasi_version_mismatch(WasiVersion::Preview2) {
+        bail_with_site!("{msg}");
+    }
+    let comp = match &module.bind().get_data()?.module {
+        ModuleType::Component(c) => c.clone(),
+        ModuleType::Core(m) => {
+            if m.imports().any(|i| i.module() == "wasi_snapshot_preview1") {
+                bail_with_site!(
+                    "Module needs WASI preview1; use WasmInstance with wasi.enable config instead"
+                )
+            }
+            bail_with_site!("Module is not a component")
+        }
+    };
+    let live_guard = LiveInstanceGuard::new(&module.bind(), config.max_instances)?;
 
     let mut builder = WasiCtxBuilder::new();
     if let Config {
@@ -152,6 +173,7 @@ fn instantiate(
     let godot_ctx = if use_comp_godot {
         let mut ctx = GodotCtx::new(_inst_id);
         ctx.filter = filter;
+        ctx.config = config.component_config.clone().map(SendSyncWrapper::new);
         Some(ctx)
     } else {
         None
@@ -179,6 +201,8 @@ fn instantiate(
     );
     #[cfg(feature = "epoch-timeout")]
     config_store_epoch(&mut store, &config)?;
+    #[cfg(feature = "fuel")]
+    config_store_fuel(&mut store)?;
     #[cfg(feature = "memory-limiter")]
     store.limiter(|data| &mut data.memory_limits);
 
@@ -198,8 +222,16 @@ fn instantiate(
             store: Mutex::new(store),
             instance: InstanceType::NoInstance,
             module,
+            export_cache: None,
+            memory_imports: HashMap::new(),
+            import_bindings: Vec::new(),
+            live_guard,
+            memory_exhausted: Arc::default(),
+            #[cfg(feature = "epoch-timeout")]
+            interrupt: None,
 
             wasi_stdin: None,
+            wasi_buffers: Vec::new(),
         },
         bindings,
     })
@@ -15,7 +15,7 @@ use nom::{
 };
 
 use crate::godot_util::{from_var_any, StructPacking};
-use crate::{bail_with_site, site_context};
+use crate::{bail_with_site, site_context, variant_dispatch};
 
 #[derive(Clone)]
 pub struct CharSlice<'a>(pub &'a [char]);
@@ -242,6 +242,29 @@ enum DataType {
     Transform3D(FloatSubtype),
 }
 
+impl DataType {
+    /// Size of a single item in bytes.
+    fn size(&self) -> usize {
+        match self {
+            Self::Padding | Self::SignedByte | Self::UnsignedByte => 1,
+            Self::SignedShort | Self::UnsignedShort => 2,
+            Self::SignedInt | Self::UnsignedInt | Self::Float => 4,
+            Self::SignedLong | Self::UnsignedLong | Self::Double => 8,
+            Self::Vector2(t) => 2 * t.size(),
+            Self::Vector3(t) => 3 * t.size(),
+            Self::Vector4(t) | Self::Rect2(t) => 4 * t.size(),
+            Self::Plane(t) | Self::Quaternion(t) => 4 * t.size(),
+            Self::Color(ColorSubtype::Byte) => 4,
+            Self::Color(ColorSubtype::Float) => 16,
+            Self::Color(ColorSubtype::Double) => 32,
+            Self::Aabb(t) | Self::Transform2D(t) => 6 * t.size(),
+            Self::Basis(t) => 9 * t.size(),
+            Self::Transform3D(t) => 12 * t.size(),
+            Self::Projection(t) => 16 * t.size(),
+        }
+    }
+}
+
 enum VectorSubtype {
     Float,
     Double,
@@ -264,6 +287,15 @@ where
     }
 }
 
+impl VectorSubtype {
+    fn size(&self) -> usize {
+        match self {
+            Self::Float | Self::Int => 4,
+            Self::Double | Self::Long => 8,
+        }
+    }
+}
+
 enum ColorSubtype {
     Float,
     Double,
@@ -289,6 +321,15 @@ enum FloatSubtype {
     Double,
 }
 
+impl FloatSubtype {
+    fn size(&self) -> usize {
+        match self {
+            Self::Float => 4,
+            Self::Double => 8,
+        }
+    }
+}
+
 fn parse_float_subtype<I, E>(i: I) -> IResult<I, FloatSubtype, E>
 where
     E: ParseError<I> + ContextError<I>,
@@ -430,7 +471,34 @@ impl<I> ContextError<I> for SingleError<I> {
     }
 }
 
-pub fn read_struct(data: &[u8], p: usize, format: &[char]) -> AnyResult<VariantArray> {
+/// Adds repeat count to total, erroring if it exceeds maximum.
+fn add_count(total: &mut usize, n: usize, max: usize) -> AnyResult<()> {
+    *total = total.saturating_add(n);
+    if *total > max {
+        bail_with_site!("Too many struct elements (maximum {max}, got {total})")
+    }
+    Ok(())
+}
+
+/// Computes size of struct in bytes, including padding.
+pub fn struct_size(format: &[char]) -> AnyResult<usize> {
+    let mut format = CharSlice(format);
+    let mut p_ = pair(opt(u32_), parse_datatype);
+    let mut ret = 0usize;
+    while !format.0.is_empty() {
+        let (i, (n, t)) = p_(format).map_err(|e| e.map(SingleError::into_owned))?;
+        format = i;
+        let n = n.unwrap_or(1) as usize;
+        let Some(v) = n.checked_mul(t.size()).and_then(|v| v.checked_add(ret)) else {
+            bail_with_site!("Struct size overflow");
+        };
+        ret = v;
+    }
+
+    Ok(ret)
+}
+
+pub fn read_struct(data: &[u8], p: usize, format: &[char], max: usize) -> AnyResult<VariantArray> {
     fn f<const N: usize, T: ToGodot>(
         (data, p, a): &mut (&[u8], usize, VariantArray),
         n: usize,
@@ -438,8 +506,8 @@ pub fn read_struct(data: &[u8], p: usize, format: &[char]) -> AnyResult<VariantA
     ) -> AnyResult<()> {
         for _ in 0..n {
             let s = *p;
-            let e = s + N;
-            let Some(data) = data.get(s..e) else {
+            let e = s.wrapping_add(N);
+            let Some(data) = s.checked_add(N).and_then(|e| data.get(s..e)) else {
                 bail_with_site!("Index out of range ({s}..{e})")
             };
             a.push(f(data.try_into().unwrap()).to_variant());
@@ -452,10 +520,14 @@ pub fn read_struct(data: &[u8], p: usize, format: &[char]) -> AnyResult<VariantA
     let mut format = CharSlice(format);
     let mut r = (data, p, Array::new());
     let mut p_ = pair(opt(u32_), parse_datatype);
+    let mut total = 0;
     while !format.0.is_empty() {
         let (i, (n, t)) = p_(format).map_err(|e| e.map(SingleError::into_owned))?;
         format = i;
         let n = n.unwrap_or(1) as usize;
+        if !matches!(t, DataType::Padding) {
+            add_count(&mut total, n, max)?;
+        }
 
         match t {
             DataType::Padding => {
@@ -582,6 +654,7 @@ pub fn write_struct(
     p: usize,
     format: &[char],
     arr: VariantArray,
+    max: usize,
 ) -> AnyResult<usize> {
     fn f<const N: usize, T: FromGodot>(
         (data, p, a): &mut (&mut [u8], usize, impl Iterator<Item = Variant>),
@@ -597,8 +670,8 @@ pub fn write_struct(
                 bail_with_site!("Input array too small")
             };
             let s = *p;
-            let e = s + N;
-            let Some(data) = data.get_mut(s..e) else {
+            let e = s.wrapping_add(N);
+            let Some(data) = s.checked_add(N).and_then(|e| data.get_mut(s..e)) else {
                 bail_with_site!("Index out of range ({s}..{e})")
             };
             f(&v, data.try_into().unwrap());
@@ -611,10 +684,14 @@ pub fn write_struct(
     let mut format = CharSlice(format);
     let mut r = (data, p, arr.iter_shared());
     let mut p_ = pair(opt(u32_), parse_datatype);
+    let mut total = 0;
     while !format.0.is_empty() {
         let (i, (n, t)) = p_(format).map_err(|e| e.map(SingleError::into_owned))?;
         format = i;
         let n = n.unwrap_or(1) as usize;
+        if !matches!(t, DataType::Padding) {
+            add_count(&mut total, n, max)?;
+        }
 
         match t {
             DataType::Padding => {
@@ -738,3 +815,117 @@ pub fn write_struct(
 
     Ok(r.1 - p)
 }
+
+const TAG_NIL: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_INT: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+const TAG_VECTOR2: u8 = 4;
+const TAG_VECTOR3: u8 = 5;
+const TAG_COLOR: u8 = 6;
+
+/// Encodes scalar value into tagged format.
+pub fn encode_tagged(v: &Variant) -> AnyResult<Vec<u8>> {
+    fn f<const N: usize, T>(tag: u8, v: T, f: impl Fn(&T, &mut [u8; N])) -> Vec<u8> {
+        let mut a = [0u8; N];
+        f(&v, &mut a);
+        let mut ret = Vec::with_capacity(N + 1);
+        ret.push(tag);
+        ret.extend_from_slice(&a);
+        ret
+    }
+
+    Ok(variant_dispatch!(v {
+        NIL => vec![TAG_NIL],
+        BOOL => vec![TAG_BOOL, v as u8],
+        INT => f(TAG_INT, v, |v, a| *a = v.to_le_bytes()),
+        FLOAT => f(TAG_FLOAT, v, |v, a| *a = v.to_le_bytes()),
+        VECTOR2 => f(TAG_VECTOR2, v, <_ as StructPacking<f32>>::write_array),
+        VECTOR3 => f(TAG_VECTOR3, v, <_ as StructPacking<f32>>::write_array),
+        COLOR => f(TAG_COLOR, v, <_ as StructPacking<f32>>::write_array),
+        _ => bail_with_site!("Unsupported value type {:?}", v.get_type()),
+    }))
+}
+
+/// Decodes tagged value at position `p`.
+pub fn decode_tagged(data: &[u8], p: usize) -> AnyResult<Variant> {
+    fn f<const N: usize, T: ToGodot>(
+        data: &[u8],
+        p: usize,
+        f: impl Fn(&[u8; N]) -> T,
+    ) -> AnyResult<Variant> {
+        let s = p.wrapping_add(1);
+        let Some(data) = s.checked_add(N).and_then(|e| data.get(s..e)) else {
+            bail_with_site!("Index out of range ({s}..{})", s.wrapping_add(N))
+        };
+        Ok(f(data.try_into().unwrap()).to_variant())
+    }
+
+    let Some(&tag) = data.get(p) else {
+        bail_with_site!("Index out of range ({p})")
+    };
+    match tag {
+        TAG_NIL => Ok(Variant::nil()),
+        TAG_BOOL => f::<1, _>(data, p, |v| v[0] != 0),
+        TAG_INT => f::<8, _>(data, p, |v| i64::from_le_bytes(*v)),
+        TAG_FLOAT => f::<8, _>(data, p, |v| f64::from_le_bytes(*v)),
+        TAG_VECTOR2 => f(data, p, <Vector2 as StructPacking<f32>>::read_array),
+        TAG_VECTOR3 => f(data, p, <Vector3 as StructPacking<f32>>::read_array),
+        TAG_COLOR => f(data, p, <Color as StructPacking<f32>>::read_array),
+        _ => bail_with_site!("Unknown tag {tag}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use super::*;
+
+    fn size(s: &str) -> AnyResult<usize> {
+        struct_size(&s.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_struct_size() {
+        for (s, n) in [
+            ("", 0),
+            ("bBx", 3),
+            ("hHiIlLfd", 2 + 2 + 4 + 4 + 8 + 8 + 4 + 8),
+            ("4i2x", 18),
+            ("3v2f", 24),
+            ("v3dv4l", 24 + 32),
+            ("CbCfCd", 4 + 16 + 32),
+            ("2rd", 64),
+        ] {
+            assert_eq!(size(s).unwrap(), n, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn test_struct_size_packing() {
+        for (s, n) in [
+            ("v2f", size_of::<<Vector2 as StructPacking<f32>>::Arr>()),
+            ("v3l", size_of::<<Vector3i as StructPacking<i64>>::Arr>()),
+            ("v4d", size_of::<<Vector4 as StructPacking<f64>>::Arr>()),
+            ("pf", size_of::<<Plane as StructPacking<f32>>::Arr>()),
+            ("qd", size_of::<<Quaternion as StructPacking<f64>>::Arr>()),
+            ("Cb", size_of::<<Color as StructPacking<u8>>::Arr>()),
+            ("ri", size_of::<<Rect2i as StructPacking<i32>>::Arr>()),
+            ("af", size_of::<<Aabb as StructPacking<f32>>::Arr>()),
+            ("md", size_of::<<Basis as StructPacking<f64>>::Arr>()),
+            ("Mf", size_of::<<Projection as StructPacking<f32>>::Arr>()),
+            ("td", size_of::<<Transform2D as StructPacking<f64>>::Arr>()),
+            ("Tf", size_of::<<Transform3D as StructPacking<f32>>::Arr>()),
+        ] {
+            assert_eq!(size(s).unwrap(), n, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn test_struct_size_error() {
+        for s in ["z", "v5f", "vf", "Ci", "4"] {
+            assert!(size(s).is_err(), "{s:?}");
+        }
+    }
+}
@@ -136,7 +136,7 @@ impl WasiContext {
             if o.bypass_stdio {
                 ctx.inherit_stdout();
             } else {
-                ctx.stdout(match config.wasi_stdout_buffer {
+                ctx.stdout(match config.stdout_buffer() {
                     PipeBufferType::Unbuffered => Box::new(UnbufferedWritePipe::new(move |buf| {
                         <Gd<RefCounted>>::from_instance_id(inst_id).emit_signal(
                             "stdout_emit".into(),
@@ -154,7 +154,7 @@ impl WasiContext {
                             "stdout_emit".into(),
                             &[GString::from(String::from_utf8_lossy(buf)).to_variant()],
                         );
-                    })) as _,
+                    }, config.wasi_block_size)) as _,
                 });
             }
         }
@@ -162,7 +162,7 @@ impl WasiContext {
             if o.bypass_stdio {
                 ctx.inherit_stderr();
             } else {
-                ctx.stderr(match config.wasi_stderr_buffer {
+                ctx.stderr(match config.stderr_buffer() {
                     PipeBufferType::Unbuffered => Box::new(UnbufferedWritePipe::new(move |buf| {
                         <Gd<RefCounted>>::from_instance_id(inst_id).emit_signal(
                             "stderr_emit".into(),
@@ -180,7 +180,7 @@ impl WasiContext {
                             "stderr_emit".into(),
                             &[GString::from(String::from_utf8_lossy(buf)).to_variant()],
                         );
-                    })) as _,
+                    }, config.wasi_block_size)) as _,
                 });
             }
         }
@@ -249,21 +249,24 @@ impl WasiContext {
     pub fn build_ctx(this: Gd<Self>, ctx: &mut WasiCtxBuilder, config: &Config) -> AnyResult<()> {
         let o = this.bind();
 
-        if config.wasi_stdout == PipeBindingType::Context {
+        if config.wasi_stdout == PipeBindingType::Context && !config.wasi_stdout_to_console {
             if o.bypass_stdio {
                 ctx.inherit_stdout();
             } else {
                 let base = (*o.base()).clone();
-                match config.wasi_stdout_buffer {
+                match config.stdout_buffer() {
                     PipeBufferType::Unbuffered => ctx.stdout(UnbufferedWritePipe::new(
                         Self::emit_binary(base, "stdout_emit"),
                     )),
                     PipeBufferType::LineBuffer => ctx.stdout(StreamWrapper::from(
                         LineWritePipe::new(Self::emit_string(base, "stdout_emit")),
                     )),
-                    PipeBufferType::BlockBuffer => ctx.stdout(StreamWrapper::from(
-                        BlockWritePipe::new(Self::emit_binary(base, "stdout_emit")),
-                    )),
+                    PipeBufferType::BlockBuffer => {
+                        ctx.stdout(StreamWrapper::from(BlockWritePipe::new(
+                            Self::emit_binary(base, "stdout_emit"),
+                            config.wasi_block_size,
+                        )))
+                    }
                 };
             }
         }
@@ -272,16 +275,19 @@ impl WasiContext {
                 ctx.inherit_stderr();
             } else {
                 let base = (*o.base()).clone();
-                match config.wasi_stderr_buffer {
+                match config.stderr_buffer() {
                     PipeBufferType::Unbuffered => ctx.stderr(UnbufferedWritePipe::new(
                         Self::emit_binary(base, "stderr_emit"),
                     )),
                     PipeBufferType::LineBuffer => ctx.stderr(StreamWrapper::from(
                         LineWritePipe::new(Self::emit_string(base, "stderr_emit")),
                     )),
-                    PipeBufferType::BlockBuffer => ctx.stderr(StreamWrapper::from(
-                        BlockWritePipe::new(Self::emit_binary(base, "stderr_emit")),
-                    )),
+                    PipeBufferType::BlockBuffer => {
+                        ctx.stderr(StreamWrapper::from(BlockWritePipe::new(
+                            Self::emit_binary(base, "stderr_emit"),
+                            config.wasi_block_size,
+                        )))
+                    }
                 };
             }
         }
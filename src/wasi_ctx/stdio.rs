@@ -1,7 +1,7 @@
 use std::collections::VecDeque;
 use std::fmt::{Display, Result as FmtResult, Write as _};
 use std::future::poll_fn;
-use std::io::{Result as IoResult, Write};
+use std::io::{ErrorKind, Read, Result as IoResult, Write};
 use std::ops::Deref;
 use std::ptr;
 use std::slice;
@@ -9,6 +9,7 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::task::{Poll, Waker};
 
+use anyhow::Result as AnyResult;
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
 use godot::prelude::*;
@@ -19,6 +20,8 @@ use wasmtime_wasi::{
     Subscribe,
 };
 
+use crate::bail_with_site;
+
 const BUFFER_LEN: usize = 8192;
 const NL_BYTE: u8 = 10;
 
@@ -41,6 +44,19 @@ impl<T> Clone for StreamWrapper<T> {
     }
 }
 
+impl<T: BufferedPipe + 'static> StreamWrapper<T> {
+    /// Gets handle to flush the pipe buffer.
+    pub fn as_buffered(&self) -> Arc<dyn BufferedPipe> {
+        self.inner.clone()
+    }
+}
+
+/// Pipe with internal buffer.
+pub trait BufferedPipe: Send + Sync {
+    /// Emits buffered data, if any.
+    fn flush_buffer(&self);
+}
+
 impl<T> Deref for StreamWrapper<T> {
     type Target = T;
 
@@ -275,6 +291,23 @@ where
         buffer.reserve_exact(BUFFER_LEN);
         Self { buffer, f }
     }
+
+    fn flush_buffer(&mut self) {
+        if !self.buffer.is_empty() {
+            let f = &self.f;
+            f(self.buffer.as_slice());
+            self.buffer.clear();
+        }
+    }
+}
+
+impl<F> BufferedPipe for LineWritePipe<F>
+where
+    for<'a> F: Fn(&'a [u8]) + Send + Sync,
+{
+    fn flush_buffer(&self) {
+        self.0.lock().flush_buffer()
+    }
 }
 
 impl<F> Drop for InnerLineWriter<F>
@@ -282,10 +315,7 @@ where
     for<'a> F: Fn(&'a [u8]),
 {
     fn drop(&mut self) {
-        if !self.buffer.is_empty() {
-            let f = &self.f;
-            f(self.buffer.as_slice())
-        }
+        self.flush_buffer()
     }
 }
 
@@ -395,8 +425,12 @@ impl<F> BlockWritePipe<F>
 where
     for<'a> F: Fn(&'a [u8]),
 {
-    pub fn new(f: F) -> Self {
-        Self(Mutex::new(InnerBlockWriter::new(f)))
+    /// Creates new pipe with block size (defaults to 8 KiB).
+    pub fn new(f: F, size: Option<usize>) -> Self {
+        Self(Mutex::new(InnerBlockWriter::new(
+            f,
+            size.unwrap_or(BUFFER_LEN).max(1),
+        )))
     }
 }
 
@@ -502,11 +536,28 @@ impl<F> InnerBlockWriter<F>
 where
     for<'a> F: Fn(&'a [u8]),
 {
-    fn new(f: F) -> Self {
+    fn new(f: F, size: usize) -> Self {
         let mut buffer = Vec::new();
-        buffer.reserve_exact(BUFFER_LEN);
+        buffer.reserve_exact(size);
         Self { buffer, f }
     }
+
+    fn flush_buffer(&mut self) {
+        if !self.buffer.is_empty() {
+            let f = &self.f;
+            f(self.buffer.as_slice());
+            self.buffer.clear();
+        }
+    }
+}
+
+impl<F> BufferedPipe for BlockWritePipe<F>
+where
+    for<'a> F: Fn(&'a [u8]) + Send + Sync,
+{
+    fn flush_buffer(&self) {
+        self.0.lock().flush_buffer()
+    }
 }
 
 impl<F> Drop for InnerBlockWriter<F>
@@ -514,10 +565,7 @@ where
     for<'a> F: Fn(&'a [u8]),
 {
     fn drop(&mut self) {
-        if !self.buffer.is_empty() {
-            let f = &self.f;
-            f(self.buffer.as_slice())
-        }
+        self.flush_buffer()
     }
 }
 
@@ -598,13 +646,43 @@ pub struct InnerStdin<F: ?Sized> {
 }
 
 struct InnerInnerStdin {
-    buf: VecDeque<String>,
+    buf: VecDeque<Vec<u8>>,
     is_eof: bool,
     ix: usize,
+    /// Rest of input, read on demand.
+    reader: Option<Box<dyn Read + Send>>,
 
     waker: Option<Waker>,
 }
 
+impl InnerInnerStdin {
+    /// Reads a chunk from reader if buffer is empty. Closes the pipe at end of reader.
+    fn fill_from_reader(&mut self) {
+        if !self.buf.is_empty() {
+            return;
+        }
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+
+        let mut v = vec![0; BUFFER_LEN];
+        loop {
+            match reader.read(&mut v) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Ok(n @ 1..) => {
+                    v.truncate(n);
+                    self.buf.push_back(v);
+                }
+                Ok(_) | Err(_) => {
+                    self.reader = None;
+                    self.is_eof = true;
+                }
+            }
+            break;
+        }
+    }
+}
+
 impl<F> Drop for OuterStdin<F> {
     fn drop(&mut self) {
         self.0.is_dropped.store(true, Ordering::Release);
@@ -621,6 +699,7 @@ impl<F: Fn()> OuterStdin<F> {
                 buf: VecDeque::new(),
                 is_eof: false,
                 ix: 0,
+                reader: None,
                 waker: None,
             }),
         });
@@ -630,6 +709,7 @@ impl<F: Fn()> OuterStdin<F> {
     fn ensure_nonempty(&self) -> MutexGuard<'_, InnerInnerStdin> {
         let mut guard = self.0.inner.lock();
         loop {
+            guard.fill_from_reader();
             match &*guard {
                 InnerInnerStdin { is_eof: true, .. } => break,
                 InnerInnerStdin { buf, .. } if !buf.is_empty() => break,
@@ -645,22 +725,50 @@ impl<F: Fn()> OuterStdin<F> {
 
 impl<F: ?Sized> InnerStdin<F> {
     pub fn add_line<T: Display>(&self, line: T) -> FmtResult {
-        if self.is_dropped.load(Ordering::Acquire) {
-            return Ok(());
+        let mut ret = String::new();
+        write!(&mut ret, "{line}")?;
+        if !ret.ends_with('\n') {
+            ret.push('\n');
+        }
+        self.add_data(ret);
+        Ok(())
+    }
+
+    /// Appends data as-is, without adding newline.
+    pub fn add_data(&self, data: String) {
+        if self.is_dropped.load(Ordering::Acquire) || data.is_empty() {
+            return;
         }
 
         let mut guard = self.inner.lock();
-        if guard.is_eof {
-            return Ok(());
+        if guard.is_eof || guard.reader.is_some() {
+            return;
         }
 
-        let buf = &mut guard.buf;
-        let mut ret = String::new();
-        write!(&mut ret, "{line}")?;
-        if !ret.ends_with('\n') {
-            ret.push('\n');
+        guard.buf.push_back(data.into_bytes());
+
+        if let Some(w) = guard.waker.take() {
+            w.wake();
+        }
+        self.cond.notify_one();
+    }
+
+    /// Reads the rest of input from reader as-is, then closes the pipe.
+    ///
+    /// Reader is closed at its first end of file, so data appended afterwards is not read.
+    /// Data added afterwards is ignored.
+    pub fn set_reader(&self, reader: Box<dyn Read + Send>) -> AnyResult<()> {
+        if self.is_dropped.load(Ordering::Acquire) {
+            bail_with_site!("Stdin is dropped");
+        }
+
+        let mut guard = self.inner.lock();
+        if guard.is_eof {
+            bail_with_site!("Stdin is closed");
+        } else if guard.reader.is_some() {
+            bail_with_site!("Stdin is already reading from another source");
         }
-        buf.push_back(ret);
+        guard.reader = Some(reader);
 
         if let Some(w) = guard.waker.take() {
             w.wake();
@@ -769,6 +877,9 @@ impl<F: Fn() + Send + Sync + 'static> Subscribe for OuterStdin<F> {
             if match &*guard {
                 InnerInnerStdin { is_eof: true, .. } => true,
                 InnerInnerStdin { buf, .. } if !buf.is_empty() => true,
+                InnerInnerStdin {
+                    reader: Some(_), ..
+                } => true,
                 _ => false,
             } {
                 return Poll::Ready(());
@@ -800,13 +911,13 @@ impl<F: Fn() + Send + Sync + 'static> HostInputStream for OuterStdin<F> {
                 buf.pop_front();
                 *ix = 0;
             } else if v.len() - *ix <= size {
-                let s = &v.as_bytes()[*ix..];
+                let s = &v[*ix..];
                 ret.extend_from_slice(s);
                 size -= s.len();
                 buf.pop_front();
                 *ix = 0;
             } else {
-                ret.extend_from_slice(&v.as_bytes()[*ix..*ix + size]);
+                ret.extend_from_slice(&v[*ix..*ix + size]);
                 *ix += size;
                 size = 0;
             }
@@ -832,7 +943,7 @@ impl<F: Fn() + Send + Sync + 'static> HostInputStream for OuterStdin<F> {
                 buf.pop_front();
                 *ix = 0;
             } else if v.len() - *ix <= size {
-                let s = &v.as_bytes()[*ix..];
+                let s = &v[*ix..];
                 ret += s.len();
                 size -= s.len();
                 buf.pop_front();
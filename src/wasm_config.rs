@@ -1,4 +1,3 @@
-#[cfg(feature = "wasi")]
 use std::collections::HashMap;
 
 use godot::prelude::*;
@@ -7,6 +6,8 @@ use godot::prelude::*;
 use crate::variant_dispatch;
 #[cfg(feature = "wasi")]
 use crate::wasi_ctx::WasiContext;
+#[cfg(feature = "object-registry-compat")]
+use crate::wasm_objregistry::WasmObjectRegistry;
 #[cfg(feature = "epoch-timeout")]
 use crate::wasm_util::{EPOCH_DEADLINE, EPOCH_MULTIPLIER};
 
@@ -18,12 +19,59 @@ pub struct Config {
     pub epoch_autoreset: bool,
     #[cfg(feature = "epoch-timeout")]
     pub epoch_timeout: u64,
+    #[cfg(feature = "epoch-timeout")]
+    pub provide_epoch_import: bool,
+    #[cfg(feature = "epoch-timeout")]
+    pub epoch_interrupt: bool,
 
     #[cfg(feature = "memory-limiter")]
     pub max_memory: Option<u64>,
     #[cfg(feature = "memory-limiter")]
     pub max_entries: Option<u64>,
 
+    pub host_data: Option<PackedByteArray>,
+    pub host_data_symbol: String,
+    pub memory_exhausted_threshold: Option<usize>,
+    pub memory_exhausted_window: Option<f64>,
+
+    pub provide_memory_import: bool,
+    pub memory_import_min: Option<u64>,
+    pub memory_import_max: Option<u64>,
+
+    pub max_results: Option<usize>,
+    pub strict_arity: bool,
+    pub max_host_reentry: Option<u32>,
+    pub max_call_depth: Option<u32>,
+    #[cfg(feature = "fuel")]
+    pub fuel_budget: Option<u64>,
+    #[cfg(feature = "fuel")]
+    pub export_fuel_multipliers: HashMap<String, f64>,
+    pub max_struct_elements: Option<usize>,
+    pub single_threaded_memory_ops: bool,
+    pub alloc_func: String,
+    pub free_func: Option<String>,
+    pub defer_host_calls: bool,
+    pub provide_time_import: bool,
+    pub provide_yield_import: bool,
+    pub provide_rand_import: bool,
+    pub provide_event_import: bool,
+    pub max_events: Option<usize>,
+    pub event_overflow_error: bool,
+    pub wasi_random_seed: Option<u64>,
+    #[cfg(feature = "object-registry-compat")]
+    pub command_buffer: bool,
+    pub cache_exports: bool,
+    pub profile: bool,
+    pub trace_calls: bool,
+    pub collect_stats: bool,
+    pub max_instances: Option<usize>,
+    pub import_aliases: HashMap<String, String>,
+    pub constants: HashMap<String, i64>,
+    pub fallback_import_resolver: Option<Callable>,
+
+    #[cfg(feature = "godot-component")]
+    pub component_config: Option<Dictionary>,
+
     #[cfg(feature = "wasi")]
     pub with_wasi: bool,
     #[cfg(feature = "wasi")]
@@ -41,9 +89,19 @@ pub struct Config {
     #[cfg(feature = "wasi")]
     pub wasi_stderr: PipeBindingType,
     #[cfg(feature = "wasi")]
-    pub wasi_stdout_buffer: PipeBufferType,
+    pub wasi_stdout_to_console: bool,
+    #[cfg(feature = "wasi")]
+    pub capture_panic_message: bool,
+    #[cfg(feature = "wasi")]
+    pub wasi_stdout_buffer: Option<PipeBufferType>,
+    #[cfg(feature = "wasi")]
+    pub wasi_stderr_buffer: Option<PipeBufferType>,
     #[cfg(feature = "wasi")]
-    pub wasi_stderr_buffer: PipeBufferType,
+    pub wasi_default_buffer: PipeBufferType,
+    #[cfg(feature = "wasi")]
+    pub wasi_preference: Option<WasiVersion>,
+    #[cfg(feature = "wasi")]
+    pub wasi_block_size: Option<usize>,
     #[cfg(feature = "wasi")]
     pub wasi_stdin_data: Option<PackedByteArray>,
     //#[cfg(feature = "wasi")]
@@ -52,6 +110,20 @@ pub struct Config {
     // Not worth cfg() it
     #[allow(dead_code)]
     pub extern_bind: ExternBindingType,
+    #[cfg(feature = "object-registry-extern")]
+    pub extern_identity: bool,
+    #[cfg(feature = "object-registry-extern")]
+    pub gc_after_call: bool,
+    #[cfg(feature = "object-registry-extern")]
+    pub extern_allow: Option<Vec<String>>,
+    #[cfg(feature = "object-registry-extern")]
+    pub max_externrefs: Option<usize>,
+    #[cfg(feature = "object-registry-compat")]
+    pub shared_registry: Option<Gd<WasmObjectRegistry>>,
+    #[cfg(feature = "object-registry-compat")]
+    pub auto_unwrap_registry: bool,
+    #[cfg(feature = "object-registry-compat")]
+    pub max_registry_entries: Option<usize>,
 }
 
 fn get_field<T: FromGodot>(
@@ -108,6 +180,18 @@ fn get_wasi_envs(v: Option<Variant>) -> Result<HashMap<String, String>, ConvertE
     Ok(ret)
 }
 
+fn get_constants(v: Option<Variant>) -> Result<HashMap<String, i64>, ConvertError> {
+    let v = match v {
+        Some(v) => v.try_to::<Dictionary>()?,
+        None => return Ok(HashMap::new()),
+    };
+    let mut ret = HashMap::with_capacity(v.len());
+    for (k, v) in v.iter_shared() {
+        ret.insert(k.try_to::<String>()?, v.try_to::<i64>()?);
+    }
+    Ok(ret)
+}
+
 impl Config {
     fn convert(dict: Dictionary) -> Result<Self, ConvertError> {
         Ok(Self {
@@ -121,6 +205,10 @@ impl Config {
                 dict.get("epoch.timeout")
                     .or_else(|| dict.get("engine.epoch_timeout")),
             )?,
+            #[cfg(feature = "epoch-timeout")]
+            provide_epoch_import: get_field(&dict, ["epoch.remainingImport"])?.unwrap_or_default(),
+            #[cfg(feature = "epoch-timeout")]
+            epoch_interrupt: get_field(&dict, ["epoch.allowInterrupt"])?.unwrap_or_default(),
 
             #[cfg(feature = "memory-limiter")]
             max_memory: get_field::<i64>(&dict, ["memory.maxGrowBytes", "engine.max_memory"])?
@@ -129,6 +217,62 @@ impl Config {
             max_entries: get_field::<i64>(&dict, ["table.maxGrowEntries", "engine.max_entries"])?
                 .map(|v| v as _),
 
+            host_data: get_field(&dict, ["memory.hostData"])?,
+            host_data_symbol: get_field(&dict, ["memory.hostDataSymbol"])?
+                .unwrap_or_else(|| "__host_data".to_string()),
+            memory_exhausted_threshold: get_field::<i64>(&dict, ["memory.exhaustedThreshold"])?
+                .map(|v| v.max(1) as _),
+            memory_exhausted_window: get_field::<f64>(&dict, ["memory.exhaustedWindow"])?
+                .filter(|v| *v > 0.0),
+
+            provide_memory_import: get_field(&dict, ["memory.provideImport"])?.unwrap_or_default(),
+            memory_import_min: get_field::<i64>(&dict, ["memory.importMinPages"])?
+                .map(|v| v.max(0) as _),
+            memory_import_max: get_field::<i64>(&dict, ["memory.importMaxPages"])?
+                .map(|v| v.max(0) as _),
+
+            max_results: get_field::<i64>(&dict, ["call.maxResults"])?.map(|v| v.max(0) as _),
+            strict_arity: get_field(&dict, ["call.strictArity"])?.unwrap_or_default(),
+            max_host_reentry: get_field::<i64>(&dict, ["host.maxReentry"])?
+                .map(|v| v.clamp(0, u32::MAX as _) as _),
+            max_call_depth: get_field::<i64>(&dict, ["call.maxDepth"])?
+                .map(|v| v.clamp(0, u32::MAX as _) as _),
+            #[cfg(feature = "fuel")]
+            fuel_budget: get_field::<i64>(&dict, ["fuel.budget"])?.map(|v| v.max(0) as _),
+            #[cfg(feature = "fuel")]
+            export_fuel_multipliers: get_fuel_multipliers(dict.get("fuel.exportMultipliers"))?,
+            max_struct_elements: get_field::<i64>(&dict, ["memory.maxStructElements"])?
+                .map(|v| v.max(0) as _),
+            single_threaded_memory_ops: get_field(&dict, ["memory.singleThreaded"])?
+                .unwrap_or_default(),
+            alloc_func: get_field(&dict, ["call.allocFunc"])?
+                .unwrap_or_else(|| "malloc".to_string()),
+            free_func: get_field(&dict, ["call.freeFunc"])?,
+            defer_host_calls: get_field(&dict, ["host.deferCalls"])?.unwrap_or_default(),
+            provide_time_import: get_field(&dict, ["host.timeImport"])?.unwrap_or_default(),
+            provide_yield_import: get_field(&dict, ["host.yieldImport"])?.unwrap_or_default(),
+            provide_rand_import: get_field(&dict, ["host.randStreamImport"])?.unwrap_or_default(),
+            provide_event_import: get_field(&dict, ["host.eventImport"])?.unwrap_or_default(),
+            max_events: get_field::<i64>(&dict, ["host.eventQueueSize"])?.map(|v| v.max(0) as _),
+            event_overflow_error: get_field(&dict, ["host.eventOverflowError"])?
+                .unwrap_or_default(),
+            wasi_random_seed: get_field::<i64>(&dict, ["wasi.randomSeed"])?.map(|v| v as _),
+            #[cfg(feature = "object-registry-compat")]
+            command_buffer: get_field(&dict, ["host.commandBuffer"])?.unwrap_or_default(),
+            cache_exports: get_field(&dict, ["instance.cacheExports"])?.unwrap_or_default(),
+            profile: get_field(&dict, ["call.profile"])?.unwrap_or_default(),
+            trace_calls: get_field(&dict, ["call.trace"])?.unwrap_or_default(),
+            collect_stats: get_field(&dict, ["memory.collectStats"])?.unwrap_or_default(),
+            max_instances: get_field::<i64>(&dict, ["instance.maxPerModule"])?
+                .map(|v| v.max(0) as _),
+            import_aliases: get_import_aliases(dict.get("import.aliases"))?,
+            constants: get_constants(dict.get("import.constants"))?,
+            fallback_import_resolver: get_field::<Callable>(&dict, ["import.fallbackResolver"])?
+                .filter(|v| v.is_valid()),
+
+            #[cfg(feature = "godot-component")]
+            component_config: get_field(&dict, ["component.config"])?,
+
             #[cfg(feature = "wasi")]
             with_wasi: get_field(&dict, ["wasi.enable", "engine.use_wasi"])?.unwrap_or_default(),
             #[cfg(feature = "wasi")]
@@ -150,19 +294,140 @@ impl Config {
             wasi_stderr: get_field(&dict, ["wasi.stderr.bindMode", "wasi.stderr"])?
                 .unwrap_or_default(),
             #[cfg(feature = "wasi")]
-            wasi_stdout_buffer: get_field(&dict, ["wasi.stdout.bufferMode", "wasi.stdout_buffer"])?
+            wasi_stdout_to_console: get_field(&dict, ["wasi.stdout.toConsole"])?
                 .unwrap_or_default(),
             #[cfg(feature = "wasi")]
-            wasi_stderr_buffer: get_field(&dict, ["wasi.stderr.bufferMode", "wasi.stderr_buffer"])?
+            capture_panic_message: get_field(&dict, ["error.capturePanicMessage"])?
                 .unwrap_or_default(),
             #[cfg(feature = "wasi")]
+            wasi_stdout_buffer: get_field(&dict, ["wasi.stdout.bufferMode", "wasi.stdout_buffer"])?,
+            #[cfg(feature = "wasi")]
+            wasi_stderr_buffer: get_field(&dict, ["wasi.stderr.bufferMode", "wasi.stderr_buffer"])?,
+            #[cfg(feature = "wasi")]
+            wasi_default_buffer: get_field(&dict, ["wasi.bufferMode"])?.unwrap_or_default(),
+            #[cfg(feature = "wasi")]
+            wasi_preference: get_field(&dict, ["wasi.preference"])?,
+            #[cfg(feature = "wasi")]
+            wasi_block_size: get_field::<i64>(&dict, ["wasi.blockSize"])?.map(|v| v.max(1) as _),
+            #[cfg(feature = "wasi")]
             wasi_stdin_data: get_field(&dict, ["wasi.stdin.inputData", "wasi.stdin_data"])?,
             //#[cfg(feature = "wasi")]
             //wasi_stdin_file: get_field(&dict, ["wasi.stdin.inputFile", "wasi.stdin_file"])?,
             extern_bind: get_field(&dict, ["extern.bindMode", "godot.extern_binding"])?
                 .unwrap_or_default(),
+            #[cfg(feature = "object-registry-extern")]
+            extern_identity: get_field(&dict, ["extern.preserveIdentity"])?.unwrap_or_default(),
+            #[cfg(feature = "object-registry-extern")]
+            gc_after_call: get_field(&dict, ["extern.gcAfterCall"])?.unwrap_or_default(),
+            #[cfg(feature = "object-registry-extern")]
+            extern_allow: get_field::<PackedStringArray>(&dict, ["extern.allow"])?
+                .map(|v| v.as_slice().iter().map(|v| v.to_string()).collect()),
+            #[cfg(feature = "object-registry-extern")]
+            max_externrefs: get_field::<i64>(&dict, ["extern.maxExternrefs"])?
+                .map(|v| v.max(0) as _),
+            #[cfg(feature = "object-registry-compat")]
+            shared_registry: get_field(&dict, ["extern.sharedRegistry"])?,
+            #[cfg(feature = "object-registry-compat")]
+            auto_unwrap_registry: get_field(&dict, ["extern.autoUnwrapResults"])?
+                .unwrap_or_default(),
+            #[cfg(feature = "object-registry-compat")]
+            max_registry_entries: get_field::<i64>(&dict, ["extern.maxRegistryEntries"])?
+                .map(|v| v.max(0) as _),
+        })
+    }
+}
+
+fn get_import_aliases(v: Option<Variant>) -> Result<HashMap<String, String>, ConvertError> {
+    let v = match v {
+        Some(v) => v.try_to::<Dictionary>()?,
+        None => return Ok(HashMap::new()),
+    };
+    let mut ret = HashMap::with_capacity(v.len());
+    for (k, v) in v.iter_shared() {
+        ret.insert(k.try_to::<String>()?, v.try_to::<String>()?);
+    }
+    Ok(ret)
+}
+
+/// Multiplier must be non-negative. Zero makes the export free.
+#[cfg(feature = "fuel")]
+fn is_valid_fuel_multiplier(m: f64) -> bool {
+    !m.is_nan() && m >= 0.0
+}
+
+#[cfg(feature = "fuel")]
+fn get_fuel_multipliers(v: Option<Variant>) -> Result<HashMap<String, f64>, ConvertError> {
+    let v = match v {
+        Some(v) => v.try_to::<Dictionary>()?,
+        None => return Ok(HashMap::new()),
+    };
+    let mut ret = HashMap::with_capacity(v.len());
+    for (k, v) in v.iter_shared() {
+        let m = v.try_to::<f64>()?;
+        if !is_valid_fuel_multiplier(m) {
+            return Err(ConvertError::with_error_value("Invalid fuel multiplier", v));
+        }
+        ret.insert(k.try_to::<String>()?, m);
+    }
+    Ok(ret)
+}
+
+impl Config {
+    /// Resolves import module and name using import aliases.
+    pub fn resolve_import<'a>(&'a self, module: &'a str, name: &'a str) -> (&'a str, &'a str) {
+        if self.import_aliases.is_empty() {
+            return (module, name);
+        }
+
+        if let Some(v) = self.import_aliases.get(&format!("{module}.{name}")) {
+            return v.split_once('.').unwrap_or((v, name));
+        }
+        match self.import_aliases.get(module) {
+            Some(v) => (v, name),
+            None => (module, name),
+        }
+    }
+
+    /// Checks if externref function is allowed by `extern.allow`.
+    #[cfg(feature = "object-registry-extern")]
+    pub fn extern_allowed(&self, name: &str) -> bool {
+        let Some(allow) = &self.extern_allow else {
+            return true;
+        };
+        allow.iter().any(|p| match p.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => p == name,
         })
     }
+
+    /// Checks `wasi.preference` against WASI version provided by the caller.
+    ///
+    /// Returns error message if it does not match.
+    #[cfg(feature = "wasi")]
+    pub fn wasi_version_mismatch(&self, version: WasiVersion) -> Option<&'static str> {
+        match self.wasi_preference? {
+            v if v == version => None,
+            WasiVersion::Preview2 if cfg!(not(feature = "wasi-preview2")) => {
+                Some("WASI preview2 requested, but wasi-preview2 feature is not enabled")
+            }
+            WasiVersion::Preview1 => {
+                Some("WASI preview1 requested; use WasmInstance with wasi.enable config instead")
+            }
+            WasiVersion::Preview2 => Some("WASI preview2 requested; use WasiCommand instead"),
+        }
+    }
+
+    /// Buffer type of standard output, falling back to default buffer type.
+    #[cfg(feature = "wasi")]
+    pub fn stdout_buffer(&self) -> PipeBufferType {
+        self.wasi_stdout_buffer.unwrap_or(self.wasi_default_buffer)
+    }
+
+    /// Buffer type of standard error, falling back to default buffer type.
+    #[cfg(feature = "wasi")]
+    pub fn stderr_buffer(&self) -> PipeBufferType {
+        self.wasi_stderr_buffer.unwrap_or(self.wasi_default_buffer)
+    }
 }
 
 impl GodotConvert for Config {
@@ -285,6 +550,42 @@ impl ToGodot for PipeBindingType {
     }
 }
 
+#[cfg(feature = "wasi")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WasiVersion {
+    Preview1,
+    Preview2,
+}
+
+#[cfg(feature = "wasi")]
+impl GodotConvert for WasiVersion {
+    type Via = GString;
+}
+
+#[cfg(feature = "wasi")]
+impl FromGodot for WasiVersion {
+    fn try_from_godot(via: Self::Via) -> Result<Self, ConvertError> {
+        let chars = via.chars();
+
+        match chars {
+            ['p', 'r', 'e', 'v', 'i', 'e', 'w', '1'] | ['p', '1'] => Ok(Self::Preview1),
+            ['p', 'r', 'e', 'v', 'i', 'e', 'w', '2'] | ['p', '2'] => Ok(Self::Preview2),
+            _ => Err(ConvertError::with_error_value("Unknown variant", via)),
+        }
+    }
+}
+
+#[cfg(feature = "wasi")]
+impl ToGodot for WasiVersion {
+    fn to_godot(&self) -> Self::Via {
+        match self {
+            Self::Preview1 => "preview1",
+            Self::Preview2 => "preview2",
+        }
+        .into()
+    }
+}
+
 #[cfg(feature = "wasi")]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[non_exhaustive]
@@ -331,3 +632,18 @@ impl ToGodot for PipeBufferType {
         .into()
     }
 }
+
+#[cfg(all(test, feature = "fuel"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuel_multiplier() {
+        for m in [0.0, 0.5, 1.0, 10.0, f64::INFINITY] {
+            assert!(is_valid_fuel_multiplier(m), "{m}");
+        }
+        for m in [-0.5, -1.0, f64::NEG_INFINITY, f64::NAN] {
+            assert!(!is_valid_fuel_multiplier(m), "{m}");
+        }
+    }
+}
@@ -1,7 +1,14 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display, Error as FmtError, Formatter};
-use std::path::PathBuf;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "epoch-timeout")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 #[cfg(feature = "epoch-timeout")]
 use std::{thread, time};
 
@@ -10,16 +17,21 @@ use cfg_if::cfg_if;
 use godot::classes::FileAccess;
 use godot::prelude::*;
 use once_cell::sync::OnceCell;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 #[cfg(feature = "component-model")]
 use wasmtime::component::Component;
-use wasmtime::{Config, Engine, ExternType, Module, Precompiled, ResourcesRequired};
+use wasmtime::{
+    Config, Engine, ExternType, FuncType, Module, Precompiled, RefType, ResourcesRequired, ValType,
+};
 
-use crate::godot_util::{from_var_any, variant_to_option, PhantomProperty};
-use crate::wasm_instance::WasmInstance;
-use crate::wasm_util::from_signature;
+use crate::godot_util::{decode_base64, from_var_any, variant_to_option, PhantomProperty};
+use crate::wasm_instance::{ImportPlan, WasmInstance};
+use crate::wasm_metadata::ModuleMetadata;
+#[cfg(feature = "demangle")]
+use crate::wasm_util::demangle_name;
 #[cfg(feature = "epoch-timeout")]
 use crate::wasm_util::EPOCH_INTERVAL;
+use crate::wasm_util::{from_signature, new_host_linker, to_signature, MEMORY_EXPORT};
 use crate::{bail_with_site, site_context, variant_dispatch};
 
 cfg_if! {
@@ -52,6 +64,7 @@ pub fn init_engine() {
             .cranelift_nan_canonicalization(cfg!(feature = "deterministic-wasm"))
             .epoch_interruption(true)
             .debug_info(true)
+            .wasm_backtrace(true)
             .wasm_reference_types(true)
             .wasm_function_references(true)
             .wasm_gc(true)
@@ -64,6 +77,8 @@ pub fn init_engine() {
             .wasm_multi_memory(true)
             .wasm_memory64(true)
             .wasm_custom_page_sizes(true);
+        #[cfg(feature = "fuel")]
+        config.consume_fuel(true);
         #[cfg(feature = "wasm-threads")]
         config.wasm_threads(true);
         #[cfg(feature = "component-model")]
@@ -88,6 +103,7 @@ pub fn deinit_engine() {
                 // Make sure epoch will time out.
                 for _ in 0..100 {
                     engine.increment_epoch();
+                    EPOCH.fetch_add(1, Ordering::Relaxed);
                 }
                 drop(engine);
                 handle.join().unwrap();
@@ -98,6 +114,16 @@ pub fn deinit_engine() {
     }
 }
 
+/// Mirror of engine epoch counter, as engine does not expose it.
+#[cfg(feature = "epoch-timeout")]
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// Gets current engine epoch.
+#[cfg(feature = "epoch-timeout")]
+pub fn get_epoch() -> u64 {
+    EPOCH.load(Ordering::Relaxed)
+}
+
 #[cfg(feature = "epoch-timeout")]
 pub fn start_epoch() -> Result<(), EngineUninitError> {
     let mut guard = ENGINE.write();
@@ -118,6 +144,7 @@ pub fn start_epoch() -> Result<(), EngineUninitError> {
                 let t = time::Instant::now();
                 while timeout < t {
                     engine.increment_epoch();
+                    EPOCH.fetch_add(1, Ordering::Relaxed);
                     timeout += EPOCH_INTERVAL;
                 }
             }
@@ -183,11 +210,149 @@ pub struct WasmModule {
     #[allow(dead_code)]
     bytes_data: PhantomProperty<PackedByteArray>,
     _bytes_data: OnceCell<PackedByteArray>,
+    live_instances: Arc<AtomicUsize>,
+    /// Cached import plans for `instantiate_fast`, bucketed by [`plan_key`].
+    import_plans: Mutex<HashMap<u64, Vec<PlanEntry>>>,
+}
+
+/// Maximum number of cached import plans per module.
+///
+/// Cache is cleared when it's full.
+const MAX_IMPORT_PLANS: usize = 64;
+
+/// Cached import plan, along with the host and config it's created from.
+struct PlanEntry {
+    host: Option<Dictionary>,
+    config: Option<Variant>,
+    plan: ImportPlan,
+}
+
+impl PlanEntry {
+    fn new(host: Option<&Dictionary>, config: Option<&Variant>, plan: ImportPlan) -> Self {
+        // Deep copy, so later changes to host or config don't alias the entry.
+        Self {
+            host: host.map(|v| v.duplicate_deep()),
+            config: config.map(|v| match v.try_to::<Dictionary>() {
+                Ok(v) => v.duplicate_deep().to_variant(),
+                Err(_) => v.clone(),
+            }),
+            plan,
+        }
+    }
+
+    fn matches(&self, host: Option<&Dictionary>, config: Option<&Variant>) -> bool {
+        self.host.as_ref() == host && self.config.as_ref() == config
+    }
+}
+
+/// Keys import plan by content hash of config and host.
+///
+/// Host functions are cached in plan, so the whole host (including callables) is hashed.
+/// Hash may collide, so entries are compared with [`PlanEntry::matches`] too.
+fn plan_key(host: Option<&Dictionary>, config: Option<&Variant>) -> u64 {
+    let mut h = DefaultHasher::new();
+    config.map(|v| v.hash()).hash(&mut h);
+    host.map(|v| v.to_variant().hash()).hash(&mut h);
+    h.finish()
+}
+
+/// Counts live instances of a module. Decrements counter on drop.
+pub struct LiveInstanceGuard(Arc<AtomicUsize>);
+
+impl LiveInstanceGuard {
+    pub fn new(module: &WasmModule, max: Option<usize>) -> AnyResult<Self> {
+        let count = module.live_instances.clone();
+        let n = count.fetch_add(1, Ordering::AcqRel);
+        let ret = Self(count);
+        if let Some(max) = max {
+            if n >= max {
+                bail_with_site!("Too many live instances of module (maximum {max})");
+            }
+        }
+        Ok(ret)
+    }
+}
+
+impl Drop for LiveInstanceGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Imports and exports of module, keyed by `"import:module.name"` and `"export:name"`.
+fn abi_entries(module: &Module) -> HashMap<String, ExternType> {
+    module
+        .imports()
+        .map(|i| (format!("import:{}.{}", i.module(), i.name()), i.ty()))
+        .chain(
+            module
+                .exports()
+                .map(|e| (format!("export:{}", e.name()), e.ty())),
+        )
+        .collect()
+}
+
+fn extern_type_eq(a: &ExternType, b: &ExternType) -> bool {
+    match (a, b) {
+        (ExternType::Func(a), ExternType::Func(b)) => FuncType::eq(a, b),
+        (ExternType::Global(a), ExternType::Global(b)) => {
+            a.mutability() == b.mutability() && ValType::eq(a.content(), b.content())
+        }
+        (ExternType::Table(a), ExternType::Table(b)) => {
+            RefType::eq(a.element(), b.element())
+                && a.minimum() == b.minimum()
+                && a.maximum() == b.maximum()
+        }
+        (ExternType::Memory(a), ExternType::Memory(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Import/export differences between two modules, as sorted entry keys.
+pub struct AbiDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl AbiDiff {
+    pub fn new(a: &Module, b: &Module) -> Self {
+        let mut a = abi_entries(a).into_iter().collect::<Vec<_>>();
+        a.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let mut b = abi_entries(b);
+
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+        for (k, t) in a {
+            match b.remove(&k) {
+                None => removed.push(k),
+                Some(t2) if !extern_type_eq(&t, &t2) => changed.push(k),
+                Some(_) => (),
+            }
+        }
+        let mut added = b.into_keys().collect::<Vec<_>>();
+        added.sort_unstable();
+
+        Self {
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    /// Module `b` is compatible with `a` if it has no removed or changed entries,
+    /// and no added imports.
+    pub fn is_compatible(&self) -> bool {
+        self.removed.is_empty()
+            && self.changed.is_empty()
+            && !self.added.iter().any(|k| k.starts_with("import:"))
+    }
 }
 
 pub struct ModuleData {
-    name: GString,
+    pub name: GString,
     pub module: ModuleType,
+    pub metadata: Option<ModuleMetadata>,
     pub imports: HashMap<String, Gd<WasmModule>>,
 }
 
@@ -218,6 +383,17 @@ impl ModuleType {
     }
 }
 
+impl ModuleData {
+    pub fn get_metadata(&self) -> AnyResult<&ModuleMetadata> {
+        match &self.metadata {
+            Some(v) => Ok(v),
+            None => bail_with_site!(
+                "Module metadata is not available (module is deserialized or module-metadata feature is disabled?)"
+            ),
+        }
+    }
+}
+
 impl WasmModule {
     pub fn get_data(&self) -> AnyResult<&ModuleData> {
         if let Some(data) = self.data.get() {
@@ -249,25 +425,30 @@ impl WasmModule {
         }
     }
 
-    fn load_module(bytes: &[u8]) -> AnyResult<ModuleType> {
+    fn load_module(bytes: &[u8]) -> AnyResult<(ModuleType, Option<ModuleMetadata>)> {
+        #[cfg(any(feature = "component-model", feature = "module-metadata"))]
+        let bytes: &[u8] = &site_context!(wat::parse_bytes(bytes))?;
+        #[cfg(feature = "component-model")]
+        if wasmparser::Parser::is_component(bytes) {
+            return Ok((
+                ModuleType::Component(site_context!(Component::from_binary(
+                    &get_engine()?,
+                    bytes,
+                ))?),
+                None,
+            ));
+        }
+
+        // Without wat crate, text format is parsed by wasmtime.
+        let module = site_context!(Module::new(&get_engine()?, bytes))?;
         cfg_if! {
-            if #[cfg(feature = "component-model")] {
-                let bytes = site_context!(wat::parse_bytes(bytes))?;
-                if wasmparser::Parser::is_component(&bytes) {
-                    Ok(ModuleType::Component(site_context!(
-                        Component::from_binary(&get_engine()?, &bytes,)
-                    )?))
-                } else {
-                    Ok(ModuleType::Core(site_context!(Module::from_binary(
-                        &get_engine()?, &bytes
-                    ))?))
-                }
+            if #[cfg(feature = "module-metadata")] {
+                let metadata = Some(ModuleMetadata::parse(bytes)?);
             } else {
-                Ok(ModuleType::Core(site_context!(Module::new(
-                    &get_engine()?, bytes
-                ))?))
+                let metadata = None;
             }
         }
+        Ok((ModuleType::Core(module), metadata))
     }
 
     fn process_deps_map(
@@ -310,9 +491,9 @@ impl WasmModule {
         .map_or_else(GString::new, GString::from)
     }
 
-    fn _initialize(&self, data: Variant, imports: Option<Dictionary>) -> bool {
+    pub fn _initialize(&self, data: Variant, imports: Option<Dictionary>) -> bool {
         let r = self.data.get_or_try_init(move || -> AnyResult<_> {
-            let module = variant_dispatch!(data {
+            let (module, metadata) = variant_dispatch!(data {
                 PACKED_BYTE_ARRAY => Self::load_module(data.as_slice())?,
                 STRING => Self::load_module(data.to_string().as_bytes())?,
                 OBJECT => match data
@@ -320,7 +501,11 @@ impl WasmModule {
                     .map_err(|v| v.try_cast::<WasmModule>())
                 {
                     Ok(v) => Self::load_module(v.get_buffer(v.get_length() as _).as_slice())?,
-                    Err(Ok(v)) => v.bind().get_data()?.module.clone(),
+                    Err(Ok(v)) => {
+                        let v = v.bind();
+                        let data = v.get_data()?;
+                        (data.module.clone(), data.metadata.clone())
+                    }
                     Err(Err(v)) => bail_with_site!("Unknown module value {}", v),
                 },
                 _ => bail_with_site!("Unknown module value {}", data),
@@ -331,6 +516,7 @@ impl WasmModule {
             Ok(ModuleData {
                 name: Self::name_from_module(&module),
                 module,
+                metadata,
                 imports,
             })
         });
@@ -365,6 +551,7 @@ impl WasmModule {
             Ok(ModuleData {
                 name: Self::name_from_module(&module),
                 module,
+                metadata: None,
                 imports,
             })
         });
@@ -376,29 +563,72 @@ impl WasmModule {
         }
     }
 
+    fn deserialize_module_file(path: &Path) -> AnyResult<ModuleType> {
+        let engine = site_context!(get_engine())?;
+        // SAFETY: Assume the supplied file is safe to deserialize.
+        unsafe {
+            Ok(match site_context!(engine.detect_precompiled_file(path))? {
+                Some(Precompiled::Module) => {
+                    ModuleType::Core(site_context!(Module::deserialize_file(&engine, path))?)
+                }
+                #[cfg(feature = "component-model")]
+                Some(Precompiled::Component) => ModuleType::Component(site_context!(
+                    Component::deserialize_file(&engine, path)
+                )?),
+                _ => bail_with_site!("Unsupported data content"),
+            })
+        }
+    }
+
     fn _deserialize_file(&self, path: String, imports: Option<Dictionary>) -> bool {
         let r = self.data.get_or_try_init(move || -> AnyResult<_> {
-            let engine = site_context!(get_engine())?;
+            let module = Self::deserialize_module_file(&PathBuf::from(path))?;
+
+            let imports = Self::process_deps_map(&module, imports)?;
+
+            Ok(ModuleData {
+                name: Self::name_from_module(&module),
+                module,
+                metadata: None,
+                imports,
+            })
+        });
+        if let Err(e) = r {
+            godot_error!("{:?}", e);
+            false
+        } else {
+            true
+        }
+    }
+    fn _load_cached(&self, path: String, source: PackedByteArray, imports: Dictionary) -> bool {
+        let r = self.data.get_or_try_init(move || -> AnyResult<_> {
             let path = PathBuf::from(path);
-            // SAFETY: Assume the supplied file is safe to deserialize.
-            let module = unsafe {
-                match site_context!(engine.detect_precompiled_file(&path))? {
-                    Some(Precompiled::Module) => {
-                        ModuleType::Core(site_context!(Module::deserialize_file(&engine, path))?)
+            let (module, metadata) = match Self::deserialize_module_file(&path) {
+                Ok(v) => (v, None),
+                Err(e) if !source.is_empty() => {
+                    // Cache entry is missing or corrupted, rebuild it from source.
+                    godot_warn!("Rebuilding module cache {}: {e}", path.display());
+                    let _ = fs::remove_file(&path);
+                    let (module, metadata) = Self::load_module(source.as_slice())?;
+                    let data = match &module {
+                        ModuleType::Core(m) => m.serialize(),
+                        #[cfg(feature = "component-model")]
+                        ModuleType::Component(m) => m.serialize(),
+                    };
+                    if let Err(e) = data.and_then(|v| Ok(fs::write(&path, v)?)) {
+                        godot_warn!("Cannot write module cache {}: {e}", path.display());
                     }
-                    #[cfg(feature = "component-model")]
-                    Some(Precompiled::Component) => ModuleType::Component(site_context!(
-                        Component::deserialize_file(&engine, path)
-                    )?),
-                    _ => bail_with_site!("Unsupported data content"),
+                    (module, metadata)
                 }
+                Err(e) => return Err(e),
             };
 
-            let imports = Self::process_deps_map(&module, imports)?;
+            let imports = Self::process_deps_map(&module, Some(imports))?;
 
             Ok(ModuleData {
                 name: Self::name_from_module(&module),
                 module,
+                metadata,
                 imports,
             })
         });
@@ -444,6 +674,32 @@ impl WasmModule {
         }
     }
 
+    /// Initialize and loads module from base64-encoded WASM/WAT data.
+    ///
+    /// Returns itself if succeed and `null` if failed.
+    #[func]
+    fn initialize_base64(&self, data: GString, imports: Dictionary) -> Option<Gd<WasmModule>> {
+        let data = match decode_base64(&data.to_string()) {
+            Ok(v) => PackedByteArray::from(&*v),
+            Err(e) => {
+                godot_error!("Cannot decode base64 data: {e:?}");
+                return None;
+            }
+        };
+
+        if self._initialize(data.to_variant(), Some(imports)) {
+            Some(self.to_gd())
+        } else {
+            None
+        }
+    }
+
+    /// Gets number of live instances created from this module.
+    #[func]
+    fn get_live_instance_count(&self) -> i64 {
+        self.live_instances.load(Ordering::Acquire) as _
+    }
+
     /// Gets the module name, if exists.
     #[func]
     fn get_name(&self) -> GString {
@@ -504,6 +760,28 @@ impl WasmModule {
         }
     }
 
+    /// Deserialize module from cache file, recompiling it from source if needed.
+    ///
+    /// If the cache file cannot be deserialized (missing, corrupted, or from
+    /// different version), it is deleted and the module is compiled from `source`.
+    /// The newly compiled module is then written back to the cache file.
+    /// If `source` is empty, this behaves like `deserialize_file()`.
+    ///
+    /// **⚠ DO NOT USE THIS WITH UNTRUSTED DATA**
+    #[func]
+    fn load_cached(
+        &self,
+        path: GString,
+        source: PackedByteArray,
+        imports: Dictionary,
+    ) -> Option<Gd<WasmModule>> {
+        if self._load_cached(path.to_string(), source, imports) {
+            Some(self.to_gd())
+        } else {
+            None
+        }
+    }
+
     /// Deserialize compiled module data.
     ///
     /// **⚠ DO NOT USE THIS WITH UNTRUSTED DATA**
@@ -541,6 +819,7 @@ impl WasmModule {
     /// with the value is a struct of the following:
     /// - `params` : Array of parameter types.
     /// - `results` : Array of result types.
+    /// - `demangled` : Demangled name. Only with `demangle` feature.
     #[func]
     fn get_exports(&self) -> Dictionary {
         self.unwrap_data(|m| {
@@ -550,12 +829,16 @@ impl WasmModule {
             for i in site_context!(m.module.get_core())?.exports() {
                 if let ExternType::Func(f) = i.ty() {
                     let (p, r) = from_signature(&f);
-                    ret.set(
-                        i.name(),
-                        [(params_str.clone(), p), (results_str.clone(), r)]
-                            .into_iter()
-                            .collect::<Dictionary>(),
+                    #[allow(unused_mut)]
+                    let mut d = [(params_str.clone(), p), (results_str.clone(), r)]
+                        .into_iter()
+                        .collect::<Dictionary>();
+                    #[cfg(feature = "demangle")]
+                    d.set(
+                        StringName::from(c"demangled"),
+                        demangle_name(i.name()).unwrap_or_else(|| i.name().to_string()),
                     );
+                    ret.set(i.name(), d);
                 }
             }
             Ok(ret)
@@ -647,6 +930,143 @@ impl WasmModule {
         .unwrap_or_default()
     }
 
+    /// Compares imports and exports of two modules.
+    ///
+    /// Returns `{ "compatible", "added", "removed", "changed" }`.
+    /// Module `b` is compatible with `a` if it has no removed or changed entries,
+    /// and no added imports.
+    #[func]
+    fn abi_compatible(a: Gd<WasmModule>, b: Gd<WasmModule>) -> Dictionary {
+        let b = b.bind();
+        a.bind()
+            .unwrap_data(|a| {
+                let (a, b) = (
+                    site_context!(a.module.get_core())?,
+                    site_context!(b.get_data()?.module.get_core())?,
+                );
+                let diff = AbiDiff::new(a, b);
+                let compatible = diff.is_compatible();
+                let [added, removed, changed] = [diff.added, diff.removed, diff.changed].map(|v| {
+                    v.into_iter()
+                        .map(GString::from)
+                        .collect::<PackedStringArray>()
+                });
+
+                let mut ret = Dictionary::new();
+                ret.set(StringName::from(c"compatible"), compatible);
+                ret.set(StringName::from(c"added"), added);
+                ret.set(StringName::from(c"removed"), removed);
+                ret.set(StringName::from(c"changed"), changed);
+                Ok(ret)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Checks exported functions against a specification.
+    ///
+    /// `spec` maps function name to `{ "params", "results" }` (same format as host functions).
+    /// Returns `{ "ok", "missing", "mismatched" }`.
+    #[func]
+    fn require_exports(&self, spec: Dictionary) -> Dictionary {
+        self.unwrap_data(|m| {
+            let module = site_context!(m.module.get_core())?;
+            let params_str = StringName::from(c"params");
+            let results_str = StringName::from(c"results");
+
+            let mut missing = PackedStringArray::new();
+            let mut mismatched = PackedStringArray::new();
+            for (k, v) in spec.iter_shared() {
+                let name = k.to_string();
+                let v = site_context!(from_var_any::<Dictionary>(v))?;
+                let ty = to_signature(
+                    v.get(params_str.clone())
+                        .unwrap_or_else(|| PackedByteArray::new().to_variant()),
+                    v.get(results_str.clone())
+                        .unwrap_or_else(|| PackedByteArray::new().to_variant()),
+                )?;
+
+                match module.get_export(&name) {
+                    Some(ExternType::Func(f)) if FuncType::eq(&f, &ty) => (),
+                    Some(_) => mismatched.push(name.into()),
+                    None => missing.push(name.into()),
+                }
+            }
+
+            let mut ret = Dictionary::new();
+            ret.set(
+                StringName::from(c"ok"),
+                missing.is_empty() && mismatched.is_empty(),
+            );
+            ret.set(StringName::from(c"missing"), missing);
+            ret.set(StringName::from(c"mismatched"), mismatched);
+            Ok(ret)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Gets the initial size (in pages) of exported memory.
+    ///
+    /// Returns `-1` if there is no exported memory.
+    #[func]
+    fn get_initial_memory_pages(&self) -> i64 {
+        self.unwrap_data(|m| {
+            Ok(
+                match site_context!(m.module.get_core())?.get_export(MEMORY_EXPORT) {
+                    Some(ExternType::Memory(mem)) => mem.minimum() as i64,
+                    _ => -1,
+                },
+            )
+        })
+        .unwrap_or(-1)
+    }
+
+    /// Gets the ranges of active data segments of exported memory.
+    ///
+    /// Each element is a `Vector2i` of start offset and length.
+    /// Segments with offset that depends on global value are not included.
+    /// Segments with offset or length that does not fit in `i32` are also not included.
+    ///
+    /// Only available if module is compiled from source.
+    #[func]
+    fn get_data_segment_ranges(&self) -> Array<Vector2i> {
+        self.unwrap_data(|m| {
+            Ok(m.get_metadata()?
+                .segments_of_memory(MEMORY_EXPORT)
+                .filter_map(|s| {
+                    Some(Vector2i::new(
+                        i32::try_from(s.offset?).ok()?,
+                        i32::try_from(s.len).ok()?,
+                    ))
+                })
+                .collect())
+        })
+        .unwrap_or_default()
+    }
+
+    /// Gets toolchain metadata from "producers" custom section.
+    ///
+    /// Returns dictionary of field name (`language`, `processed-by`, `sdk`)
+    /// to dictionary of tool name and version.
+    /// Empty if the section is absent or module is not compiled from source.
+    #[func]
+    fn get_producers(&self) -> Dictionary {
+        self.unwrap_data(|m| {
+            let mut ret = Dictionary::new();
+            let Some(metadata) = &m.metadata else {
+                return Ok(ret);
+            };
+            for (field, values) in &metadata.producers {
+                let mut d = Dictionary::new();
+                for (name, version) in values {
+                    d.set(name.as_str(), version.as_str());
+                }
+                ret.set(field.as_str(), d);
+            }
+            Ok(ret)
+        })
+        .unwrap_or_default()
+    }
+
     /// Gets statistics about memories and tables required to instantiate this module (without imports).
     ///
     /// You can use this for minimal checks against resource exhaustion.
@@ -755,11 +1175,72 @@ impl WasmModule {
         let config = if config.is_nil() { None } else { Some(config) };
 
         let inst = WasmInstance::new_gd();
-        if inst.bind().initialize_(self.to_gd(), host, config) {
+        if inst.bind().initialize_(self.to_gd(), host, config, None) {
             Some(inst)
         } else {
             godot_error!("Error instantiating");
             None
         }
     }
+
+    /// Instantiate module, reusing import resolution of previous call.
+    ///
+    /// Plan (including converted host functions) is keyed by content of config and host.
+    #[func]
+    fn instantiate_fast(&self, host: Variant, config: Variant) -> Option<Gd<WasmInstance>> {
+        let Ok(host) = variant_to_option::<Dictionary>(host) else {
+            godot_error!("Host is not a dictionary!");
+            return None;
+        };
+        let config = if config.is_nil() { None } else { Some(config) };
+
+        let key = plan_key(host.as_ref(), config.as_ref());
+        let plan = self.import_plans.lock().get(&key).and_then(|v| {
+            v.iter()
+                .find(|e| e.matches(host.as_ref(), config.as_ref()))
+                .map(|e| e.plan.clone())
+        });
+        let plan = match plan {
+            Some(v) => v,
+            None => match new_host_linker() {
+                Ok(host) => ImportPlan {
+                    sources: Arc::new([]),
+                    host,
+                },
+                Err(e) => {
+                    godot_error!("{e:?}");
+                    return None;
+                }
+            },
+        };
+        let entry = plan
+            .sources
+            .is_empty()
+            .then(|| PlanEntry::new(host.as_ref(), config.as_ref(), plan.clone()));
+
+        let inst = WasmInstance::new_gd();
+        if !inst
+            .bind()
+            .initialize_(self.to_gd(), host, config, Some(plan))
+        {
+            godot_error!("Error instantiating");
+            return None;
+        }
+
+        if let Some(mut entry) = entry {
+            if let Ok(data) = inst.bind().get_data() {
+                entry.plan.sources = data
+                    .import_bindings
+                    .iter()
+                    .map(|(_, _, s)| s.clone())
+                    .collect();
+                let mut plans = self.import_plans.lock();
+                if plans.values().map(Vec::len).sum::<usize>() >= MAX_IMPORT_PLANS {
+                    plans.clear();
+                }
+                plans.entry(key).or_default().push(entry);
+            }
+        }
+        Some(inst)
+    }
 }
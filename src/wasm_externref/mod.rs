@@ -1,36 +1,128 @@
 mod funcs;
 
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
 use anyhow::Result as AnyResult;
 use godot::prelude::*;
-use wasmtime::{AsContext, AsContextMut, ExternRef, Rooted};
+use wasmtime::{AsContext, AsContextMut, ExternRef, ManuallyRooted, Rooted};
 
 use crate::godot_util::SendSyncWrapper;
-use crate::site_context;
+use crate::wasm_instance::StoreData;
+use crate::{bail_with_site, site_context};
 pub use funcs::Funcs;
 
+/// Externref data. Keeps track of live externref count.
+struct ExternData {
+    v: SendSyncWrapper<Variant>,
+    count: Arc<AtomicUsize>,
+}
+
+impl ExternData {
+    fn new(v: Variant, count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::AcqRel);
+        Self {
+            v: SendSyncWrapper::new(v),
+            count,
+        }
+    }
+}
+
+impl Drop for ExternData {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn new_externref<T>(
+    mut ctx: impl AsContextMut<Data = T>,
+    v: Variant,
+) -> AnyResult<Rooted<ExternRef>>
+where
+    T: AsRef<StoreData>,
+{
+    let data = ctx.as_context().data().as_ref();
+    let count = data.externref_count.clone();
+    if let Some(max) = data.max_externrefs {
+        if count.load(Ordering::Acquire) >= max {
+            bail_with_site!("Too many externrefs (maximum {max})");
+        }
+    }
+    site_context!(ExternRef::new(&mut ctx, ExternData::new(v, count)))
+}
+
 pub fn externref_to_variant(
     ctx: impl AsContext,
     v: Option<Rooted<ExternRef>>,
 ) -> AnyResult<Variant> {
     v.and_then(|v| {
         site_context!(v.data(ctx.as_context()))
-            .map(|v| {
-                v.downcast_ref::<SendSyncWrapper<Variant>>()
-                    .map(|v| (**v).clone())
-            })
+            .map(|v| v.downcast_ref::<ExternData>().map(|v| (*v.v).clone()))
             .transpose()
     })
     .transpose()
     .map(|v| v.unwrap_or_default())
 }
 
-pub fn variant_to_externref(
-    ctx: impl AsContextMut,
+pub fn variant_to_externref<T>(
+    mut ctx: impl AsContextMut<Data = T>,
     v: Variant,
-) -> AnyResult<Option<Rooted<ExternRef>>> {
+) -> AnyResult<Option<Rooted<ExternRef>>>
+where
+    T: AsRef<StoreData> + AsMut<StoreData>,
+{
     if v.is_nil() {
-        Ok(None)
-    } else {
-        site_context!(ExternRef::new(ctx, SendSyncWrapper::new(v)).map(Some))
+        return Ok(None);
     }
+
+    let mut ctx = ctx.as_context_mut();
+    // RefCounted objects are not cached, as the cached externref would keep them alive.
+    let obj = match ctx.data().as_ref().externref_identity {
+        Some(_) => v
+            .try_to::<Gd<Object>>()
+            .ok()
+            .filter(|o| o.clone().try_cast::<RefCounted>().is_err()),
+        None => None,
+    };
+    let Some(obj) = obj else {
+        return new_externref(ctx, v).map(Some);
+    };
+
+    let mut cache = ctx.data_mut().as_mut().externref_identity.take().unwrap();
+    let r = get_or_insert_identity(&mut ctx, &mut cache, obj.instance_id(), v);
+    ctx.data_mut().as_mut().externref_identity = Some(cache);
+    r.map(Some)
+}
+
+fn get_or_insert_identity<T>(
+    mut ctx: impl AsContextMut<Data = T>,
+    cache: &mut HashMap<InstanceId, ManuallyRooted<ExternRef>>,
+    id: InstanceId,
+    v: Variant,
+) -> AnyResult<Rooted<ExternRef>>
+where
+    T: AsRef<StoreData>,
+{
+    if let Some(r) = cache.get(&id) {
+        return Ok(r.to_rooted(&mut ctx));
+    }
+
+    // Evict freed objects every time cache size doubles.
+    if cache.len() >= 64 && cache.len().is_power_of_two() {
+        let freed = cache
+            .keys()
+            .filter(|&&k| <Gd<Object>>::try_from_instance_id(k).is_err())
+            .copied()
+            .collect::<Vec<_>>();
+        for k in freed {
+            if let Some(r) = cache.remove(&k) {
+                r.unroot(&mut ctx);
+            }
+        }
+    }
+
+    let r = new_externref(&mut ctx, v)?;
+    cache.insert(id, site_context!(r.to_manually_rooted(&mut ctx))?);
+    Ok(r)
 }
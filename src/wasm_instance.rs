@@ -1,33 +1,54 @@
 #[cfg(feature = "wasi")]
 use std::any::Any;
+use std::borrow::{Borrow, Cow};
 #[cfg(feature = "wasm-threads")]
 use std::cell::UnsafeCell;
-use std::collections::hash_map::{Entry, HashMap};
+use std::collections::hash_map::{DefaultHasher, Entry, HashMap};
+use std::collections::{HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
 #[cfg(feature = "wasi")]
+use std::io::{Read, Result as IoResult};
+#[cfg(feature = "object-registry-extern")]
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 use std::{fmt, mem, ptr};
 
 use anyhow::{bail, Result as AnyResult};
 use cfg_if::cfg_if;
+#[cfg(feature = "wasi")]
+use godot::classes::file_access::ModeFlags;
+use godot::classes::image::Format as ImageFormat;
+#[cfg(feature = "wasi")]
+use godot::classes::FileAccess;
+use godot::classes::Image;
+use godot::classes::{ClassDb, IRefCounted, Script};
+#[cfg(feature = "object-registry-compat")]
+use godot::global::type_string;
+use godot::global::{bytes_to_var, var_to_bytes, Error as GError};
 use godot::prelude::*;
-use once_cell::sync::OnceCell;
-use parking_lot::{lock_api::RawMutex as RawMutexTrait, Mutex, RawMutex};
+use parking_lot::{
+    lock_api::RawMutex as RawMutexTrait, MappedRwLockReadGuard, Mutex, RawMutex, RwLock,
+    RwLockReadGuard,
+};
 use rayon::prelude::*;
 use scopeguard::guard;
 #[cfg(feature = "component-model")]
 use wasmtime::component::Instance as InstanceComp;
 #[cfg(feature = "wasi")]
 use wasmtime::component::ResourceTable;
-#[cfg(feature = "wasi")]
 use wasmtime::Linker;
-#[cfg(feature = "memory-limiter")]
 use wasmtime::ResourceLimiter;
 #[cfg(feature = "wasm-threads")]
 use wasmtime::SharedMemory;
 use wasmtime::{
-    AsContextMut, Extern, Func, FuncType, Instance as InstanceWasm, Memory, Store, StoreContextMut,
+    AsContextMut, Extern, ExternType, Func, FuncType, Global, Instance as InstanceWasm, Memory,
+    Module, Mutability, Store, StoreContextMut, Trap, Val, ValType, WasmBacktrace,
 };
+#[cfg(feature = "object-registry-extern")]
+use wasmtime::{ExternRef, ManuallyRooted};
 #[cfg(feature = "wasi")]
 use wasmtime_wasi::preview1::{add_to_linker_sync, WasiP1Ctx};
 #[cfg(feature = "wasi")]
@@ -36,14 +57,14 @@ use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiView};
 #[cfg(feature = "wasi")]
 use crate::godot_util::gstring_from_maybe_utf8;
 use crate::godot_util::{
-    option_to_variant, variant_to_option, PackedArrayLike, PhantomProperty, SendSyncWrapper,
-    StructPacking,
+    gstring_from_utf16_le, option_to_variant, utf16_le_from_str, variant_to_option,
+    PackedArrayLike, PhantomProperty, SendSyncWrapper, StructPacking,
 };
-use crate::rw_struct::{read_struct, write_struct};
+use crate::rw_struct::{decode_tagged, encode_tagged, read_struct, struct_size, write_struct};
 #[cfg(feature = "wasi")]
 use crate::wasi_ctx::stdio::{
-    BlockWritePipe, ByteBufferReadPipe, InnerStdin, LineWritePipe, OuterStdin, StreamWrapper,
-    UnbufferedWritePipe,
+    BlockWritePipe, BufferedPipe, ByteBufferReadPipe, InnerStdin, LineWritePipe, OuterStdin,
+    StreamWrapper, UnbufferedWritePipe,
 };
 #[cfg(feature = "wasi")]
 use crate::wasi_ctx::WasiContext;
@@ -51,19 +72,34 @@ use crate::wasm_config::Config;
 #[cfg(any(feature = "object-registry-compat", feature = "object-registry-extern"))]
 use crate::wasm_config::ExternBindingType;
 #[cfg(feature = "wasi")]
-use crate::wasm_config::{PipeBindingType, PipeBufferType};
-use crate::wasm_engine::{get_engine, ModuleData, ModuleType, WasmModule};
+use crate::wasm_config::{PipeBindingType, PipeBufferType, WasiVersion};
+use crate::wasm_engine::{
+    get_engine, AbiDiff, LiveInstanceGuard, ModuleData, ModuleType, WasmModule,
+};
+#[cfg(feature = "epoch-timeout")]
+use crate::wasm_engine::{get_epoch, start_epoch};
 #[cfg(feature = "object-registry-extern")]
 use crate::wasm_externref::Funcs as ExternrefFuncs;
 #[cfg(feature = "object-registry-compat")]
-use crate::wasm_objregistry::{Funcs as ObjregistryFuncs, ObjectRegistry};
+use crate::wasm_objregistry::{CommandFuncs, Funcs as ObjregistryFuncs, ObjectRegistry};
 #[cfg(feature = "object-registry-extern")]
 use crate::wasm_util::EXTERNREF_MODULE;
 #[cfg(feature = "object-registry-compat")]
 use crate::wasm_util::OBJREGISTRY_MODULE;
-use crate::wasm_util::{config_store_common, raw_call, HostModuleCache, MEMORY_EXPORT};
+use crate::wasm_util::{
+    config_store_common, raw_call, resolve_fallback_import, EventFuncs, HostModuleCache, HostTrap,
+    RandFuncs, RandStream, TimeFuncs, YieldFuncs, CONSTANTS_MODULE, DEFAULT_MAX_EVENTS,
+    DEFAULT_MAX_STRUCT_ELEMENTS, ENV_MODULE, MEMORY_EXPORT,
+};
+#[cfg(feature = "epoch-timeout")]
+use crate::wasm_util::{set_epoch_deadline, EpochFuncs, EPOCH_DEADLINE, EPOCH_MULTIPLIER};
 use crate::{bail_with_site, site_context, variant_dispatch};
 
+/// Maximum length of value summary in `registry_describe()`.
+#[cfg(feature = "object-registry-compat")]
+const REGISTRY_SUMMARY_LEN: usize = 64;
+
+#[derive(Clone)]
 enum MemoryType {
     Memory(Memory),
     #[cfg(feature = "wasm-threads")]
@@ -131,22 +167,85 @@ enum MemoryType {
 /// | `Td` | `Transform2D` | 96 | 3D transform represented as 12 64-bit floating-point number |
 pub struct WasmInstance {
     base: Base<RefCounted>,
-    data: OnceCell<InstanceData<StoreData>>,
-    memory: Option<MemoryType>,
+    /// Instance data, replaced by `reload_module()`.
+    ///
+    /// Read lock is held while using it (including during calls),
+    /// so it's only replaced if no one else uses it.
+    data: RwLock<Option<InstanceData<StoreData>>>,
+    /// Memory of instance data. Only written while data write lock is held,
+    /// or by `memory_set_name()` with the store locked.
+    memory: RwLock<Option<MemoryType>>,
+    memory_warned: AtomicBool,
+    trap_handler: Mutex<Option<Callable>>,
+    memory_grow_callback: Mutex<Option<Callable>>,
+    /// Host and config used for instantiation, reused by `reload_module()`.
+    init_args: Mutex<Option<(Option<Dictionary>, Option<Variant>)>>,
+    /// Set while `initialize_async()` is in progress.
+    init_pending: AtomicBool,
+    /// Names that are not exported functions, so `get_property()` skips them.
+    /// Cleared by `reload_module()`.
+    missing_exports: Mutex<HashSet<String>>,
 
     /// Reference to the module that is used to instantiate this object.
     #[var(get = get_module)]
     #[allow(dead_code)]
     module: PhantomProperty<Option<Gd<WasmModule>>>,
+
+    /// Copy of the whole memory. Empty if there is no memory.
+    #[var(get = get_memory_bytes, usage_flags = [EDITOR, READ_ONLY])]
+    #[allow(dead_code)]
+    memory_bytes: PhantomProperty<PackedByteArray>,
 }
 
 pub struct InstanceData<T> {
     pub store: Mutex<Store<T>>,
     pub instance: InstanceType,
     pub module: Gd<WasmModule>,
+    pub export_cache: Option<HashMap<String, Extern>>,
+    pub memory_imports: HashMap<String, Extern>,
+    /// Module name, import name, and where the import is resolved from.
+    pub import_bindings: Vec<(String, String, Cow<'static, str>)>,
+    pub live_guard: LiveInstanceGuard,
+    /// Shared with store memory limiter.
+    pub memory_exhausted: Arc<AtomicBool>,
+    /// Interrupts running call when set. Only if `epoch.allowInterrupt` is enabled.
+    #[cfg(feature = "epoch-timeout")]
+    pub interrupt: Option<Arc<AtomicBool>>,
 
     #[cfg(feature = "wasi")]
     pub wasi_stdin: Option<Arc<InnerStdin<dyn Any + Send + Sync>>>,
+    #[cfg(feature = "wasi")]
+    pub wasi_buffers: Vec<Arc<dyn BufferedPipe>>,
+}
+
+/// Returns `true` if import source can be called outside of main thread.
+///
+/// Dependency modules are checked by their own imports.
+fn is_thread_safe_source(src: &str) -> bool {
+    matches!(src, "rand" | "event" | "constants" | "epoch" | "memory") || src.starts_with("module:")
+}
+
+/// Module with all imports resolved, but not instantiated yet.
+///
+/// Holds no Godot object, so it can be instantiated in another thread.
+pub struct PreparedInstance<T> {
+    store: Store<T>,
+    module: Module,
+    imports: Vec<Extern>,
+    instance: Option<InstanceWasm>,
+    memory_imports: HashMap<String, Extern>,
+    import_bindings: Vec<(String, String, Cow<'static, str>)>,
+    main_thread_import: Option<String>,
+    host_memory: Option<Memory>,
+    /// Host data blob and symbol.
+    host_data: Option<(Vec<u8>, String)>,
+    cache_exports: bool,
+    live_guard: LiveInstanceGuard,
+
+    #[cfg(feature = "wasi")]
+    wasi_stdin: Option<Arc<InnerStdin<dyn Any + Send + Sync>>>,
+    #[cfg(feature = "wasi")]
+    wasi_buffers: Vec<Arc<dyn BufferedPipe>>,
 }
 
 #[allow(dead_code)]
@@ -195,21 +294,73 @@ impl Default for InnerLock {
 
 pub struct StoreData {
     inner_lock: InnerLock,
-    pub error_signal: Option<String>,
+    pub error_signal: Option<HostTrap>,
+    pub max_results: Option<usize>,
+    pub strict_arity: bool,
+    pub max_host_reentry: Option<u32>,
+    pub host_depth: u32,
+    pub max_call_depth: Option<u32>,
+    pub call_depth: u32,
+    #[cfg(feature = "epoch-timeout")]
+    pub interrupt: Option<Arc<AtomicBool>>,
+    /// Epoch of `call_wasm_within_budget()` deadline.
+    #[cfg(feature = "epoch-timeout")]
+    pub budget_deadline: Option<u64>,
+    #[cfg(feature = "epoch-timeout")]
+    pub budget_exceeded: bool,
+    #[cfg(feature = "fuel")]
+    pub fuel_budget: Option<u64>,
+    #[cfg(feature = "fuel")]
+    pub fuel_multipliers: HashMap<String, f64>,
+    #[cfg(feature = "object-registry-compat")]
+    pub auto_unwrap_registry: bool,
+    pub max_struct_elements: usize,
+    pub single_threaded_memory_ops: bool,
+    pub alloc_func: String,
+    pub free_func: Option<String>,
+    pub defer_host_calls: bool,
+    pub yield_requested: bool,
+    pub rand_seed: u64,
+    pub rand_streams: HashMap<i32, RandStream>,
+    /// Events emitted with `env.emit_event()`, as type and payload.
+    pub events: VecDeque<(i32, Vec<u8>)>,
+    pub max_events: usize,
+    pub event_overflow_error: bool,
+    pub memory_watches: Vec<Option<MemoryWatch>>,
+    /// Funcrefs of object methods, keyed by object, method and signature.
+    pub funcref_cache: HashMap<(InstanceId, String, String), Func>,
+    /// Number of funcrefs created from callables.
+    pub funcref_count: usize,
+    pub profile: Option<HashMap<String, ProfileEntry>>,
+    pub memory_stats: Option<MemoryStats>,
+    pub trace: Option<VecDeque<TraceEntry>>,
 
     #[cfg(feature = "epoch-timeout")]
     pub epoch_timeout: u64,
     #[cfg(feature = "epoch-timeout")]
     pub epoch_autoreset: bool,
+    #[cfg(feature = "epoch-timeout")]
+    pub epoch_deadline: u64,
 
-    #[cfg(feature = "memory-limiter")]
     pub memory_limits: MemoryLimit,
 
     #[cfg(feature = "object-registry-compat")]
     pub object_registry: Option<ObjectRegistry>,
+    /// Externrefs of objects that are not `RefCounted`, keyed by instance ID.
+    #[cfg(feature = "object-registry-extern")]
+    pub externref_identity: Option<HashMap<InstanceId, ManuallyRooted<ExternRef>>>,
+    #[cfg(feature = "object-registry-extern")]
+    pub gc_after_call: bool,
+    #[cfg(feature = "object-registry-extern")]
+    pub externref_count: Arc<AtomicUsize>,
+    #[cfg(feature = "object-registry-extern")]
+    pub max_externrefs: Option<usize>,
 
     #[cfg(feature = "wasi")]
     pub wasi_ctx: MaybeWasi,
+    /// Recent standard error output, if captured.
+    #[cfg(feature = "wasi")]
+    pub stderr_tail: Option<Arc<Mutex<Vec<u8>>>>,
 }
 
 impl AsRef<Self> for StoreData {
@@ -242,22 +393,154 @@ impl Default for StoreData {
         Self {
             inner_lock: InnerLock::default(),
             error_signal: None,
+            max_results: None,
+            strict_arity: false,
+            max_host_reentry: None,
+            host_depth: 0,
+            max_call_depth: None,
+            call_depth: 0,
+            #[cfg(feature = "epoch-timeout")]
+            interrupt: None,
+            #[cfg(feature = "epoch-timeout")]
+            budget_deadline: None,
+            #[cfg(feature = "epoch-timeout")]
+            budget_exceeded: false,
+            #[cfg(feature = "fuel")]
+            fuel_budget: None,
+            #[cfg(feature = "fuel")]
+            fuel_multipliers: HashMap::new(),
+            #[cfg(feature = "object-registry-compat")]
+            auto_unwrap_registry: false,
+            max_struct_elements: DEFAULT_MAX_STRUCT_ELEMENTS,
+            single_threaded_memory_ops: false,
+            alloc_func: String::new(),
+            free_func: None,
+            defer_host_calls: false,
+            yield_requested: false,
+            rand_seed: 0,
+            rand_streams: HashMap::new(),
+            events: VecDeque::new(),
+            max_events: DEFAULT_MAX_EVENTS,
+            event_overflow_error: false,
+            memory_watches: Vec::new(),
+            funcref_cache: HashMap::new(),
+            funcref_count: 0,
+            profile: None,
+            memory_stats: None,
+            trace: None,
 
             #[cfg(feature = "epoch-timeout")]
             epoch_timeout: 0,
             #[cfg(feature = "epoch-timeout")]
             epoch_autoreset: false,
+            #[cfg(feature = "epoch-timeout")]
+            epoch_deadline: 0,
 
-            #[cfg(feature = "memory-limiter")]
             memory_limits: MemoryLimit::default(),
 
             #[cfg(feature = "object-registry-compat")]
             object_registry: None,
+            #[cfg(feature = "object-registry-extern")]
+            externref_identity: None,
+            #[cfg(feature = "object-registry-extern")]
+            gc_after_call: false,
+            #[cfg(feature = "object-registry-extern")]
+            externref_count: Arc::default(),
+            #[cfg(feature = "object-registry-extern")]
+            max_externrefs: None,
 
             #[cfg(feature = "wasi")]
             wasi_ctx: MaybeWasi::NoCtx,
+            #[cfg(feature = "wasi")]
+            stderr_tail: None,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct ProfileEntry {
+    calls: u64,
+    total: Duration,
+}
+
+/// Maximum number of remembered names that are not exported functions.
+///
+/// Set is cleared when it's full.
+const MAX_MISSING_EXPORTS: usize = 256;
+
+/// Maximum number of recorded calls in trace.
+const MAX_TRACE_ENTRIES: usize = 4096;
+
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    name: String,
+    depth: u32,
+    args_hash: u64,
+    /// `None` if the call errored.
+    result_hash: Option<u64>,
+    #[cfg(feature = "fuel")]
+    fuel: u64,
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct MemoryStats {
+    bytes_read: u64,
+    bytes_written: u64,
+    read_calls: u64,
+    write_calls: u64,
+}
+
+impl MemoryStats {
+    fn record(stats: &mut Option<Self>, n: usize, write: bool) {
+        let Some(s) = stats else { return };
+        if write {
+            s.bytes_written = s.bytes_written.saturating_add(n as u64);
+            s.write_calls += 1;
+        } else {
+            s.bytes_read = s.bytes_read.saturating_add(n as u64);
+            s.read_calls += 1;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryWatch {
+    start: usize,
+    len: usize,
+    hash: u64,
+}
+
+impl MemoryWatch {
+    fn hash_bytes(s: &[u8]) -> u64 {
+        let mut h = DefaultHasher::new();
+        s.hash(&mut h);
+        h.finish()
+    }
+
+    fn new(data: &[u8], start: usize, len: usize) -> AnyResult<Self> {
+        Ok(Self {
+            start,
+            len,
+            hash: Self::hash_bytes(memory_slice(data, start, len)?),
+        })
+    }
+
+    /// Rehashes watched range. Returns false if it's no longer in bound.
+    fn resync(&mut self, data: &[u8]) -> bool {
+        match memory_slice(data, self.start, self.len) {
+            Ok(s) => {
+                self.hash = Self::hash_bytes(s);
+                true
+            }
+            Err(_) => false,
         }
     }
+
+    /// Checks if watched range has changed since last check.
+    fn changed(&mut self, data: &[u8]) -> AnyResult<bool> {
+        let hash = Self::hash_bytes(memory_slice(data, self.start, self.len)?);
+        Ok(mem::replace(&mut self.hash, hash) != hash)
+    }
 }
 
 #[allow(dead_code)]
@@ -290,37 +573,88 @@ impl WasiView for StoreData {
     }
 }
 
-#[cfg(feature = "memory-limiter")]
+/// Resource limiter. Limits are only set with `memory-limiter` feature.
 pub struct MemoryLimit {
     pub max_memory: u64,
     pub max_table_entries: u64,
+    /// Memory sizes (in pages) after each growth, if observed.
+    pub grown_pages: Option<Vec<u64>>,
+    /// Number of denied growth (and time window) before memory is exhausted.
+    pub exhausted_threshold: Option<(usize, Option<Duration>)>,
+    /// Set if memory is exhausted, until `memory_exhausted` signal is emitted.
+    pub exhausted: Arc<AtomicBool>,
+    denials: Vec<Instant>,
 }
 
-#[cfg(feature = "memory-limiter")]
 impl Default for MemoryLimit {
     fn default() -> Self {
         Self {
             max_memory: u64::MAX,
             max_table_entries: u64::MAX,
+            grown_pages: None,
+            exhausted_threshold: None,
+            exhausted: Arc::default(),
+            denials: Vec::new(),
         }
     }
 }
 
-#[cfg(feature = "memory-limiter")]
 impl MemoryLimit {
+    #[allow(unused_mut)]
     pub fn from_config(config: &Config) -> Self {
-        let mut ret = Self::default();
-        if let Some(v) = config.max_memory {
-            ret.max_memory = v;
-        }
-        if let Some(v) = config.max_entries {
-            ret.max_table_entries = v;
+        let mut ret = Self {
+            exhausted_threshold: config.memory_exhausted_threshold.map(|n| {
+                let window = config
+                    .memory_exhausted_window
+                    .and_then(|v| Duration::try_from_secs_f64(v).ok());
+                (n, window)
+            }),
+            ..Self::default()
+        };
+        #[cfg(feature = "memory-limiter")]
+        {
+            if let Some(v) = config.max_memory {
+                ret.max_memory = v;
+            }
+            if let Some(v) = config.max_entries {
+                ret.max_table_entries = v;
+            }
         }
         ret
     }
+
+    fn allow_memory(&mut self, current: usize, desired: usize) -> bool {
+        if self.max_memory == u64::MAX {
+            return true;
+        }
+
+        let delta = (desired - current) as u64;
+        if let Some(v) = self.max_memory.checked_sub(delta) {
+            self.max_memory = v;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Records denied memory growth, marking memory as exhausted if it happens too often.
+    fn deny_memory(&mut self) {
+        let Some((n, window)) = self.exhausted_threshold else {
+            return;
+        };
+
+        let now = Instant::now();
+        if let Some(window) = window {
+            self.denials.retain(|&t| now.duration_since(t) <= window);
+        }
+        self.denials.push(now);
+        if self.denials.len() >= n {
+            self.denials.clear();
+            self.exhausted.store(true, Ordering::Release);
+        }
+    }
 }
 
-#[cfg(feature = "memory-limiter")]
 impl ResourceLimiter for MemoryLimit {
     fn memory_growing(
         &mut self,
@@ -328,19 +662,24 @@ impl ResourceLimiter for MemoryLimit {
         desired: usize,
         max: Option<usize>,
     ) -> AnyResult<bool> {
-        if max.map_or(false, |max| desired > max) {
+        if max.map_or(false, |max| desired > max) || !self.allow_memory(current, desired) {
+            self.deny_memory();
             return Ok(false);
-        } else if self.max_memory == u64::MAX {
-            return Ok(true);
         }
 
-        let delta = (desired - current) as u64;
-        if let Some(v) = self.max_memory.checked_sub(delta) {
-            self.max_memory = v;
-            Ok(true)
-        } else {
-            Ok(false)
+        self.denials.clear();
+        if let Some(v) = &mut self.grown_pages {
+            v.push((desired / WASM_PAGE_SIZE) as u64);
+        }
+        Ok(true)
+    }
+
+    fn memory_grow_failed(&mut self, _: anyhow::Error) -> AnyResult<()> {
+        if let Some(v) = &mut self.grown_pages {
+            v.pop();
         }
+        self.deny_memory();
+        Ok(())
     }
 
     fn table_growing(&mut self, current: u32, desired: u32, max: Option<u32>) -> AnyResult<bool> {
@@ -360,18 +699,287 @@ impl ResourceLimiter for MemoryLimit {
     }
 }
 
+const WASM_PAGE_SIZE: usize = 65536;
+
+#[cfg(feature = "wasi")]
+const STDERR_TAIL_LEN: usize = 4096;
+
+/// Appends to captured standard error, keeping only the last bytes.
+#[cfg(feature = "wasi")]
+fn record_stderr(tail: &Option<Arc<Mutex<Vec<u8>>>>, buf: &[u8]) {
+    let Some(tail) = tail else {
+        return;
+    };
+    let mut tail = tail.lock();
+    tail.extend_from_slice(buf);
+    if let Some(n) = tail.len().checked_sub(STDERR_TAIL_LEN) {
+        tail.drain(..n);
+    }
+}
+
+/// Minimum chunk length of parallel memory operations.
+///
+/// Single-threaded operations use a single chunk, which is processed in the calling thread.
+fn min_chunk_len(single_threaded: bool) -> usize {
+    if single_threaded {
+        usize::MAX
+    } else {
+        1
+    }
+}
+
+/// Finds memory by export name, or by `module.name` of memory import.
+fn lookup_memory(
+    inst: &InstanceWasm,
+    store: impl AsContextMut,
+    memory_imports: &HashMap<String, Extern>,
+    name: &str,
+) -> Option<MemoryType> {
+    match inst
+        .get_export(store, name)
+        .or_else(|| memory_imports.get(name).cloned())
+    {
+        Some(Extern::Memory(mem)) => Some(MemoryType::Memory(mem)),
+        #[cfg(feature = "wasm-threads")]
+        Some(Extern::SharedMemory(mem)) => Some(MemoryType::SharedMemory(mem)),
+        _ => None,
+    }
+}
+
+/// Gets `n` bytes of memory at `i`, failing if it's out of bound (including overflow).
+fn memory_slice(data: &[u8], i: usize, n: usize) -> AnyResult<&[u8]> {
+    match i.checked_add(n).and_then(|e| data.get(i..e)) {
+        Some(s) => Ok(s),
+        None => bail_with_site!("Index out of bound {}-{}", i, i.wrapping_add(n)),
+    }
+}
+
+/// Mutable version of [`memory_slice`].
+fn memory_slice_mut(data: &mut [u8], i: usize, n: usize) -> AnyResult<&mut [u8]> {
+    match i.checked_add(n).and_then(|e| data.get_mut(i..e)) {
+        Some(s) => Ok(s),
+        None => bail_with_site!("Index out of bound {}-{}", i, i.wrapping_add(n)),
+    }
+}
+
+/// Zips stream parameters of `read_soa()` into `(base, stride, type)`.
+fn soa_streams(
+    bases: &[i64],
+    strides: &[i32],
+    types: &[i32],
+) -> AnyResult<Vec<(usize, usize, VariantType)>> {
+    if bases.len() != strides.len() || bases.len() != types.len() {
+        bail_with_site!(
+            "Mismatched stream count ({}, {}, {})",
+            bases.len(),
+            strides.len(),
+            types.len()
+        );
+    }
+
+    bases
+        .iter()
+        .zip(strides)
+        .zip(types)
+        .map(|((&i, &stride), &t)| match VariantType::try_from_ord(t) {
+            Some(t) => Ok((i as usize, stride as usize, t)),
+            None => bail_with_site!("Unsupported type ID {t}"),
+        })
+        .collect()
+}
+
+/// Gets slice covering `n` elements of `size` bytes, `stride` bytes apart.
+fn strided_slice(s: &[u8], i: usize, stride: usize, n: usize, size: usize) -> AnyResult<&[u8]> {
+    if stride < size {
+        bail_with_site!("Stride is less than element size ({stride} < {size})");
+    }
+    if n == 0 {
+        return Ok(&[]);
+    }
+
+    let Some(e) = stride
+        .checked_mul(n - 1)
+        .and_then(|v| v.checked_add(size))
+        .and_then(|v| v.checked_add(i))
+    else {
+        bail_with_site!("Index out of range ({i}+{stride}*{n})");
+    };
+    match s.get(i..e) {
+        Some(v) => Ok(v),
+        None => bail_with_site!("Index out of range ({i}..{e})"),
+    }
+}
+
+/// Reads `n` elements of packed array type `t`, `stride` bytes apart.
+///
+/// Returns the array and number of bytes read.
+fn read_strided(
+    data: &[u8],
+    i: usize,
+    stride: usize,
+    n: usize,
+    t: VariantType,
+    min_len: usize,
+) -> AnyResult<(Variant, usize)> {
+    fn f<const N: usize, R>(
+        s: &[u8],
+        i: usize,
+        stride: usize,
+        n: usize,
+        min_len: usize,
+        f: impl Fn(&[u8; N]) -> R::Elem + Send + Sync,
+    ) -> AnyResult<(Variant, usize)>
+    where
+        R: PackedArrayLike + ToGodot,
+        R::Elem: Send,
+    {
+        let s = strided_slice(s, i, stride, n, N)?;
+        let mut r = R::default();
+        if n == 0 {
+            return Ok((r.to_variant(), 0));
+        }
+
+        r.resize(n);
+        r.as_mut_slice()
+            .par_iter_mut()
+            .with_min_len(min_len)
+            .enumerate()
+            .for_each(|(j, d)| {
+                let j = j * stride;
+                *d = f(s[j..j + N].try_into().unwrap());
+            });
+
+        Ok((r.to_variant(), n * N))
+    }
+
+    match t {
+        VariantType::PACKED_BYTE_ARRAY => {
+            f::<1, PackedByteArray>(data, i, stride, n, min_len, |s| s[0])
+        }
+        VariantType::PACKED_INT32_ARRAY => {
+            f::<4, PackedInt32Array>(data, i, stride, n, min_len, |s| i32::from_le_bytes(*s))
+        }
+        VariantType::PACKED_INT64_ARRAY => {
+            f::<8, PackedInt64Array>(data, i, stride, n, min_len, |s| i64::from_le_bytes(*s))
+        }
+        VariantType::PACKED_FLOAT32_ARRAY => {
+            f::<4, PackedFloat32Array>(data, i, stride, n, min_len, |s| f32::from_le_bytes(*s))
+        }
+        VariantType::PACKED_FLOAT64_ARRAY => {
+            f::<8, PackedFloat64Array>(data, i, stride, n, min_len, |s| f64::from_le_bytes(*s))
+        }
+        VariantType::PACKED_VECTOR2_ARRAY => f::<8, PackedVector2Array>(
+            data,
+            i,
+            stride,
+            n,
+            min_len,
+            <_ as StructPacking<f32>>::read_array,
+        ),
+        VariantType::PACKED_VECTOR3_ARRAY => f::<12, PackedVector3Array>(
+            data,
+            i,
+            stride,
+            n,
+            min_len,
+            <_ as StructPacking<f32>>::read_array,
+        ),
+        VariantType::PACKED_COLOR_ARRAY => f::<16, PackedColorArray>(
+            data,
+            i,
+            stride,
+            n,
+            min_len,
+            <_ as StructPacking<f32>>::read_array,
+        ),
+        #[cfg(feature = "packed-vector4")]
+        VariantType::PACKED_VECTOR4_ARRAY => f::<16, PackedVector4Array>(
+            data,
+            i,
+            stride,
+            n,
+            min_len,
+            <_ as StructPacking<f32>>::read_array,
+        ),
+        VariantType::QUATERNION => read_quaternions(data, i, stride, n, min_len),
+        _ => bail_with_site!("Unsupported type {t:?}"),
+    }
+}
+
+/// Reads `n` quaternions (xyzw 32-bit float), `stride` bytes apart, as `Array[Quaternion]`.
+///
+/// Returns the array and number of bytes read.
+fn read_quaternions(
+    s: &[u8],
+    i: usize,
+    stride: usize,
+    n: usize,
+    min_len: usize,
+) -> AnyResult<(Variant, usize)> {
+    const N: usize = 16;
+    let s = strided_slice(s, i, stride, n, N)?;
+    if n == 0 {
+        return Ok((Array::<Quaternion>::new().to_variant(), 0));
+    }
+
+    let v = (0..n)
+        .into_par_iter()
+        .with_min_len(min_len)
+        .map(|j| {
+            let j = j * stride;
+            <Quaternion as StructPacking<f32>>::read_array(s[j..j + N].try_into().unwrap())
+        })
+        .collect::<Vec<_>>();
+
+    Ok((Array::from(&*v).to_variant(), n * N))
+}
+
+/// Cached import resolution of `WasmModule.instantiate_fast()`.
+pub struct ImportPlan<T = StoreData> {
+    /// Import sources, in import order. Empty if not known yet.
+    pub sources: Arc<[Cow<'static, str>]>,
+    /// Converted host functions, shared between stores.
+    pub host: Arc<Mutex<Linker<T>>>,
+}
+
+impl<T> Clone for ImportPlan<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sources: self.sources.clone(),
+            host: self.host.clone(),
+        }
+    }
+}
+
 struct InstanceArgs<'a, T> {
     store: StoreContextMut<'a, T>,
-    #[allow(dead_code)]
     config: &'a Config,
+    /// Import sources from plan.
+    plan: Option<Arc<[Cow<'static, str>]>>,
     insts: HashMap<InstanceId, Option<InstanceWasm>>,
+    /// Memory imports of the last instantiated module.
+    memory_imports: HashMap<String, Extern>,
+    /// Import sources of the last instantiated module.
+    import_bindings: Vec<(String, String, Cow<'static, str>)>,
+    /// First import of any instantiated module that must be called in main thread.
+    main_thread_import: Option<String>,
     host: Option<HostModuleCache<T>>,
     #[cfg(feature = "object-registry-compat")]
     objregistry_funcs: ObjregistryFuncs,
+    #[cfg(feature = "object-registry-compat")]
+    command_funcs: CommandFuncs,
     #[cfg(feature = "object-registry-extern")]
     externref_funcs: ExternrefFuncs,
+    time_funcs: TimeFuncs,
+    yield_funcs: YieldFuncs,
+    rand_funcs: RandFuncs,
+    event_funcs: EventFuncs,
+    #[cfg(feature = "epoch-timeout")]
+    epoch_funcs: EpochFuncs,
     #[cfg(feature = "wasi")]
     wasi_linker: Option<Linker<T>>,
+    /// Host-provided memory, shared by all memory imports.
+    host_memory: Option<Memory>,
 }
 
 impl<T> InstanceData<T>
@@ -379,20 +987,47 @@ where
     T: Send + AsRef<StoreData> + AsMut<StoreData>,
 {
     pub fn instantiate(
-        _inst_id: InstanceId,
-        mut store: Store<T>,
+        inst_id: InstanceId,
+        store: Store<T>,
         config: &Config,
         module: Gd<WasmModule>,
         host: Option<Dictionary>,
+        plan: Option<ImportPlan<T>>,
     ) -> AnyResult<Self> {
+        let mut ret = Self::prepare(inst_id, store, config, &module, host, plan)?;
+        ret.run()?;
+        ret.into_data(module)
+    }
+
+    /// Resolves all imports of module, without instantiating it.
+    ///
+    /// Dependency modules are instantiated.
+    pub fn prepare(
+        _inst_id: InstanceId,
+        mut store: Store<T>,
+        config: &Config,
+        module: &Gd<WasmModule>,
+        host: Option<Dictionary>,
+        plan: Option<ImportPlan<T>>,
+    ) -> AnyResult<PreparedInstance<T>> {
+        let live_guard = LiveInstanceGuard::new(&module.bind(), config.max_instances)?;
         config_store_common(&mut store, config)?;
 
         #[cfg(feature = "wasi")]
         let mut wasi_stdin = None;
+        #[cfg(feature = "wasi")]
+        let mut wasi_buffers = Vec::new();
 
         #[cfg(feature = "wasi")]
         let wasi_linker = if config.with_wasi {
+            if let Some(msg) = config.wasi_version_mismatch(WasiVersion::Preview1) {
+                bail_with_site!("{msg}");
+            }
+
             let mut builder = WasiCtxBuilder::new();
+            if let Some(seed) = config.wasi_random_seed {
+                builder.insecure_random_seed(seed.into());
+            }
 
             let StoreData { wasi_ctx, .. } = store.data_mut().as_mut();
 
@@ -408,8 +1043,20 @@ where
                     wasi_stdin = Some(inner as _);
                 }
             }
-            if config.wasi_stdout == PipeBindingType::Instance {
-                match config.wasi_stdout_buffer {
+            if config.wasi_stdout_to_console {
+                {
+                    let p = StreamWrapper::from(LineWritePipe::new(|buf| {
+                        let n = buf
+                            .iter()
+                            .rposition(|&c| c != b'\n' && c != b'\r')
+                            .map_or(0, |i| i + 1);
+                        godot_print!("{}", String::from_utf8_lossy(&buf[..n]));
+                    }));
+                    wasi_buffers.push(p.as_buffered());
+                    builder.stdout(p);
+                }
+            } else if config.wasi_stdout == PipeBindingType::Instance {
+                match config.stdout_buffer() {
                     PipeBufferType::Unbuffered => {
                         builder.stdout(UnbufferedWritePipe::new(move |buf| {
                             <Gd<RefCounted>>::from_instance_id(_inst_id).emit_signal(
@@ -419,27 +1066,39 @@ where
                         }))
                     }
                     PipeBufferType::LineBuffer => {
-                        builder.stdout(StreamWrapper::from(LineWritePipe::new(move |buf| {
+                        let p = StreamWrapper::from(LineWritePipe::new(move |buf| {
                             <Gd<RefCounted>>::from_instance_id(_inst_id).emit_signal(
                                 StringName::from(c"stdout_emit"),
                                 &[gstring_from_maybe_utf8(buf).to_variant()],
                             );
-                        })))
+                        }));
+                        wasi_buffers.push(p.as_buffered());
+                        builder.stdout(p);
                     }
                     PipeBufferType::BlockBuffer => {
-                        builder.stdout(StreamWrapper::from(BlockWritePipe::new(move |buf| {
-                            <Gd<RefCounted>>::from_instance_id(_inst_id).emit_signal(
-                                StringName::from(c"stdout_emit"),
-                                &[gstring_from_maybe_utf8(buf).to_variant()],
-                            );
-                        })))
+                        let p = StreamWrapper::from(BlockWritePipe::new(
+                            move |buf| {
+                                <Gd<RefCounted>>::from_instance_id(_inst_id).emit_signal(
+                                    StringName::from(c"stdout_emit"),
+                                    &[gstring_from_maybe_utf8(buf).to_variant()],
+                                );
+                            },
+                            config.wasi_block_size,
+                        ));
+                        wasi_buffers.push(p.as_buffered());
+                        builder.stdout(p);
                     }
                 };
             }
+            let stderr_tail = config
+                .capture_panic_message
+                .then(<Arc<Mutex<Vec<u8>>>>::default);
             if config.wasi_stderr == PipeBindingType::Instance {
-                match config.wasi_stderr_buffer {
+                match config.stderr_buffer() {
                     PipeBufferType::Unbuffered => {
+                        let tail = stderr_tail.clone();
                         builder.stderr(UnbufferedWritePipe::new(move |buf| {
+                            record_stderr(&tail, buf);
                             <Gd<RefCounted>>::from_instance_id(_inst_id).emit_signal(
                                 StringName::from(c"stderr_emit"),
                                 &[PackedByteArray::from(buf).to_variant()],
@@ -447,22 +1106,40 @@ where
                         }))
                     }
                     PipeBufferType::LineBuffer => {
-                        builder.stderr(StreamWrapper::from(LineWritePipe::new(move |buf| {
+                        let tail = stderr_tail.clone();
+                        let p = StreamWrapper::from(LineWritePipe::new(move |buf| {
+                            record_stderr(&tail, buf);
                             <Gd<RefCounted>>::from_instance_id(_inst_id).emit_signal(
                                 StringName::from(c"stderr_emit"),
                                 &[gstring_from_maybe_utf8(buf).to_variant()],
                             );
-                        })))
+                        }));
+                        wasi_buffers.push(p.as_buffered());
+                        builder.stderr(p);
                     }
                     PipeBufferType::BlockBuffer => {
-                        builder.stderr(StreamWrapper::from(BlockWritePipe::new(move |buf| {
-                            <Gd<RefCounted>>::from_instance_id(_inst_id).emit_signal(
-                                StringName::from(c"stderr_emit"),
-                                &[gstring_from_maybe_utf8(buf).to_variant()],
-                            );
-                        })))
+                        let tail = stderr_tail.clone();
+                        let p = StreamWrapper::from(BlockWritePipe::new(
+                            move |buf| {
+                                record_stderr(&tail, buf);
+                                <Gd<RefCounted>>::from_instance_id(_inst_id).emit_signal(
+                                    StringName::from(c"stderr_emit"),
+                                    &[gstring_from_maybe_utf8(buf).to_variant()],
+                                );
+                            },
+                            config.wasi_block_size,
+                        ));
+                        wasi_buffers.push(p.as_buffered());
+                        builder.stderr(p);
                     }
                 };
+            } else if let (PipeBindingType::Unbound, Some(tail)) =
+                (config.wasi_stderr, &stderr_tail)
+            {
+                let tail = Some(tail.clone());
+                builder.stderr(UnbufferedWritePipe::new(move |buf| {
+                    record_stderr(&tail, buf)
+                }));
             }
 
             match &config.wasi_context {
@@ -470,6 +1147,7 @@ where
                 None => WasiContext::init_ctx_no_context(&mut builder, config),
             }?;
             *wasi_ctx = MaybeWasi::Preview1(builder.build_p1());
+            store.data_mut().as_mut().stderr_tail = stderr_tail;
             let mut r = <Linker<T>>::new(store.engine());
             add_to_linker_sync(&mut r, |data| match &mut data.as_mut().wasi_ctx {
                 MaybeWasi::Preview1(v) => v,
@@ -482,29 +1160,155 @@ where
 
         #[cfg(feature = "object-registry-compat")]
         if config.extern_bind == ExternBindingType::Registry {
-            store.data_mut().as_mut().object_registry = Some(ObjectRegistry::default());
+            let mut registry = match &config.shared_registry {
+                Some(r) => r.bind().registry.clone(),
+                None => ObjectRegistry::default(),
+            };
+            if let Some(max) = config.max_registry_entries {
+                // Deferred, as it's called with store locked and maybe from another thread.
+                registry.set_limit(max, move |ix| {
+                    <Gd<RefCounted>>::from_instance_id(_inst_id).call_deferred(
+                        StringName::from(c"emit_signal"),
+                        &[
+                            StringName::from(c"registry_overflow").to_variant(),
+                            (ix as i64).to_variant(),
+                        ],
+                    );
+                });
+            }
+            store.data_mut().as_mut().object_registry = Some(registry);
         }
 
-        let instance = InstanceArgs {
+        let mut args = InstanceArgs {
             store: store.as_context_mut(),
             config,
+            host: host
+                .map(|h| match &plan {
+                    Some(p) => Ok(HostModuleCache::with_linker(h, p.host.clone())),
+                    None => HostModuleCache::new(h),
+                })
+                .transpose()?,
+            plan: plan.map(|p| p.sources),
             insts: HashMap::new(),
-            host: host.map(HostModuleCache::new).transpose()?,
+            memory_imports: HashMap::new(),
+            import_bindings: Vec::new(),
+            main_thread_import: None,
             #[cfg(feature = "object-registry-compat")]
             objregistry_funcs: ObjregistryFuncs::default(),
+            #[cfg(feature = "object-registry-compat")]
+            command_funcs: CommandFuncs::default(),
             #[cfg(feature = "object-registry-extern")]
             externref_funcs: ExternrefFuncs::default(),
+            time_funcs: TimeFuncs::default(),
+            yield_funcs: YieldFuncs::default(),
+            rand_funcs: RandFuncs::default(),
+            event_funcs: EventFuncs::default(),
+            #[cfg(feature = "epoch-timeout")]
+            epoch_funcs: EpochFuncs::default(),
             #[cfg(feature = "wasi")]
             wasi_linker,
+            host_memory: None,
+        };
+        let (module_, imports) = args.resolve_imports(module.bind().get_data()?)?;
+
+        Ok(PreparedInstance {
+            module: module_,
+            imports,
+            instance: None,
+            memory_imports: args.memory_imports,
+            import_bindings: args.import_bindings,
+            main_thread_import: args.main_thread_import,
+            host_memory: args.host_memory,
+            host_data: config
+                .host_data
+                .as_ref()
+                .map(|v| (v.to_vec(), config.host_data_symbol.clone())),
+            cache_exports: config.cache_exports,
+            live_guard,
+            store,
+            #[cfg(feature = "wasi")]
+            wasi_stdin,
+            #[cfg(feature = "wasi")]
+            wasi_buffers,
+        })
+    }
+}
+
+impl<T> PreparedInstance<T>
+where
+    T: Send + AsRef<StoreData> + AsMut<StoreData>,
+{
+    /// Checks if module can be instantiated in another thread.
+    ///
+    /// Start function may call any import, so imports that need the main thread
+    /// (host functions, WASI, object registry, fallback resolver, etc.) must not be bound.
+    pub fn check_off_thread(&self) -> AnyResult<()> {
+        if let Some(i) = &self.main_thread_import {
+            bail_with_site!("Import {i} cannot be called outside of main thread");
         }
-        .instantiate_wasm(module.bind().get_data()?)?;
+        Ok(())
+    }
 
-        Ok(Self {
+    /// Instantiates module, running its start function.
+    pub fn run(&mut self) -> AnyResult<()> {
+        let instance = InstanceWasm::new(&mut self.store, &self.module, &self.imports)?;
+
+        if let Some((blob, symbol)) = &self.host_data {
+            let mem = instance
+                .get_memory(&mut self.store, MEMORY_EXPORT)
+                .or(self.host_memory);
+            write_host_data(&mut self.store, &instance, mem, symbol, blob)?;
+        }
+
+        self.instance = Some(instance);
+        Ok(())
+    }
+
+    /// Converts into instance data. Must be called after [`Self::run`].
+    pub fn into_data(self, module: Gd<WasmModule>) -> AnyResult<InstanceData<T>> {
+        let Self {
+            mut store,
+            instance,
+            memory_imports,
+            import_bindings,
+            cache_exports,
+            live_guard,
+            #[cfg(feature = "wasi")]
+            wasi_stdin,
+            #[cfg(feature = "wasi")]
+            wasi_buffers,
+            ..
+        } = self;
+        let Some(instance) = instance else {
+            bail_with_site!("Module is not instantiated yet")
+        };
+
+        let export_cache = if cache_exports {
+            Some(
+                instance
+                    .exports(&mut store)
+                    .map(|e| (e.name().to_string(), e.into_extern()))
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        Ok(InstanceData {
             instance: InstanceType::Core(instance),
             module,
+            export_cache,
+            memory_imports,
+            import_bindings,
+            live_guard,
+            memory_exhausted: store.data().as_ref().memory_limits.exhausted.clone(),
+            #[cfg(feature = "epoch-timeout")]
+            interrupt: store.data().as_ref().interrupt.clone(),
             store: Mutex::new(store),
             #[cfg(feature = "wasi")]
             wasi_stdin,
+            #[cfg(feature = "wasi")]
+            wasi_buffers,
         })
     }
 }
@@ -513,75 +1317,318 @@ impl<'a, T> InstanceArgs<'a, T>
 where
     T: Send + AsRef<StoreData> + AsMut<StoreData>,
 {
-    fn instantiate_wasm(&mut self, module: &ModuleData) -> AnyResult<InstanceWasm> {
-        #[allow(irrefutable_let_patterns)]
-        let ModuleType::Core(module_) = &module.module
-        else {
-            bail_with_site!("Cannot instantiate component")
+    /// Resolves an import.
+    ///
+    /// If `hint` is set, only the source with that name is tried.
+    fn resolve_import(
+        &mut self,
+        module: &ModuleData,
+        i: &ImportType<'_>,
+        hint: Option<&str>,
+    ) -> AnyResult<Option<(Extern, Cow<'static, str>)>> {
+        let config = self.config;
+        let allow = |s: &str| hint.map_or(true, |h| h == s);
+        let (module_name, name) = config.resolve_import(i.module(), i.name());
+
+        let mut src = Cow::Borrowed("host");
+        let mut v = match &mut self.host {
+            Some(v) if allow("host") => {
+                v.get_extern(&mut self.store, module_name, name, &i.ty())?
+            }
+            _ => None,
         };
 
-        let it = module_.imports().map(|i| {
-            let mut v = match &mut self.host {
-                Some(v) => v.get_extern(&mut self.store, i.module(), i.name())?,
-                None => None,
-            };
-
-            #[cfg(any(feature = "object-registry-compat", feature = "object-registry-extern"))]
-            if v.is_none() {
-                v = match (i.module(), &self.config) {
-                    #[cfg(feature = "object-registry-compat")]
-                    (
-                        OBJREGISTRY_MODULE,
-                        Config {
-                            extern_bind: ExternBindingType::Registry,
-                            ..
-                        },
-                    ) => self.objregistry_funcs.get_func(&mut self.store, i.name()),
-                    #[cfg(feature = "object-registry-extern")]
-                    (
-                        EXTERNREF_MODULE,
-                        Config {
-                            extern_bind: ExternBindingType::Native,
-                            ..
-                        },
-                    ) => self.externref_funcs.get_func(&mut self.store, i.name()),
-                    _ => None,
+        #[cfg(any(feature = "object-registry-compat", feature = "object-registry-extern"))]
+        if v.is_none() {
+            v = match (module_name, config) {
+                #[cfg(feature = "object-registry-compat")]
+                (
+                    OBJREGISTRY_MODULE,
+                    Config {
+                        extern_bind: ExternBindingType::Registry,
+                        ..
+                    },
+                ) if allow("objregistry") => {
+                    src = Cow::Borrowed("objregistry");
+                    self.objregistry_funcs.get_func(&mut self.store, name)
                 }
-                .map(|v| v.into());
-            }
-
-            #[cfg(feature = "wasi")]
-            if v.is_none() {
-                if let Some(l) = &self.wasi_linker {
-                    v = l.get_by_import(&mut self.store, &i);
+                #[cfg(feature = "object-registry-extern")]
+                (
+                    EXTERNREF_MODULE,
+                    Config {
+                        extern_bind: ExternBindingType::Native,
+                        ..
+                    },
+                ) if allow("externref") && config.extern_allowed(name) => {
+                    src = Cow::Borrowed("externref");
+                    self.externref_funcs.get_func(&mut self.store, name)
                 }
+                _ => None,
             }
+            .map(|v| v.into());
+        }
 
-            if v.is_none() {
-                if let Some(o) = module.imports.get(i.module()) {
-                    let id = o.instance_id();
-                    v = loop {
-                        match self.insts.entry(id) {
-                            Entry::Occupied(v) => match v.get() {
-                                Some(v) => break v.get_export(&mut self.store, i.name()),
-                                None => bail_with_site!("Recursive data structure"),
-                            },
-                            Entry::Vacant(v) => v.insert(None),
-                        };
-                        let t = self.instantiate_wasm(o.bind().get_data()?)?;
-                        self.insts.insert(id, Some(t));
-                    };
-                }
-            }
+        if v.is_none() && allow("time") && config.provide_time_import && module_name == ENV_MODULE {
+            src = Cow::Borrowed("time");
+            v = self
+                .time_funcs
+                .get_func(&mut self.store, name)
+                .map(|v| v.into());
+        }
+
+        if v.is_none() && allow("yield") && config.provide_yield_import && module_name == ENV_MODULE
+        {
+            src = Cow::Borrowed("yield");
+            v = self
+                .yield_funcs
+                .get_func(&mut self.store, name)
+                .map(|v| v.into());
+        }
+
+        if v.is_none() && allow("rand") && config.provide_rand_import && module_name == ENV_MODULE {
+            src = Cow::Borrowed("rand");
+            v = self
+                .rand_funcs
+                .get_func(&mut self.store, name)
+                .map(|v| v.into());
+        }
+
+        if v.is_none() && allow("event") && config.provide_event_import && module_name == ENV_MODULE
+        {
+            src = Cow::Borrowed("event");
+            v = self
+                .event_funcs
+                .get_func(&mut self.store, name)
+                .map(|v| v.into());
+        }
+
+        #[cfg(feature = "object-registry-compat")]
+        if v.is_none() && allow("objregistry") && config.command_buffer && module_name == ENV_MODULE
+        {
+            src = Cow::Borrowed("objregistry");
+            v = self
+                .command_funcs
+                .get_func(&mut self.store, name)
+                .map(|v| v.into());
+        }
+
+        if v.is_none() && allow("constants") && module_name == CONSTANTS_MODULE {
+            if let (Some(&c), ExternType::Global(ty)) = (config.constants.get(name), i.ty()) {
+                let val = match ty.content() {
+                    ValType::I32 => Val::I32(c as _),
+                    ValType::I64 => Val::I64(c),
+                    t => bail_with_site!("Constant {name} cannot be imported as {t}"),
+                };
+                if ty.mutability() == Mutability::Var {
+                    bail_with_site!("Constant {name} cannot be imported as mutable global");
+                }
+                src = Cow::Borrowed("constants");
+                v = Some(Global::new(&mut self.store, ty, val)?.into());
+            }
+        }
+
+        #[cfg(feature = "epoch-timeout")]
+        if v.is_none() && allow("epoch") && config.provide_epoch_import && module_name == ENV_MODULE
+        {
+            src = Cow::Borrowed("epoch");
+            v = self
+                .epoch_funcs
+                .get_func(&mut self.store, name)
+                .map(|v| v.into());
+        }
+
+        #[cfg(feature = "wasi")]
+        if v.is_none() && allow("wasi") {
+            if let Some(l) = &self.wasi_linker {
+                src = Cow::Borrowed("wasi");
+                v = l.get(&mut self.store, module_name, name);
+            }
+        }
+
+        if v.is_none() && hint.map_or(true, |h| h.starts_with("module:")) {
+            if let Some(o) = module.imports.get(module_name) {
+                let id = o.instance_id();
+                src = Cow::Owned(format!("module:{}", id.to_i64()));
+                v = loop {
+                    match self.insts.entry(id) {
+                        Entry::Occupied(v) => match v.get() {
+                            Some(v) => break v.get_export(&mut self.store, name),
+                            None => bail_with_site!("Recursive data structure"),
+                        },
+                        Entry::Vacant(v) => v.insert(None),
+                    };
+                    let t = self.instantiate_wasm(o.bind().get_data()?)?;
+                    self.insts.insert(id, Some(t));
+                };
+            }
+        }
+
+        // Only if no module dependency provides it.
+        if v.is_none() && allow("memory") && config.provide_memory_import {
+            if let ExternType::Memory(ty) = i.ty() {
+                let mem = match self.host_memory {
+                    Some(mem) => mem,
+                    None => {
+                        if ty.is_shared() {
+                            bail_with_site!(
+                                "Shared memory import {module_name}.{name} is not supported"
+                            );
+                        }
+                        let min = config.memory_import_min.unwrap_or(ty.minimum());
+                        let max = config.memory_import_max.or(ty.maximum());
+                        let ty = if ty.is_64() {
+                            wasmtime::MemoryType::new64(min, max)
+                        } else {
+                            wasmtime::MemoryType::new(
+                                min.min(u32::MAX as _) as _,
+                                max.map(|v| v.min(u32::MAX as _) as _),
+                            )
+                        };
+                        let mem = Memory::new(&mut self.store, ty)?;
+                        self.host_memory = Some(mem);
+                        mem
+                    }
+                };
+                src = Cow::Borrowed("memory");
+                v = Some(mem.into());
+            }
+        }
+
+        if v.is_none() && allow("fallback") {
+            if let (Some(resolver), ExternType::Func(ty)) =
+                (&config.fallback_import_resolver, i.ty())
+            {
+                src = Cow::Borrowed("fallback");
+                v = resolve_fallback_import(&mut self.store, resolver, module_name, name, &ty)?
+                    .map(|v| v.into());
+            }
+        }
+
+        Ok(v.map(|v| (v, src)))
+    }
+
+    fn instantiate_wasm(&mut self, module: &ModuleData) -> AnyResult<InstanceWasm> {
+        let (module, imports) = self.resolve_imports(module)?;
+        InstanceWasm::new(&mut self.store, &module, &imports)
+    }
+
+    /// Resolves all imports of core module.
+    fn resolve_imports(&mut self, module: &ModuleData) -> AnyResult<(Module, Vec<Extern>)> {
+        #[allow(irrefutable_let_patterns)]
+        let ModuleType::Core(module_) = &module.module
+        else {
+            #[cfg(feature = "component-model")]
+            if let ModuleType::Component(c) = &module.module {
+                let engine = c.engine();
+                if c.component_type()
+                    .imports(engine)
+                    .any(|(n, _)| n.starts_with("wasi:"))
+                {
+                    if cfg!(feature = "wasi-preview2") {
+                        bail_with_site!("Module needs WASI preview2; use WasiCommand instead")
+                    } else {
+                        bail_with_site!(
+                            "Module needs WASI preview2; enable wasi-preview2 feature and use WasiCommand"
+                        )
+                    }
+                }
+            }
+            bail_with_site!("Cannot instantiate component")
+        };
+
+        let config = self.config;
+        // Only used by the outermost module.
+        let plan = self.plan.take();
+        let it = module_.imports().enumerate().map(|(ix, i)| {
+            let hint = plan.as_ref().and_then(|p| p.get(ix)).map(|v| &**v);
+            let mut v = self.resolve_import(module, &i, hint)?;
+            if v.is_none() && hint.is_some() {
+                // Plan does not match, fallback to full resolution.
+                v = self.resolve_import(module, &i, None)?;
+            }
 
             match v {
                 Some(v) => Ok(v),
-                None => bail_with_site!("Unknown import {:?}.{:?}", i.module(), i.name()),
+                None => {
+                    if let Some(msg) = wasi_mismatch(config, i.module()) {
+                        bail_with_site!("{msg} (import {:?}.{:?})", i.module(), i.name())
+                    }
+                    bail_with_site!("Unknown import {:?}.{:?}", i.module(), i.name())
+                }
             }
         });
-        let imports = it.collect::<AnyResult<Vec<_>>>()?;
+        let (imports, sources): (Vec<_>, Vec<_>) =
+            it.collect::<AnyResult<Vec<_>>>()?.into_iter().unzip();
+
+        self.import_bindings = module_
+            .imports()
+            .zip(sources)
+            .map(|(i, s)| (i.module().to_string(), i.name().to_string(), s))
+            .collect();
+        if self.main_thread_import.is_none() {
+            self.main_thread_import = self
+                .import_bindings
+                .iter()
+                .find(|(_, _, s)| !is_thread_safe_source(s))
+                .map(|(m, n, s)| format!("{m:?}.{n:?} (from {s})"));
+        }
+
+        self.memory_imports = module_
+            .imports()
+            .zip(&imports)
+            .filter(|(i, _)| matches!(i.ty(), ExternType::Memory(_)))
+            .map(|(i, v)| (format!("{}.{}", i.module(), i.name()), v.clone()))
+            .collect();
+
+        Ok((module_.clone(), imports))
+    }
+}
+
+/// Explains unresolved import caused by mismatched WASI version.
+fn wasi_mismatch(_config: &Config, module: &str) -> Option<&'static str> {
+    match module {
+        "wasi_snapshot_preview1" | "wasi_unstable" => {
+            cfg_if! {
+                if #[cfg(feature = "wasi")] {
+                    if _config.with_wasi {
+                        None
+                    } else {
+                        Some("Module needs WASI preview1; enable wasi.enable config")
+                    }
+                } else {
+                    Some("Module needs WASI preview1; enable wasi feature")
+                }
+            }
+        }
+        m if m.starts_with("wasi:") => {
+            Some("Module needs WASI preview2, which is only available to components")
+        }
+        _ => None,
+    }
+}
 
-        InstanceWasm::new(&mut self.store, module_, &imports)
+impl<T> InstanceData<T> {
+    /// Gets memory import provided by host (see `memory.provideImport` config).
+    pub fn host_memory_import(&self) -> Option<Extern> {
+        let (module, name, _) = self
+            .import_bindings
+            .iter()
+            .find(|(_, _, s)| s == "memory")?;
+        self.memory_imports
+            .get(&format!("{module}.{name}"))
+            .cloned()
+    }
+
+    /// Gets export of core instance, using export cache if available.
+    pub fn get_core_export(
+        &self,
+        store: impl AsContextMut<Data = T>,
+        name: &str,
+    ) -> AnyResult<Option<Extern>> {
+        if let Some(cache) = &self.export_cache {
+            return Ok(cache.get(name).cloned());
+        }
+        Ok(site_context!(self.instance.get_core())?.get_export(store, name))
     }
 }
 
@@ -607,6 +1654,26 @@ where
 
         f(self, guard_.as_context_mut())
     }
+
+    /// Like `acquire_store`, but returns `None` instead of waiting if the store is in use.
+    pub fn try_acquire_store<F, R>(&self, f: F) -> Option<R>
+    where
+        for<'a> F: FnOnce(&Self, StoreContextMut<'a, T>) -> R,
+    {
+        let mut guard_ = self.store.try_lock()?;
+
+        let _scope;
+        // SAFETY: Context should be destroyed after function call
+        unsafe {
+            let p = &mut guard_.data_mut().as_mut().mutex_raw;
+            let v = mem::replace(p, self.store.raw() as *const _);
+            _scope = guard(p as *mut _, move |p| {
+                *p = v;
+            });
+        }
+
+        Some(f(self, guard_.as_context_mut()))
+    }
 }
 
 impl InnerLock {
@@ -636,6 +1703,40 @@ impl StoreData {
         self.inner_lock.release_store(f)
     }
 
+    /// Runs host call with store released, enforcing `host.maxReentry`.
+    pub(crate) fn host_call<F, R>(&mut self, f: F) -> AnyResult<R>
+    where
+        F: FnOnce() -> AnyResult<R>,
+    {
+        if let Some(max) = self.max_host_reentry {
+            if self.host_depth >= max {
+                bail_with_site!("Host call reentry limit exceeded (maximum {max})")
+            }
+        }
+
+        self.host_depth += 1;
+        let r = self.release_store(f);
+        self.host_depth -= 1;
+        r
+    }
+
+    /// Gets number of epoch ticks until the next deadline should be checked.
+    #[cfg(feature = "epoch-timeout")]
+    pub fn next_deadline(&self, now: u64) -> u64 {
+        if self.interrupt.is_some() {
+            return 1;
+        }
+        [
+            (self.epoch_timeout > 0).then_some(self.epoch_deadline),
+            self.budget_deadline,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|v| v.saturating_sub(now).max(1))
+        .min()
+        .unwrap_or(EPOCH_DEADLINE)
+    }
+
     #[cfg(feature = "object-registry-compat")]
     pub fn get_registry(&self) -> AnyResult<&ObjectRegistry> {
         match self.object_registry.as_ref() {
@@ -662,22 +1763,144 @@ impl WasmInstance {
             .emit_signal(StringName::from(c"error_happened"), &args);
     }
 
-    pub fn get_data(&self) -> AnyResult<&InstanceData<StoreData>> {
-        if let Some(data) = self.data.get() {
-            Ok(data)
+    /// Runs trap handler, returns `true` if default logging should be suppressed.
+    fn run_trap_handler(&self, e: &anyhow::Error) -> bool {
+        if e.downcast_ref::<Trap>().is_none() && e.downcast_ref::<HostTrap>().is_none() {
+            return false;
+        }
+        // Clone handler, so it can reset itself.
+        let Some(f) = self.trap_handler.lock().clone() else {
+            return false;
+        };
+
+        let bt = e
+            .downcast_ref::<WasmBacktrace>()
+            .map_or_else(String::new, |v| v.to_string());
+        let args = [
+            GString::from(e.root_cause().to_string()).to_variant(),
+            GString::from(bt).to_variant(),
+        ];
+        f.callv(args.into_iter().collect())
+            .try_to::<bool>()
+            .unwrap_or_default()
+    }
+
+    /// Re-syncs state derived from active memory, after it changes.
+    ///
+    /// Watches are rehashed, so that switching memory does not count as a change.
+    /// Watches outside of the new memory are removed.
+    fn resync_memory_state(&self) {
+        self.get_memory_and_data(|data, store| {
+            for w in store.memory_watches.iter_mut() {
+                if w.as_mut().is_some_and(|v| !v.resync(data)) {
+                    *w = None;
+                }
+            }
+            Ok(())
+        });
+    }
+
+    /// Runs memory grow callback for each growth since last run.
+    ///
+    /// If `blocking` is false and the store is in use, growths are reported on a later call.
+    fn run_memory_grow_callback(&self, blocking: bool) {
+        let Some(f) = self.memory_grow_callback.lock().clone() else {
+            return;
+        };
+        let Ok(data) = self.get_data() else {
+            return;
+        };
+        let take = |_: &InstanceData<StoreData>, mut store: StoreContextMut<'_, StoreData>| {
+            store
+                .data_mut()
+                .memory_limits
+                .grown_pages
+                .as_mut()
+                .map(mem::take)
+                .unwrap_or_default()
+        };
+        let grown = if blocking {
+            data.acquire_store(take)
         } else {
-            bail_with_site!("Uninitialized instance")
+            data.try_acquire_store(take).unwrap_or_default()
+        };
+        drop(data);
+
+        for pages in grown {
+            f.callv([(pages as i64).to_variant()].into_iter().collect());
+        }
+    }
+
+    /// Emits `memory_exhausted` signal if memory is exhausted since last check.
+    fn check_memory_exhausted(&self) {
+        let Ok(data) = self.get_data() else {
+            return;
+        };
+        let exhausted = data.memory_exhausted.swap(false, Ordering::AcqRel);
+        drop(data);
+        if exhausted {
+            self.to_gd()
+                .emit_signal(StringName::from(c"memory_exhausted"), &[]);
+        }
+    }
+
+    fn emit_error(&self, e: anyhow::Error) {
+        let s = format!("{e:?}");
+        if !self.run_trap_handler(&e) {
+            godot_error!("{s}");
+        }
+        if let Some(HostTrap {
+            msg,
+            code: Some(code),
+        }) = e.downcast_ref::<HostTrap>()
+        {
+            let args = [GString::from(msg.as_str()).to_variant(), code.to_variant()];
+            self.base()
+                .clone()
+                .emit_signal(StringName::from(c"error_code_happened"), &args);
+        }
+        if let Some(&trap) = e.downcast_ref::<Trap>() {
+            let (code, msg) = trap_info(trap);
+            let msg = self.base().tr(StringName::from(msg));
+            let args = [GString::from(code).to_variant(), msg.to_variant()];
+            self.base()
+                .clone()
+                .emit_signal(StringName::from(c"trap_happened"), &args);
+        }
+        self.emit_error_wrapper(s);
+    }
+
+    pub fn get_data(&self) -> AnyResult<MappedRwLockReadGuard<'_, InstanceData<StoreData>>> {
+        // Recursive, as host functions may call back into the instance.
+        match RwLockReadGuard::try_map(self.data.read_recursive(), Option::as_ref) {
+            Ok(v) => Ok(v),
+            Err(_) => bail_with_site!("Uninitialized instance"),
         }
     }
 
+    /// Gets memory of instance data.
+    fn memory(&self) -> Option<MemoryType> {
+        self.memory.read().clone()
+    }
+
     pub fn unwrap_data<F, R>(&self, f: F) -> Option<R>
     where
         F: FnOnce(&InstanceData<StoreData>) -> AnyResult<R>,
     {
-        match self.get_data().and_then(f) {
+        self.unwrap_data_(f, true)
+    }
+
+    /// Like `unwrap_data`, but never waits for the store afterwards if `blocking` is false.
+    fn unwrap_data_<F, R>(&self, f: F, blocking: bool) -> Option<R>
+    where
+        F: FnOnce(&InstanceData<StoreData>) -> AnyResult<R>,
+    {
+        let r = self.get_data().and_then(|m| f(&m));
+        self.run_memory_grow_callback(blocking);
+        self.check_memory_exhausted();
+        match r {
             Ok(v) => Some(v),
             Err(e) => {
-                let s = format!("{e:?}");
                 /*
                 error(
                     e.downcast_ref::<Site>()
@@ -686,90 +1909,259 @@ impl WasmInstance {
                     &s,
                 );
                 */
-                godot_error!("{s}");
-                self.emit_error_wrapper(s);
+                self.emit_error(e);
                 None
             }
         }
     }
 
+    /// Calls exported function by name.
+    ///
+    /// `f` is called with the function and its type, and does the actual call (usually with [`call_func`]).
+    /// If `blocking` is false, returns `Some(None)` instead of waiting if the store is in use.
+    fn call_export_with<F, R>(&self, name: &str, blocking: bool, f: F) -> Option<Option<R>>
+    where
+        for<'a> F: FnOnce(
+            &InstanceData<StoreData>,
+            StoreContextMut<'a, StoreData>,
+            Func,
+            FuncType,
+        ) -> AnyResult<R>,
+    {
+        let run = |m: &InstanceData<StoreData>, mut store: StoreContextMut<'_, StoreData>| {
+            let (func, ty) = get_export_func(m, &mut store, name)?;
+            f(m, store, func, ty)
+        };
+        self.unwrap_data_(
+            |m| {
+                if blocking {
+                    m.acquire_store(run).map(Some)
+                } else {
+                    m.try_acquire_store(run).transpose()
+                }
+            },
+            blocking,
+        )
+    }
+
     pub fn initialize_(
         &self,
         module: Gd<WasmModule>,
         host: Option<Dictionary>,
         config: Option<Variant>,
+        plan: Option<ImportPlan>,
     ) -> bool {
-        let r = self.data.get_or_try_init(move || -> AnyResult<_> {
-            let mut ret = InstanceData::instantiate(
-                self.base().instance_id(),
-                Store::new(&site_context!(get_engine())?, StoreData::default()),
-                &match config {
-                    Some(v) => match Config::try_from_variant(&v) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            godot_error!("{:?}", e);
-                            Config::default()
-                        }
-                    },
-                    None => Config::default(),
-                },
-                module,
-                host,
-            )?;
+        let r = self.instantiate_(module, host, config, plan);
+        self.finish_initialize(r)
+    }
 
-            // SAFETY: Nobody else can access memory
-            unsafe {
-                *(ptr::addr_of!(self.memory) as *mut Option<MemoryType>) = match &ret.instance {
-                    InstanceType::Core(inst) => {
-                        match inst.get_export(ret.store.get_mut(), MEMORY_EXPORT) {
-                            Some(Extern::Memory(mem)) => Some(MemoryType::Memory(mem)),
-                            #[cfg(feature = "wasm-threads")]
-                            Some(Extern::SharedMemory(mem)) => Some(MemoryType::SharedMemory(mem)),
-                            _ => None,
-                        }
-                    }
-                    #[allow(unreachable_patterns)]
+    /// Instantiates module. Returns `true` if it's newly instantiated.
+    fn instantiate_(
+        &self,
+        module: Gd<WasmModule>,
+        host: Option<Dictionary>,
+        config: Option<Variant>,
+        plan: Option<ImportPlan>,
+    ) -> AnyResult<bool> {
+        self.init_data(host.clone(), config.clone(), || {
+            self.create_data(module, host, config, plan)
+        })
+    }
+
+    /// Sets instance data if not yet initialized. Returns `true` if it is initialized.
+    fn init_data(
+        &self,
+        host: Option<Dictionary>,
+        config: Option<Variant>,
+        f: impl FnOnce() -> AnyResult<(InstanceData<StoreData>, Option<MemoryType>)>,
+    ) -> AnyResult<bool> {
+        // Lock is held to serialize initialization.
+        // Data lock is not held, so start function can call back into the instance.
+        let mut args = self.init_args.lock();
+        if self.data.read().is_some() {
+            return Ok(false);
+        }
+        let (ret, memory) = f()?;
+        *args = Some((host, config));
+
+        let mut data = self.data.write();
+        *self.memory.write() = memory;
+        *data = Some(ret);
+        Ok(true)
+    }
+
+    fn parse_config(config: Option<Variant>) -> Config {
+        match config {
+            Some(v) => match Config::try_from_variant(&v) {
+                Ok(v) => v,
+                Err(e) => {
+                    godot_error!("{:?}", e);
+                    Config::default()
+                }
+            },
+            None => Config::default(),
+        }
+    }
+
+    /// Instantiates module in a new store, along with its memory.
+    fn create_data(
+        &self,
+        module: Gd<WasmModule>,
+        host: Option<Dictionary>,
+        config: Option<Variant>,
+        plan: Option<ImportPlan>,
+    ) -> AnyResult<(InstanceData<StoreData>, Option<MemoryType>)> {
+        let ret = InstanceData::instantiate(
+            self.base().instance_id(),
+            Store::new(&site_context!(get_engine())?, StoreData::default()),
+            &Self::parse_config(config),
+            module,
+            host,
+            plan,
+        )?;
+        Ok(Self::with_memory(ret))
+    }
+
+    /// Resolves imports of module in a new store, without instantiating it.
+    fn prepare_data(
+        &self,
+        module: &Gd<WasmModule>,
+        host: Option<Dictionary>,
+        config: Option<Variant>,
+    ) -> AnyResult<PreparedInstance<StoreData>> {
+        InstanceData::prepare(
+            self.base().instance_id(),
+            Store::new(&site_context!(get_engine())?, StoreData::default()),
+            &Self::parse_config(config),
+            module,
+            host,
+            None,
+        )
+    }
+
+    /// Pairs instance data with its memory.
+    fn with_memory(
+        mut ret: InstanceData<StoreData>,
+    ) -> (InstanceData<StoreData>, Option<MemoryType>) {
+        let memory = match &ret.instance {
+            InstanceType::Core(inst) => {
+                match inst
+                    .get_export(ret.store.get_mut(), MEMORY_EXPORT)
+                    .or_else(|| ret.host_memory_import())
+                {
+                    Some(Extern::Memory(mem)) => Some(MemoryType::Memory(mem)),
+                    #[cfg(feature = "wasm-threads")]
+                    Some(Extern::SharedMemory(mem)) => Some(MemoryType::SharedMemory(mem)),
                     _ => None,
-                };
+                }
+            }
+            #[allow(unreachable_patterns)]
+            _ => None,
+        };
+        (ret, memory)
+    }
+
+    /// Emits signals of instantiation result.
+    fn finish_initialize(&self, r: AnyResult<bool>) -> bool {
+        match r.and_then(|is_init| Ok((is_init, self.get_data()?))) {
+            Err(e) => {
+                self.emit_error(e);
+                false
+            }
+            Ok((is_init, m)) => {
+                if is_init {
+                    #[allow(unused_mut)]
+                    let mut wasi = false;
+                    #[cfg(feature = "wasi")]
+                    {
+                        wasi = !matches!(m.store.lock().data().wasi_ctx, MaybeWasi::NoCtx);
+                    }
+
+                    let args = [m.module.to_variant(), wasi.to_variant()];
+                    // Signal handler may reload the instance.
+                    drop(m);
+                    self.base()
+                        .clone()
+                        .emit_signal(StringName::from(c"instantiated"), &args);
+                }
+                true
             }
-            Ok(ret)
-        });
-        if let Err(e) = r {
-            let s = format!("{e:?}");
-            godot_error!("{s}");
-            self.emit_error_wrapper(s);
-            false
-        } else {
-            true
         }
     }
 
+    fn signal_error_(&self, msg: GString, code: Option<i64>) -> Variant {
+        option_to_variant(self.unwrap_data(|m| {
+            m.acquire_store(|_, mut store| {
+                let v = HostTrap {
+                    msg: msg.to_string(),
+                    code,
+                };
+                Ok(store
+                    .data_mut()
+                    .error_signal
+                    .replace(v)
+                    .map(|v| v.msg)
+                    .unwrap_or_default())
+            })
+        }))
+    }
+
     fn get_memory<F, R>(&self, f: F) -> Option<R>
     where
         for<'a> F: FnOnce(&'a mut [u8]) -> AnyResult<R>,
     {
-        self.unwrap_data(|m| {
-            m.acquire_store(|_, store| match &self.memory {
-                Some(MemoryType::Memory(mem)) => f(mem.data_mut(store)),
-                #[cfg(feature = "wasm-threads")]
-                Some(MemoryType::SharedMemory(mem)) => {
-                    // SAFETY: Externalize concurrent access to user
-                    #[allow(mutable_transmutes)]
-                    let s = unsafe { mem::transmute::<&[UnsafeCell<u8>], &mut [u8]>(mem.data()) };
-                    f(s)
+        self.get_memory_and_data(|data, _| f(data))
+    }
+
+    fn get_memory_and_data<F, R>(&self, f: F) -> Option<R>
+    where
+        for<'a> F: FnOnce(&'a mut [u8], &'a mut StoreData) -> AnyResult<R>,
+    {
+        self.unwrap_data(|m| m.acquire_store(|_, store| self.with_store_memory(store, f)))
+            .flatten()
+    }
+
+    /// Like `get_memory_and_data`, but with store already acquired.
+    fn with_store_memory<F, R>(
+        &self,
+        store: StoreContextMut<'_, StoreData>,
+        f: F,
+    ) -> AnyResult<Option<R>>
+    where
+        for<'a> F: FnOnce(&'a mut [u8], &'a mut StoreData) -> AnyResult<R>,
+    {
+        // Cloned, so the lock is not held while running `f`.
+        match &self.memory() {
+            Some(MemoryType::Memory(mem)) => {
+                let (data, store) = mem.data_and_store_mut(store);
+                f(data, store).map(Some)
+            }
+            #[cfg(feature = "wasm-threads")]
+            Some(MemoryType::SharedMemory(mem)) => {
+                // SAFETY: Externalize concurrent access to user
+                #[allow(mutable_transmutes)]
+                let s = unsafe { mem::transmute::<&[UnsafeCell<u8>], &mut [u8]>(mem.data()) };
+                let mut store = store;
+                f(s, store.data_mut()).map(Some)
+            }
+            None => {
+                // Warn only once, instead of erroring on every call.
+                if !self.memory_warned.swap(true, Ordering::Relaxed) {
+                    godot_warn!("No memory exported, memory functions will do nothing");
                 }
-                None => bail_with_site!("No memory exported"),
-            })
-        })
+                Ok(None)
+            }
+        }
     }
 
     fn read_memory<F, R>(&self, i: usize, n: usize, f: F) -> Option<R>
     where
         F: FnOnce(&[u8]) -> AnyResult<R>,
     {
-        self.get_memory(|data| match data.get(i..i + n) {
-            Some(s) => f(s),
-            None => bail_with_site!("Index out of bound {}-{}", i, i + n),
+        self.get_memory_and_data(|data, store| {
+            let s = memory_slice(data, i, n)?;
+            MemoryStats::record(&mut store.memory_stats, n, false);
+            f(s)
         })
     }
 
@@ -777,18 +2169,398 @@ impl WasmInstance {
     where
         for<'a> F: FnOnce(&'a mut [u8]) -> AnyResult<R>,
     {
-        self.get_memory(|data| match data.get_mut(i..i + n) {
-            Some(s) => f(s),
-            None => bail_with_site!("Index out of bound {}-{}", i, i + n),
+        self.get_memory_and_data(|data, store| {
+            let s = memory_slice_mut(data, i, n)?;
+            MemoryStats::record(&mut store.memory_stats, n, true);
+            f(s)
         })
     }
 }
 
-struct WasmCallable {
-    name: StringName,
-    ty: FuncType,
-    f: Func,
-    this: SendSyncWrapper<Gd<WasmInstance>>,
+/// Reads Godot file, for stdin.
+#[cfg(feature = "wasi")]
+struct FileReader(SendSyncWrapper<Gd<FileAccess>>);
+
+#[cfg(feature = "wasi")]
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let v = self.0.get_buffer(buf.len() as i64);
+        let v = v.as_slice();
+        buf[..v.len()].copy_from_slice(v);
+        Ok(v.len())
+    }
+}
+
+/// Copies host data blob into memory, at address of exported global `symbol`.
+fn write_host_data<T>(
+    mut store: impl AsContextMut<Data = T>,
+    instance: &InstanceWasm,
+    mem: Option<Memory>,
+    symbol: &str,
+    blob: &[u8],
+) -> AnyResult<()> {
+    let Some(mem) = mem else {
+        bail_with_site!("Cannot write host data, module has no memory")
+    };
+    let Some(g) = instance.get_global(&mut store, symbol) else {
+        bail_with_site!("Host data symbol {symbol} is not an exported global")
+    };
+    let p = match g.get(&mut store) {
+        Val::I32(v) => v as u32 as usize,
+        Val::I64(v) => v as usize,
+        v => bail_with_site!(
+            "Host data symbol {symbol} is not an address ({:?})",
+            v.ty(&store)
+        ),
+    };
+
+    match p
+        .checked_add(blob.len())
+        .and_then(|e| mem.data_mut(&mut store).get_mut(p..e))
+    {
+        Some(s) => s.copy_from_slice(blob),
+        None => bail_with_site!("Index out of bound {}-{}", p, p.wrapping_add(blob.len())),
+    }
+    Ok(())
+}
+
+/// Gets trap code name and human-readable description.
+fn trap_info(trap: Trap) -> (&'static str, &'static str) {
+    match trap {
+        Trap::StackOverflow => ("stack_overflow", "Call stack exhausted"),
+        Trap::MemoryOutOfBounds => ("memory_out_of_bounds", "Out of bounds memory access"),
+        Trap::HeapMisaligned => ("heap_misaligned", "Misaligned memory access"),
+        Trap::TableOutOfBounds => ("table_out_of_bounds", "Out of bounds table access"),
+        Trap::IndirectCallToNull => ("indirect_call_to_null", "Indirect call to null function"),
+        Trap::BadSignature => ("bad_signature", "Indirect call type mismatch"),
+        Trap::IntegerOverflow => ("integer_overflow", "Integer overflow"),
+        Trap::IntegerDivisionByZero => ("integer_division_by_zero", "Integer divide by zero"),
+        Trap::BadConversionToInteger => {
+            ("bad_conversion_to_integer", "Invalid conversion to integer")
+        }
+        Trap::UnreachableCodeReached => ("unreachable", "Unreachable code executed"),
+        Trap::Interrupt => ("interrupt", "Execution interrupted (timeout)"),
+        Trap::OutOfFuel => ("out_of_fuel", "Out of fuel"),
+        Trap::NullReference => ("null_reference", "Null reference"),
+        _ => ("unknown", "Unknown trap"),
+    }
+}
+
+/// Converts `i32` results into registry objects.
+#[cfg(feature = "object-registry-compat")]
+fn unwrap_registry_results(
+    data: &StoreData,
+    ty: &FuncType,
+    r: VariantArray,
+) -> AnyResult<VariantArray> {
+    let reg = data.get_registry()?;
+    Ok(ty
+        .results()
+        .zip(r.iter_shared())
+        .map(|(t, v)| match (t, v.try_to::<i64>()) {
+            (ValType::I32, Ok(i)) => reg.get_or_nil(i as u32 as _),
+            _ => v,
+        })
+        .collect())
+}
+
+/// Gets exported function and its type by name.
+fn get_export_func(
+    m: &InstanceData<StoreData>,
+    mut store: impl AsContextMut<Data = StoreData>,
+    name: &str,
+) -> AnyResult<(Func, FuncType)> {
+    match m.get_core_export(&mut store, name)? {
+        Some(Extern::Func(f)) => {
+            let ty = f.ty(&store);
+            Ok((f, ty))
+        }
+        Some(_) => bail_with_site!("Export {name} is not a function"),
+        None => bail_with_site!("Export {name} does not exists"),
+    }
+}
+
+/// Calls exported function by name.
+fn call_export<It>(
+    m: &InstanceData<StoreData>,
+    mut store: StoreContextMut<'_, StoreData>,
+    name: &str,
+    args: It,
+) -> AnyResult<VariantArray>
+where
+    It: IntoIterator,
+    It::Item: Borrow<Variant>,
+{
+    let (f, ty) = get_export_func(m, &mut store, name)?;
+    call_func(store, name, &f, &ty, args)
+}
+
+/// Calls function, setting epoch deadline if configured.
+fn call_func<It>(
+    mut store: StoreContextMut<'_, StoreData>,
+    name: &str,
+    f: &Func,
+    ty: &FuncType,
+    args: It,
+) -> AnyResult<VariantArray>
+where
+    It: IntoIterator,
+    It::Item: Borrow<Variant>,
+{
+    #[cfg(feature = "epoch-timeout")]
+    if let v @ 1.. = store.data().epoch_timeout {
+        set_epoch_deadline(&mut store, v);
+    }
+
+    unsafe { profiled_call(store, name, f, ty, args) }
+}
+
+/// Converts pointer or length result into `usize`.
+///
+/// `i32` is treated as unsigned, negative `i64` is rejected.
+fn result_to_usize(t: &ValType, v: &Variant) -> AnyResult<usize> {
+    match t {
+        ValType::I32 => Ok(site_context!(v.try_to::<i32>())? as u32 as usize),
+        _ => match site_context!(v.try_to::<i64>())? {
+            v @ 0.. => Ok(v as usize),
+            v => bail_with_site!("Negative pointer or length {v}"),
+        },
+    }
+}
+
+/// Bytes per pixel of uncompressed image format.
+fn image_pixel_size(format: ImageFormat) -> Option<usize> {
+    Some(match format {
+        ImageFormat::L8 | ImageFormat::R8 => 1,
+        ImageFormat::LA8
+        | ImageFormat::RG8
+        | ImageFormat::RGBA4444
+        | ImageFormat::RGB565
+        | ImageFormat::RH => 2,
+        ImageFormat::RGB8 => 3,
+        ImageFormat::RGBA8 | ImageFormat::RF | ImageFormat::RGH | ImageFormat::RGBE9995 => 4,
+        ImageFormat::RGBH => 6,
+        ImageFormat::RGF | ImageFormat::RGBAH => 8,
+        ImageFormat::RGBF => 12,
+        ImageFormat::RGBAF => 16,
+        _ => return None,
+    })
+}
+
+/// Size of raw image data, including mipmaps down to 1x1.
+fn image_data_size(width: usize, height: usize, mipmaps: bool, pixel: usize) -> Option<usize> {
+    let (mut w, mut h) = (width, height);
+    let mut n = w.checked_mul(h)?;
+    while mipmaps && (w > 1 || h > 1) {
+        w = (w / 2).max(1);
+        h = (h / 2).max(1);
+        n = n.checked_add(w * h)?;
+    }
+    n.checked_mul(pixel)
+}
+
+/// Converts `i32` results into registry objects, if `extern.autoUnwrapRegistry` is enabled.
+fn auto_unwrap_results(
+    _data: &StoreData,
+    _ty: &FuncType,
+    r: VariantArray,
+) -> AnyResult<VariantArray> {
+    #[cfg(feature = "object-registry-compat")]
+    if _data.auto_unwrap_registry {
+        return unwrap_registry_results(_data, _ty, r);
+    }
+    Ok(r)
+}
+
+/// Gets bytes of AssemblyScript string at `p`.
+///
+/// Byte length is stored as 32-bit integer right before the string.
+fn as_string_bytes(data: &[u8], p: i64) -> AnyResult<&[u8]> {
+    let Some(h) = usize::try_from(p).ok().and_then(|p| p.checked_sub(4)) else {
+        bail_with_site!("Invalid string pointer {p}")
+    };
+    let Some(&[a, b, c, d]) = data.get(h..h + 4) else {
+        bail_with_site!("Index out of bound {}-{}", h, h + 4)
+    };
+    let n = i32::from_le_bytes([a, b, c, d]);
+    let i = h + 4;
+    let s = usize::try_from(n)
+        .ok()
+        .and_then(|n| data.get(i..i.checked_add(n)?));
+    match s {
+        Some(s) => Ok(s),
+        None => bail_with_site!("Invalid string length {n} at {i}"),
+    }
+}
+
+/// Unpacks `n` bits starting at bit `shift`, one byte (0 or 1) per bit.
+fn unpack_bits(s: &[u8], shift: usize, n: usize) -> Vec<u8> {
+    s.iter()
+        .flat_map(|&v| (0..8).map(move |b| (v >> b) & 1))
+        .skip(shift)
+        .take(n)
+        .collect()
+}
+
+/// Packs bits starting at bit `shift`, one byte per bit (nonzero is set).
+///
+/// Other bits are preserved.
+fn pack_bits(s: &mut [u8], shift: usize, a: &[u8]) {
+    for (j, &v) in a.iter().enumerate() {
+        let b = j + shift;
+        let m = 1u8 << (b % 8);
+        if v != 0 {
+            s[b / 8] |= m;
+        } else {
+            s[b / 8] &= !m;
+        }
+    }
+}
+
+/// Converts fuel budget into fuel limit of a call with multiplier `mult`.
+#[cfg(feature = "fuel")]
+fn fuel_limit(budget: Option<u64>, mult: f64) -> u64 {
+    match budget {
+        Some(v) if mult > 0.0 => (v as f64 / mult) as u64,
+        _ => u64::MAX,
+    }
+}
+
+/// Runs WASM call `f`, enforcing `call.maxDepth`.
+fn nested_call<T, R, F>(mut store: StoreContextMut<'_, T>, f: F) -> AnyResult<R>
+where
+    T: AsMut<StoreData>,
+    F: FnOnce(StoreContextMut<'_, T>) -> AnyResult<R>,
+{
+    let data = store.data_mut().as_mut();
+    if let Some(max) = data.max_call_depth {
+        if data.call_depth >= max {
+            bail_with_site!("Call depth limit exceeded (maximum {max})")
+        }
+    }
+
+    data.call_depth += 1;
+    let r = f(store.as_context_mut());
+    store.data_mut().as_mut().call_depth -= 1;
+    r
+}
+
+/// Calls into WASM, recording call time if profiling is enabled.
+unsafe fn profiled_call<It>(
+    store: StoreContextMut<'_, StoreData>,
+    name: &str,
+    f: &Func,
+    ty: &FuncType,
+    args: It,
+) -> AnyResult<VariantArray>
+where
+    It: IntoIterator,
+    It::Item: Borrow<Variant>,
+{
+    nested_call(store, |store| profiled_call_(store, name, f, ty, args))
+}
+
+unsafe fn profiled_call_<It>(
+    mut store: StoreContextMut<'_, StoreData>,
+    name: &str,
+    f: &Func,
+    ty: &FuncType,
+    args: It,
+) -> AnyResult<VariantArray>
+where
+    It: IntoIterator,
+    It::Item: Borrow<Variant>,
+{
+    // Nested calls are charged with the multiplier of outermost call.
+    #[cfg(feature = "fuel")]
+    let fuel = if store.data().call_depth == 1 {
+        let data = store.data_mut();
+        let mult = data.fuel_multipliers.get(name).copied().unwrap_or(1.0);
+        let limit = fuel_limit(data.fuel_budget, mult);
+        store.set_fuel(limit)?;
+        Some((limit, mult))
+    } else {
+        None
+    };
+    store.data_mut().yield_requested = false;
+    #[cfg(feature = "wasi")]
+    if let Some(tail) = &store.data().stderr_tail {
+        tail.lock().clear();
+    }
+    let start = store.data().profile.is_some().then(Instant::now);
+    let mut args_hash = store.data().trace.is_some().then(DefaultHasher::new);
+    #[cfg(feature = "fuel")]
+    let fuel_start = store.get_fuel().unwrap_or_default();
+    let args = args.into_iter().inspect(|v| {
+        if let Some(h) = &mut args_hash {
+            v.borrow().hash().hash(h);
+        }
+    });
+    let r = raw_call(&mut store, f, ty, args);
+
+    if let Some(h) = args_hash {
+        let entry = TraceEntry {
+            name: name.to_string(),
+            depth: store.data().call_depth - 1,
+            args_hash: h.finish(),
+            result_hash: r.as_ref().ok().map(|v| {
+                let mut h = DefaultHasher::new();
+                v.to_variant().hash().hash(&mut h);
+                h.finish()
+            }),
+            #[cfg(feature = "fuel")]
+            fuel: fuel_start.saturating_sub(store.get_fuel().unwrap_or_default()),
+        };
+        if let Some(trace) = &mut store.data_mut().trace {
+            if trace.len() >= MAX_TRACE_ENTRIES {
+                trace.pop_front();
+            }
+            trace.push_back(entry);
+        }
+    }
+
+    #[cfg(feature = "fuel")]
+    if let Some((limit, mult)) = fuel {
+        let used = limit.saturating_sub(store.get_fuel()?);
+        if let Some(v) = &mut store.data_mut().fuel_budget {
+            *v = v.saturating_sub((used as f64 * mult).ceil() as u64);
+        }
+    }
+
+    #[cfg(feature = "wasi")]
+    let r = match (r, &store.data().stderr_tail) {
+        (Err(e), Some(tail)) if e.downcast_ref::<Trap>().is_some() => {
+            let tail = mem::take(&mut *tail.lock());
+            let msg = String::from_utf8_lossy(&tail);
+            let msg = msg.trim_end();
+            Err(if msg.is_empty() {
+                e
+            } else {
+                e.context(format!("Guest standard error:\n{msg}"))
+            })
+        }
+        (r, _) => r,
+    };
+
+    if let (Some(start), Some(profile)) = (start, &mut store.data_mut().profile) {
+        if !profile.contains_key(name) {
+            profile.insert(name.to_string(), ProfileEntry::default());
+        }
+        let entry = profile.get_mut(name).unwrap();
+        entry.calls += 1;
+        entry.total += start.elapsed();
+    }
+
+    r
+}
+
+/// Callable to exported function.
+///
+/// Function is looked up by name on every call, so it stays valid after `reload_module()`.
+struct WasmCallable {
+    name: StringName,
+    ty: FuncType,
+    this: SendSyncWrapper<Gd<WasmInstance>>,
 }
 
 impl PartialEq for WasmCallable {
@@ -811,13 +2583,12 @@ impl Hash for WasmCallable {
 
 impl fmt::Debug for WasmCallable {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { this, name, ty, f } = self;
+        let Self { this, name, ty } = self;
 
         fmt.debug_struct("WasmCallable")
             .field("object", &**this)
             .field("name", name)
             .field("type", ty)
-            .field("func", f)
             .finish()
     }
 }
@@ -841,154 +2612,1011 @@ impl fmt::Display for WasmCallable {
 
         write!(f, "WasmCallable({:?}.{}<(", *self.this, self.name)?;
 
-        write_iter(self.ty.params(), f)?;
-        write!(f, "), (")?;
-        write_iter(self.ty.results(), f)?;
+        write_iter(self.ty.params(), f)?;
+        write!(f, "), (")?;
+        write_iter(self.ty.results(), f)?;
+
+        write!(f, ")>)")
+    }
+}
+
+impl RustCallable for WasmCallable {
+    fn invoke(&mut self, args: &[&Variant]) -> Result<Variant, ()> {
+        let Self { name, ty, this } = self;
+
+        let name = name.to_string();
+        let r = this.bind().call_export_with(&name, true, |_, store, f, t| {
+            if !FuncType::eq(&t, ty) {
+                bail_with_site!("Export {name} signature has changed");
+            }
+            call_func(store, &name, &f, &t, args.iter().copied())
+        });
+        match r.flatten() {
+            Some(v) => Ok(v.to_variant()),
+            None => Err(()),
+        }
+    }
+}
+
+#[godot_api]
+impl IRefCounted for WasmInstance {
+    /// Exposes exported functions as `Callable` properties.
+    ///
+    /// Methods and properties of the class and its script take precedence.
+    /// Calling export as a method (`instance.my_export()`) is not supported by Godot.
+    fn get_property(&self, property: StringName) -> Option<Variant> {
+        let module = self.get_data().ok()?.module.clone();
+        let name = property.to_string();
+        if self.missing_exports.lock().contains(&name) {
+            return None;
+        }
+
+        // Type is taken from module, so store is not locked.
+        let ty = match module
+            .bind()
+            .get_data()
+            .ok()?
+            .module
+            .get_core()
+            .ok()?
+            .get_export(&name)
+        {
+            Some(ExternType::Func(ty)) => ty,
+            _ => {
+                let mut missing = self.missing_exports.lock();
+                if missing.len() >= MAX_MISSING_EXPORTS {
+                    missing.clear();
+                }
+                missing.insert(name);
+                return None;
+            }
+        };
+
+        let has_name = |l: Array<Dictionary>| {
+            l.iter_shared()
+                .any(|d| d.get("name").map_or(false, |v| v.to_string() == name))
+        };
+
+        let db = ClassDb::singleton();
+        let class = Self::class_name().to_string_name();
+        if db.class_has_method(class.clone(), property.clone())
+            || has_name(db.class_get_property_list(class))
+        {
+            return None;
+        }
+        if let Ok(script) = self.base().get_script().try_to::<Gd<Script>>() {
+            if has_name(script.get_script_property_list())
+                || has_name(script.get_script_method_list())
+            {
+                return None;
+            }
+        }
+
+        let this = SendSyncWrapper::new(self.to_gd());
+        Some(
+            Callable::from_custom(WasmCallable {
+                name: property,
+                ty,
+                this,
+            })
+            .to_variant(),
+        )
+    }
+}
+
+#[godot_api]
+impl WasmInstance {
+    /// Emitted if an error happened. Use it to handle errors.
+    #[signal]
+    fn error_happened(message: GString);
+    /// Emitted before `error_happened` if the error is a trap with code from `signal_error_with_code`.
+    #[signal]
+    fn error_code_happened(message: GString, code: i64);
+    /// Emitted before `error_happened` if the error is a WASM trap.
+    #[signal]
+    fn trap_happened(code: GString, message: GString);
+    /// Emitted after the instance is successfully instantiated.
+    #[signal]
+    fn instantiated(module: Gd<WasmModule>, wasi: bool);
+    /// Emitted whenever WASI stdout is written. Only usable with WASI.
+    #[signal]
+    fn stdout_emit(message: Variant);
+    /// Emitted whenever WASI stderr is written. Only usable with WASI.
+    #[signal]
+    fn stderr_emit(message: Variant);
+    /// Emitted whenever WASI stdin is tried to be read. Only usable with WASI.
+    #[signal]
+    fn stdin_request();
+
+    /// Emitted (deferred) when object registry is full and a value is refused.
+    /// `index` is the index it would have been registered at.
+    #[signal]
+    fn registry_overflow(index: i64);
+    /// Emitted when memory growth is denied too many times, as set by config.
+    #[signal]
+    fn memory_exhausted();
+
+    /// Initialize and instantiates module.
+    ///
+    /// **⚠ MUST BE CALLED FOR THE FIRST TIME AND ONLY ONCE.**
+    ///
+    /// Returns itself if succeed, `null` otherwise.
+    ///
+    /// Arguments:
+    /// - `module` : `WasmModule` to be instantiated.
+    /// - `host` : Dictionary containing host module and functions to be bound.
+    ///   It's value is a struct of the following:
+    ///   - `params` : Array of parameter types.
+    ///   - `results` : Array of result types.
+    ///   - `callable` : `Callable` to be bound. Prefer this over object-method.
+    ///   - `object` : Object to be bound.
+    ///   - `method` : Method to be bound.
+    /// - `config` : Configuration option.
+    ///
+    /// Usage:
+    /// ```
+    /// var module := WasmModule.new().initialize("...", {})
+    /// var instance := WasmInstance.new().initialize(module, {}, {})
+    ///
+    /// if instance == null:
+    ///   # Cannot instantiate module
+    ///   pass
+    /// ```
+    #[func]
+    fn initialize(
+        &self,
+        module: Gd<WasmModule>,
+        host: Variant,
+        config: Variant,
+    ) -> Option<Gd<WasmInstance>> {
+        let Ok(host) = variant_to_option::<Dictionary>(host) else {
+            godot_error!("Host is not a dictionary!");
+            return None;
+        };
+        let config = if config.is_nil() { None } else { Some(config) };
+
+        if self.initialize_(module, host, config, None) {
+            Some(self.to_gd())
+        } else {
+            None
+        }
+    }
+
+    /// Initialize and instantiates module in a separate thread.
+    ///
+    /// **⚠ MUST BE CALLED FOR THE FIRST TIME AND ONLY ONCE.**
+    ///
+    /// Imports are resolved (and dependency modules instantiated) in the calling thread,
+    /// only instantiation of the module itself runs in a separate thread.
+    /// Because start function runs in that thread, it fails if any import needs the main thread
+    /// (host functions, WASI, object registry, time, yield, or fallback resolver).
+    /// Use `initialize` for such modules.
+    ///
+    /// Returns `true` if instantiation is started. Result is reported with
+    /// `instantiated` or `error_happened` signal. Until then, the instance is uninitialized.
+    /// Calling it again while instantiation is in progress does nothing and returns `true`.
+    ///
+    /// Arguments are the same as `initialize`.
+    #[func]
+    fn initialize_async(&self, module: Gd<WasmModule>, host: Variant, config: Variant) -> bool {
+        let Ok(host) = variant_to_option::<Dictionary>(host) else {
+            godot_error!("Host is not a dictionary!");
+            return false;
+        };
+        let config = if config.is_nil() { None } else { Some(config) };
+        if self.data.read().is_some() || self.init_pending.swap(true, Ordering::AcqRel) {
+            return true;
+        }
+
+        // Everything touching Godot objects is done here, the thread only runs wasmtime.
+        let inst_id = self.base().instance_id();
+        let prepared = self
+            .prepare_data(&module, host.clone(), config.clone())
+            .and_then(|v| {
+                v.check_off_thread()?;
+                Ok(v)
+            });
+        let prepared = match prepared {
+            Ok(v) => v,
+            Err(e) => {
+                self.init_pending.store(false, Ordering::Release);
+                self.emit_error(e);
+                return false;
+            }
+        };
+
+        let result = <Arc<Mutex<Option<SendSyncWrapper<AnyResult<_>>>>>>::default();
+        let args = Mutex::new(Some(SendSyncWrapper::new((module, host, config))));
+        let finish = {
+            let result = result.clone();
+            Callable::from_fn("initialize_async_finish", move |_| {
+                let (Some(args), Some(r)) = (args.lock().take(), result.lock().take()) else {
+                    return Ok(Variant::nil());
+                };
+                let Ok(this) = <Gd<WasmInstance>>::try_from_instance_id(inst_id) else {
+                    return Ok(Variant::nil());
+                };
+
+                let (module, host, config) = args.into_inner();
+                let inst = this.bind();
+                inst.init_pending.store(false, Ordering::Release);
+                let r = inst.init_data(host, config, || {
+                    let p: PreparedInstance<StoreData> = r.into_inner()?;
+                    Ok(Self::with_memory(p.into_data(module)?))
+                });
+                inst.finish_initialize(r);
+                Ok(Variant::nil())
+            })
+        };
+
+        let prepared = SendSyncWrapper::new(prepared);
+        let finish = SendSyncWrapper::new(finish);
+        thread::spawn(move || {
+            let mut p = prepared.into_inner();
+            let r = p.run().map(|_| p);
+            *result.lock() = Some(SendSyncWrapper::new(r));
+            finish.call_deferred(&[]);
+        });
+        true
+    }
+
+    /// Re-instantiates with a new module, preserving memory and globals.
+    ///
+    /// Fails if the instance is in use (e.g. called from a host function while WASM is running).
+    ///
+    /// The new module must be ABI-compatible with the current one (see `WasmModule.abi_compatible()`).
+    /// Host and config from initialization are reused.
+    /// Memory content, mutable exported globals, memory watches, object registry,
+    /// random streams and pending events are carried into the new instance.
+    /// Other store state (profile, trace, statistics, externrefs, WASI state) is reset.
+    /// Callables from `bind_wasm_callable()` and export properties call the new instance.
+    ///
+    /// Returns `true` if succeed. If failed, the current instance is left as is.
+    #[func]
+    fn reload_module(&self, new_module: Gd<WasmModule>) -> bool {
+        match self.reload_module_(new_module) {
+            Ok(()) => {
+                self.memory_warned.store(false, Ordering::Relaxed);
+                true
+            }
+            Err(e) => {
+                self.emit_error(e);
+                false
+            }
+        }
+    }
+
+    fn reload_module_(&self, new_module: Gd<WasmModule>) -> AnyResult<()> {
+        {
+            let (a, b) = (self.get_data()?.module.clone(), new_module.clone());
+            let (a, b) = (a.bind(), b.bind());
+            let diff = AbiDiff::new(
+                site_context!(a.get_data()?.module.get_core())?,
+                site_context!(b.get_data()?.module.get_core())?,
+            );
+            if !diff.is_compatible() {
+                bail_with_site!(
+                    "Module is not ABI-compatible (removed: {:?}, changed: {:?})",
+                    diff.removed,
+                    diff.changed
+                );
+            }
+        }
+
+        // Instantiated without holding the lock, as start function may call back into the instance.
+        let (host, config) = self.init_args.lock().clone().unwrap_or_default();
+        let (data, new_memory) = self.create_data(new_module, host, config, None)?;
+
+        // Calls hold the read lock, so it fails if any call is in progress.
+        let Some(mut guard) = self.data.try_write() else {
+            bail_with_site!("Cannot reload while instance is in use");
+        };
+        let Some(m) = &*guard else {
+            bail_with_site!("Uninitialized instance");
+        };
+        let (memory, globals, state) = m.acquire_store(|m, mut store| -> AnyResult<_> {
+            let memory = match self.memory() {
+                Some(MemoryType::Memory(mem)) => Some(mem.data(&store).to_vec()),
+                #[cfg(feature = "wasm-threads")]
+                Some(MemoryType::SharedMemory(_)) => {
+                    bail_with_site!("Cannot reload instance with shared memory")
+                }
+                None => None,
+            };
+
+            let globals = site_context!(m.instance.get_core())?
+                .exports(&mut store)
+                .filter_map(|e| Some((e.name().to_string(), e.into_global()?)))
+                .collect::<Vec<_>>();
+            let globals = globals
+                .into_iter()
+                .filter(|(_, g)| g.ty(&store).mutability() == Mutability::Var)
+                .map(|(k, g)| (k, g.get(&mut store)))
+                // Reference values are bound to the old store
+                .filter(|(_, v)| {
+                    matches!(
+                        v,
+                        Val::I32(_) | Val::I64(_) | Val::F32(_) | Val::F64(_) | Val::V128(_)
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            // Per-store state that does not reference the old store
+            let data = store.data();
+            let state = StoreData {
+                memory_watches: data.memory_watches.clone(),
+                rand_streams: data.rand_streams.clone(),
+                events: data.events.clone(),
+                #[cfg(feature = "object-registry-compat")]
+                object_registry: data.object_registry.clone(),
+                ..StoreData::default()
+            };
+
+            Ok((memory, globals, state))
+        })?;
+
+        data.acquire_store(|m, mut store| -> AnyResult<()> {
+            match (&memory, &new_memory) {
+                (None, _) => (),
+                (Some(v), Some(MemoryType::Memory(mem))) => {
+                    let n = v.len() as u64;
+                    let size = mem.data_size(&store) as u64;
+                    if n > size {
+                        site_context!(
+                            mem.grow(&mut store, (n - size).div_ceil(mem.page_size(&store)))
+                        )?;
+                    }
+                    mem.data_mut(&mut store)[..v.len()].copy_from_slice(v);
+                }
+                _ => bail_with_site!("Incompatible memory"),
+            }
+
+            let inst = site_context!(m.instance.get_core())?;
+            for (k, v) in globals {
+                if let Some(g) = inst.get_global(&mut store, &k) {
+                    site_context!(g.set(&mut store, v))?;
+                }
+            }
+
+            let data = store.data_mut();
+            data.memory_watches = state.memory_watches;
+            data.rand_streams = state.rand_streams;
+            data.events = state.events;
+            #[cfg(feature = "object-registry-compat")]
+            if data.object_registry.is_some() {
+                data.object_registry = state.object_registry;
+            }
+            Ok(())
+        })?;
+
+        *self.memory.write() = new_memory;
+        *guard = Some(data);
+        self.missing_exports.lock().clear();
+        Ok(())
+    }
+
+    /// Gets the module used to instantiate this object.
+    #[func]
+    fn get_module(&self) -> Option<Gd<WasmModule>> {
+        self.unwrap_data(|m| Ok(m.module.clone()))
+    }
+
+    /// Calls into WASM.
+    ///
+    /// Arguments:
+    /// - `name` : Name of the exported function.
+    /// - `args` : Array of parameters.
+    ///
+    /// Returns an array of results, or `null` if failed.
+    #[func]
+    fn call_wasm(&self, name: StringName, args: VariantArray) -> Variant {
+        let name = name.to_string();
+        option_to_variant(
+            self.call_export_with(&name, true, |_, mut store, f, ty| {
+                let r = call_func(store.as_context_mut(), &name, &f, &ty, args.iter_shared())?;
+                auto_unwrap_results(store.data(), &ty, r)
+            })
+            .flatten(),
+        )
+    }
+
+    /// Calls into WASM without waiting for other calls.
+    ///
+    /// Returns `ERR_BUSY` if the instance is in use by another thread,
+    /// otherwise it's the same as `call_wasm`.
+    #[func]
+    fn try_call_wasm(&self, name: StringName, args: VariantArray) -> Variant {
+        let name = name.to_string();
+        let r = self.call_export_with(&name, false, |_, mut store, f, ty| {
+            let r = call_func(store.as_context_mut(), &name, &f, &ty, args.iter_shared())?;
+            auto_unwrap_results(store.data(), &ty, r)
+        });
+        match r {
+            Some(Some(v)) => v.to_variant(),
+            Some(None) => GError::ERR_BUSY.to_variant(),
+            None => Variant::nil(),
+        }
+    }
+
+    /// Calls into WASM, aborting it if it runs longer than `max_ms` milliseconds.
+    ///
+    /// Returns `{ "completed": bool, "result": Array|null }`.
+    /// Budget is rounded up to epoch tick interval.
+    #[func]
+    fn call_wasm_within_budget(
+        &self,
+        name: StringName,
+        args: VariantArray,
+        max_ms: f64,
+    ) -> Dictionary {
+        cfg_if! {
+            if #[cfg(feature = "epoch-timeout")] {
+                if let Err(e) = site_context!(start_epoch()) {
+                    self.emit_error(e);
+                    return Dictionary::new();
+                }
+                let name = name.to_string();
+                let r = self
+                    .call_export_with(&name, true, |_, mut store, f, ty| {
+                        let ticks = (max_ms.max(0.0) * EPOCH_MULTIPLIER as f64 / 1000.0).ceil() as u64;
+                        let data = store.data_mut();
+                        data.budget_deadline = Some(get_epoch().saturating_add(ticks.max(1)));
+                        data.budget_exceeded = false;
+                        let v = data.epoch_timeout;
+                        set_epoch_deadline(&mut store, v);
+
+                        let r = call_func(store.as_context_mut(), &name, &f, &ty, args.iter_shared());
+                        let data = store.data_mut();
+                        data.budget_deadline = None;
+                        let r = match r {
+                            Err(_) if mem::take(&mut data.budget_exceeded) => return Ok(None),
+                            r => r?,
+                        };
+
+                        auto_unwrap_results(store.data(), &ty, r).map(Some)
+                    })
+                    .flatten();
+
+                let mut ret = Dictionary::new();
+                ret.set(StringName::from(c"completed"), matches!(r, Some(Some(_))));
+                ret.set(StringName::from(c"result"), option_to_variant(r.flatten()));
+                ret
+            } else {
+                godot_error!("Feature epoch-timeout not enabled!");
+                Dictionary::new()
+            }
+        }
+    }
+
+    /// Calls into WASM by qualified name.
+    ///
+    /// Core module exports are flat, so `module_path` must be empty.
+    #[func]
+    fn call_wasm_qualified(
+        &self,
+        module_path: PackedStringArray,
+        name: StringName,
+        args: VariantArray,
+    ) -> Variant {
+        if !module_path.is_empty() {
+            godot_error!("Core module does not have nested instances");
+            return Variant::nil();
+        }
+        self.call_wasm(name, args)
+    }
+
+    /// Calls into WASM, converting integer results into registry objects.
+    ///
+    /// Arguments:
+    /// - `name` : Name of the exported function.
+    /// - `args` : Array of parameters.
+    ///
+    /// Returns an array of results, or `null` if failed.
+    #[func]
+    fn call_wasm_unwrap_objects(&self, name: StringName, args: VariantArray) -> Variant {
+        cfg_if! {
+            if #[cfg(feature = "object-registry-compat")] {
+                let name = name.to_string();
+                option_to_variant(
+                    self.call_export_with(&name, true, |_, mut store, f, ty| {
+                        let r = call_func(store.as_context_mut(), &name, &f, &ty, args.iter_shared())?;
+                        unwrap_registry_results(store.data(), &ty, r)
+                    })
+                    .flatten(),
+                )
+            } else {
+                godot_error!("Feature object-registry-compat not enabled!");
+                Variant::nil()
+            }
+        }
+    }
+
+    /// Calls into WASM and reads the returned buffer.
+    ///
+    /// The function must return exactly two integers, pointer and length of the buffer.
+    ///
+    /// Arguments:
+    /// - `name` : Name of the exported function.
+    /// - `args` : Array of parameters.
+    ///
+    /// Returns content of the buffer.
+    #[func]
+    fn call_wasm_returning_buffer(&self, name: StringName, args: VariantArray) -> PackedByteArray {
+        let name = name.to_string();
+        let r = self.call_export_with(&name, true, |_, store, f, ty| {
+            if !matches!(
+                ty.results().collect::<Vec<_>>()[..],
+                [ValType::I32 | ValType::I64, ValType::I32 | ValType::I64]
+            ) {
+                bail_with_site!("Function {name} does not return (pointer, length) pair");
+            }
+
+            let r = call_func(store, &name, &f, &ty, args.iter_shared())?;
+            let mut it = ty
+                .results()
+                .zip(r.iter_shared())
+                .map(|(t, v)| result_to_usize(&t, &v));
+            match (it.next(), it.next()) {
+                (Some(p), Some(n)) => Ok((p?, n?)),
+                _ => bail_with_site!("Function {name} does not return (pointer, length) pair"),
+            }
+        });
+
+        r.flatten()
+            .and_then(|(p, n)| self.read_memory(p, n, |s| Ok(PackedByteArray::from(s))))
+            .unwrap_or_default()
+    }
+
+    /// Writes buffer into WASM memory, then calls into WASM with it.
+    ///
+    /// Buffer is allocated by calling the allocator export.
+    /// If free export is configured, buffer is freed after the call.
+    ///
+    /// Arguments:
+    /// - `name` : Name of the exported function.
+    /// - `data` : Buffer to be passed.
+    /// - `extra_args` : Parameters after pointer and length.
+    ///
+    /// Returns an array of results, or `null` if failed.
+    #[func]
+    fn call_wasm_with_buffer(
+        &self,
+        name: StringName,
+        data: PackedByteArray,
+        extra_args: VariantArray,
+    ) -> Variant {
+        let len = data.len() as i64;
+        option_to_variant(self.unwrap_data(move |m| {
+            m.acquire_store(move |m, mut store| {
+                let alloc = store.data().alloc_func.clone();
+                let (f, ty) = get_export_func(m, &mut store, &alloc)?;
+                let r = call_func(store.as_context_mut(), &alloc, &f, &ty, [len.to_variant()])?;
+                let (p, ptr) = match (ty.results().next(), r.len()) {
+                    (Some(t), 1) => (result_to_usize(&t, &r.at(0))?, r.at(0)),
+                    _ => bail_with_site!("Allocator {alloc} does not return a pointer"),
+                };
+
+                let written = self.with_store_memory(store.as_context_mut(), |mem, store| match p
+                    .checked_add(data.len())
+                    .and_then(|e| mem.get_mut(p..e))
+                {
+                    Some(s) => {
+                        MemoryStats::record(&mut store.memory_stats, s.len(), true);
+                        s.copy_from_slice(data.as_slice());
+                        Ok(())
+                    }
+                    None => {
+                        bail_with_site!("Index out of bound {}-{}", p, p.wrapping_add(data.len()))
+                    }
+                });
+                let r = match written {
+                    Ok(Some(())) => {
+                        let args = [ptr.clone(), len.to_variant()];
+                        call_export(
+                            m,
+                            store.as_context_mut(),
+                            &name.to_string(),
+                            args.into_iter().chain(extra_args.iter_shared()),
+                        )
+                    }
+                    Ok(None) => Err(anyhow::anyhow!("Cannot write buffer")),
+                    Err(e) => Err(e),
+                };
+
+                if let Some(free) = store.data().free_func.clone() {
+                    call_export(m, store, &free, [ptr, len.to_variant()])?;
+                }
+                r
+            })
+        }))
+    }
+
+    /// Calls into WASM with named parameters.
+    ///
+    /// Parameter names are read from the "name" custom section,
+    /// so the module must be compiled from source with debug names.
+    ///
+    /// Arguments:
+    /// - `name` : Name of the exported function.
+    /// - `kwargs` : Dictionary of parameter name to value.
+    ///
+    /// Returns an array of results, or `null` if failed.
+    #[func]
+    fn call_wasm_named(&self, name: StringName, kwargs: Dictionary) -> Variant {
+        let name = name.to_string();
+        option_to_variant(
+            self.call_export_with(&name, true, |m, store, f, ty| {
+                let module = m.module.bind();
+                let names = module
+                    .get_data()?
+                    .get_metadata()?
+                    .param_names(&name, ty.params().len())?;
+                let args = names
+                    .into_iter()
+                    .map(|n| match kwargs.get(n) {
+                        Some(v) => Ok(v),
+                        None => bail_with_site!("Missing parameter {n}"),
+                    })
+                    .collect::<AnyResult<Vec<_>>>()?;
+                drop(module);
+
+                call_func(store, &name, &f, &ty, args)
+            })
+            .flatten(),
+        )
+    }
+
+    /// Binds WASM function into a `Callable`.
+    ///
+    /// Arguments:
+    /// - `name` : Name of the exported function.
+    ///
+    /// Returns a `Callable` that can be used to call into WASM.
+    #[func]
+    fn bind_wasm_callable(&self, name: StringName) -> Callable {
+        self.unwrap_data(|m| {
+            m.acquire_store(|m, store| {
+                let (_, ty) = get_export_func(m, store, &name.to_string())?;
+
+                let this = SendSyncWrapper::new(self.to_gd());
+                Ok(Callable::from_custom(WasmCallable { name, ty, this }))
+            })
+        })
+        .unwrap_or_else(Callable::invalid)
+    }
+
+    /// Gets call profile of exported functions.
+    ///
+    /// Returns dictionary of function name to `{ "calls": int, "total_usec": int }`.
+    /// Only usable if profiling is enabled.
+    #[func]
+    fn get_profile(&self) -> Dictionary {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, store| {
+                let Some(profile) = &store.data().profile else {
+                    bail_with_site!("Profiling is not enabled")
+                };
+
+                let mut ret = Dictionary::new();
+                for (k, v) in profile {
+                    let mut d = Dictionary::new();
+                    d.set(StringName::from(c"calls"), v.calls as i64);
+                    d.set(StringName::from(c"total_usec"), v.total.as_micros() as i64);
+                    ret.set(k.as_str(), d);
+                }
+                Ok(ret)
+            })
+        })
+        .unwrap_or_default()
+    }
+
+    /// Clears call profile.
+    #[func]
+    fn reset_profile(&self) {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, mut store| {
+                if let Some(profile) = &mut store.data_mut().profile {
+                    profile.clear();
+                }
+                Ok(())
+            })
+        });
+    }
+
+    /// Gets recorded call trace, oldest first.
+    ///
+    /// Only usable if call tracing is enabled.
+    #[func]
+    fn get_trace(&self) -> Array<Dictionary> {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, store| {
+                let Some(trace) = &store.data().trace else {
+                    bail_with_site!("Call tracing is not enabled")
+                };
+
+                Ok(trace
+                    .iter()
+                    .map(|v| {
+                        let mut d = Dictionary::new();
+                        d.set(StringName::from(c"name"), v.name.as_str());
+                        d.set(StringName::from(c"depth"), v.depth as i64);
+                        d.set(StringName::from(c"args_hash"), v.args_hash as i64);
+                        d.set(
+                            StringName::from(c"result_hash"),
+                            option_to_variant(v.result_hash.map(|v| v as i64)),
+                        );
+                        #[cfg(feature = "fuel")]
+                        d.set(StringName::from(c"fuel"), v.fuel as i64);
+                        d
+                    })
+                    .collect())
+            })
+        })
+        .unwrap_or_default()
+    }
+
+    /// Clears call trace.
+    #[func]
+    fn clear_trace(&self) {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, mut store| {
+                if let Some(trace) = &mut store.data_mut().trace {
+                    trace.clear();
+                }
+                Ok(())
+            })
+        });
+    }
+
+    /// Drains events emitted by WASM with `env.emit_event()`.
+    ///
+    /// Returns list of `{ "type", "bytes" }`, in emission order.
+    #[func]
+    fn poll_events(&self) -> Array<Dictionary> {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, mut store| {
+                Ok(store
+                    .data_mut()
+                    .events
+                    .drain(..)
+                    .map(|(t, v)| {
+                        let mut d = Dictionary::new();
+                        d.set(StringName::from(c"type"), t);
+                        d.set(StringName::from(c"bytes"), PackedByteArray::from(&*v));
+                        d
+                    })
+                    .collect())
+            })
+        })
+        .unwrap_or_default()
+    }
+
+    /// Gets memory access statistics.
+    ///
+    /// Returns `{ "bytes_read", "bytes_written", "read_calls", "write_calls" }`.
+    /// Only usable if statistics collection is enabled.
+    #[func]
+    fn get_memory_stats(&self) -> Dictionary {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, store| {
+                let Some(stats) = &store.data().memory_stats else {
+                    bail_with_site!("Memory statistics is not enabled")
+                };
 
-        write!(f, ")>)")
+                let mut ret = Dictionary::new();
+                ret.set(StringName::from(c"bytes_read"), stats.bytes_read as i64);
+                ret.set(
+                    StringName::from(c"bytes_written"),
+                    stats.bytes_written as i64,
+                );
+                ret.set(StringName::from(c"read_calls"), stats.read_calls as i64);
+                ret.set(StringName::from(c"write_calls"), stats.write_calls as i64);
+                Ok(ret)
+            })
+        })
+        .unwrap_or_default()
     }
-}
-
-impl RustCallable for WasmCallable {
-    fn invoke(&mut self, args: &[&Variant]) -> Result<Variant, ()> {
-        let Self { ty, f, this, .. } = self;
 
-        let r = this.bind().unwrap_data(|m| {
-            m.acquire_store(|_, #[allow(unused_mut)] mut store| {
-                #[cfg(feature = "epoch-timeout")]
-                if let v @ 1.. = store.data().epoch_timeout {
-                    store.set_epoch_deadline(v);
-                }
+    /// Gets how each import of the module is resolved.
+    ///
+    /// Returns list of `{ "module", "name", "resolved_by" }`.
+    #[func]
+    fn get_import_bindings(&self) -> Array<Dictionary> {
+        self.unwrap_data(|m| {
+            Ok(m.import_bindings
+                .iter()
+                .map(|(module, name, src)| {
+                    let mut d = Dictionary::new();
+                    d.set(StringName::from(c"module"), GString::from(module));
+                    d.set(StringName::from(c"name"), GString::from(name));
+                    d.set(StringName::from(c"resolved_by"), GString::from(&**src));
+                    d
+                })
+                .collect())
+        })
+        .unwrap_or_default()
+    }
 
-                unsafe { raw_call(store, f, ty, args.iter().copied()) }
+    /// Resets random stream used by `rand_stream()` import.
+    #[func]
+    fn reseed_stream(&self, id: i32, seed: i64) {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, mut store| {
+                store
+                    .data_mut()
+                    .rand_streams
+                    .insert(id, RandStream::new(seed as _, id));
+                Ok(())
             })
         });
-        match r {
-            Some(v) => Ok(v.to_variant()),
-            None => Err(()),
-        }
     }
-}
 
-#[godot_api]
-impl WasmInstance {
-    /// Emitted if an error happened. Use it to handle errors.
-    #[signal]
-    fn error_happened(message: GString);
-    /// Emitted whenever WASI stdout is written. Only usable with WASI.
-    #[signal]
-    fn stdout_emit(message: Variant);
-    /// Emitted whenever WASI stderr is written. Only usable with WASI.
-    #[signal]
-    fn stderr_emit(message: Variant);
-    /// Emitted whenever WASI stdin is tried to be read. Only usable with WASI.
-    #[signal]
-    fn stdin_request();
+    /// Returns `true` if WASM called `yield()` import during the last call.
+    #[func]
+    fn was_yield_requested(&self) -> bool {
+        self.unwrap_data(|m| m.acquire_store(|_, store| Ok(store.data().yield_requested)))
+            .unwrap_or_default()
+    }
 
-    /// Initialize and instantiates module.
-    ///
-    /// **⚠ MUST BE CALLED FOR THE FIRST TIME AND ONLY ONCE.**
-    ///
-    /// Returns itself if succeed, `null` otherwise.
-    ///
-    /// Arguments:
-    /// - `module` : `WasmModule` to be instantiated.
-    /// - `host` : Dictionary containing host module and functions to be bound.
-    ///   It's value is a struct of the following:
-    ///   - `params` : Array of parameter types.
-    ///   - `results` : Array of result types.
-    ///   - `callable` : `Callable` to be bound. Prefer this over object-method.
-    ///   - `object` : Object to be bound.
-    ///   - `method` : Method to be bound.
-    /// - `config` : Configuration option.
-    ///
-    /// Usage:
-    /// ```
-    /// var module := WasmModule.new().initialize("...", {})
-    /// var instance := WasmInstance.new().initialize(module, {}, {})
+    /// Returns number of live externrefs.
     ///
-    /// if instance == null:
-    ///   # Cannot instantiate module
-    ///   pass
-    /// ```
+    /// Always returns 0 if `object-registry-extern` feature is disabled.
     #[func]
-    fn initialize(
-        &self,
-        module: Gd<WasmModule>,
-        host: Variant,
-        config: Variant,
-    ) -> Option<Gd<WasmInstance>> {
-        let Ok(host) = variant_to_option::<Dictionary>(host) else {
-            godot_error!("Host is not a dictionary!");
-            return None;
-        };
-        let config = if config.is_nil() { None } else { Some(config) };
-
-        if self.initialize_(module, host, config) {
-            Some(self.to_gd())
-        } else {
-            None
+    fn externref_count(&self) -> i64 {
+        cfg_if! {
+            if #[cfg(feature = "object-registry-extern")] {
+                self.unwrap_data(|m| {
+                    m.acquire_store(|_, store| {
+                        Ok(store.data().externref_count.load(Ordering::Acquire) as i64)
+                    })
+                })
+                .unwrap_or_default()
+            } else {
+                0
+            }
         }
     }
 
-    /// Gets the module used to instantiate this object.
+    /// Interrupts running call, and any further calls. Thread-safe, does not wait for the store.
+    ///
+    /// Requires `epoch.allowInterrupt` config. Returns `false` if it is not enabled.
     #[func]
-    fn get_module(&self) -> Option<Gd<WasmModule>> {
-        self.unwrap_data(|m| Ok(m.module.clone()))
+    fn interrupt(&self) -> bool {
+        cfg_if! {
+            if #[cfg(feature = "epoch-timeout")] {
+                let Ok(data) = self.get_data() else {
+                    return false;
+                };
+                match &data.interrupt {
+                    Some(flag) => {
+                        flag.store(true, Ordering::Release);
+                        true
+                    }
+                    None => false,
+                }
+            } else {
+                false
+            }
+        }
     }
 
-    /// Calls into WASM.
+    /// Returns remaining fuel budget.
     ///
-    /// Arguments:
-    /// - `name` : Name of the exported function.
-    /// - `args` : Array of parameters.
+    /// Returns -1 if there is no budget or `fuel` feature is disabled.
+    #[func]
+    fn get_fuel_remaining(&self) -> i64 {
+        cfg_if! {
+            if #[cfg(feature = "fuel")] {
+                self.unwrap_data(|m| {
+                    m.acquire_store(|_, store| {
+                        Ok(store.data().fuel_budget.map_or(-1, |v| v.min(i64::MAX as _) as i64))
+                    })
+                })
+                .unwrap_or(-1)
+            } else {
+                -1
+            }
+        }
+    }
+
+    /// Gets a snapshot of instance state, for diagnostics.
     ///
-    /// Returns an array of results, or `null` if failed.
+    /// Keys that does not apply (eg. disabled feature) are omitted.
     #[func]
-    fn call_wasm(&self, name: StringName, args: VariantArray) -> Variant {
-        option_to_variant(self.unwrap_data(move |m| {
-            m.acquire_store(move |m, mut store| {
-                let name = name.to_string();
-                let f = match site_context!(m.instance.get_core())?.get_export(&mut store, &name) {
-                    Some(Extern::Func(f)) => f,
-                    Some(_) => bail_with_site!("Export {name} is not a function"),
-                    None => bail_with_site!("Export {name} does not exists"),
-                };
-                let ty = f.ty(&store);
+    fn debug_info(&self) -> Dictionary {
+        self.unwrap_data(|m| {
+            m.acquire_store(|m, mut store| {
+                let mut ret = Dictionary::new();
+                {
+                    let module = m.module.bind();
+                    let module = module.get_data()?;
+                    ret.set(StringName::from(c"module_name"), module.name.clone());
+                    ret.set(
+                        StringName::from(c"is_component"),
+                        !matches!(module.module, ModuleType::Core(_)),
+                    );
+                }
 
+                let exports = match &m.instance {
+                    InstanceType::NoInstance => 0,
+                    InstanceType::Core(inst) => inst.exports(&mut store).count(),
+                    #[cfg(feature = "component-model")]
+                    InstanceType::Component(_) => match &m.module.bind().get_data()?.module {
+                        ModuleType::Component(c) => c.component_type().exports(c.engine()).count(),
+                        _ => 0,
+                    },
+                };
+                ret.set(StringName::from(c"export_count"), exports as i64);
+
+                let memory = self.memory();
+                let (size, pages) = match &memory {
+                    Some(MemoryType::Memory(mem)) => (mem.data_size(&store), mem.size(&store)),
+                    #[cfg(feature = "wasm-threads")]
+                    Some(MemoryType::SharedMemory(mem)) => (mem.data_size(), mem.size()),
+                    None => (0, 0),
+                };
+                ret.set(StringName::from(c"has_memory"), memory.is_some());
+                ret.set(StringName::from(c"memory_size"), size as i64);
+                ret.set(StringName::from(c"memory_pages"), pages as i64);
+
+                #[allow(unused_variables)]
+                let data = store.data();
+                #[cfg(feature = "wasi")]
+                ret.set(
+                    StringName::from(c"wasi"),
+                    !matches!(data.wasi_ctx, MaybeWasi::NoCtx),
+                );
+                #[cfg(feature = "object-registry-compat")]
+                ret.set(
+                    StringName::from(c"registry"),
+                    data.object_registry.is_some(),
+                );
                 #[cfg(feature = "epoch-timeout")]
-                if let v @ 1.. = store.data().epoch_timeout {
-                    store.set_epoch_deadline(v);
+                ret.set(
+                    StringName::from(c"epoch_timeout"),
+                    data.epoch_timeout as f64 / crate::wasm_util::EPOCH_MULTIPLIER as f64,
+                );
+                #[cfg(feature = "memory-limiter")]
+                if data.memory_limits.max_memory != u64::MAX {
+                    ret.set(
+                        StringName::from(c"memory_remaining"),
+                        data.memory_limits.max_memory as i64,
+                    );
                 }
-
-                unsafe { raw_call(store, &f, &ty, args.iter_shared()) }
+                Ok(ret)
             })
-        }))
+        })
+        .unwrap_or_default()
     }
 
-    /// Binds WASM function into a `Callable`.
+    /// Sets callable that is called whenever a call traps.
     ///
-    /// Arguments:
-    /// - `name` : Name of the exported function.
+    /// It is called with trap message and WASM backtrace (empty if not available).
+    /// If it returns `true`, error is not printed. Signals are still emitted.
+    /// Invalid callable removes the handler.
+    #[func]
+    fn set_trap_handler(&self, cb: Callable) {
+        *self.trap_handler.lock() = cb.is_valid().then_some(cb);
+    }
+
+    /// Sets callable that is called whenever WASM memory grows.
     ///
-    /// Returns a `Callable` that can be used to call into WASM.
+    /// It is called with the new memory size in pages, after the store is unlocked.
+    /// Invalid callable removes the callback.
     #[func]
-    fn bind_wasm_callable(&self, name: StringName) -> Callable {
+    fn set_memory_grow_callback(&self, cb: Callable) {
+        let cb = cb.is_valid().then_some(cb);
+        let observe = cb.is_some();
+        *self.memory_grow_callback.lock() = cb;
         self.unwrap_data(|m| {
-            m.acquire_store(|m, mut store| {
-                let n = name.to_string();
-                let f = match site_context!(m.instance.get_core())?.get_export(&mut store, &n) {
-                    Some(Extern::Func(f)) => f,
-                    Some(_) => bail_with_site!("Export {n} is not a function"),
-                    None => bail_with_site!("Export {n} does not exists"),
-                };
-                let ty = f.ty(&store);
-
-                let this = SendSyncWrapper::new(self.to_gd());
-                Ok(Callable::from_custom(WasmCallable { name, ty, f, this }))
+            m.acquire_store(|_, mut store| {
+                let v = &mut store.data_mut().memory_limits.grown_pages;
+                if observe != v.is_some() {
+                    *v = observe.then(Vec::new);
+                }
+                Ok(())
             })
-        })
-        .unwrap_or_else(Callable::invalid)
+        });
     }
 
     /// Emits trap when returning from host. Should only be used from imported host functions.
@@ -996,15 +3624,17 @@ impl WasmInstance {
     /// Returns previous error message, if any.
     #[func]
     fn signal_error(&self, msg: GString) -> Variant {
-        option_to_variant(self.unwrap_data(|m| {
-            m.acquire_store(|_, mut store| {
-                Ok(store
-                    .data_mut()
-                    .error_signal
-                    .replace(msg.to_string())
-                    .unwrap_or_default())
-            })
-        }))
+        self.signal_error_(msg, None)
+    }
+
+    /// Emits trap with error code when returning from host. Should only be used from imported host functions.
+    ///
+    /// The code is emitted with `error_code_happened` signal.
+    ///
+    /// Returns previous error message, if any.
+    #[func]
+    fn signal_error_with_code(&self, msg: GString, code: i64) -> Variant {
+        self.signal_error_(msg, Some(code))
     }
 
     /// Cancels effect of `signal_error`.
@@ -1014,7 +3644,12 @@ impl WasmInstance {
     fn signal_error_cancel(&self) -> Variant {
         option_to_variant(self.unwrap_data(|m| {
             m.acquire_store(|_, mut store| {
-                Ok(store.data_mut().error_signal.take().unwrap_or_default())
+                Ok(store
+                    .data_mut()
+                    .error_signal
+                    .take()
+                    .map(|v| v.msg)
+                    .unwrap_or_default())
             })
         }))
     }
@@ -1027,7 +3662,7 @@ impl WasmInstance {
                 self.unwrap_data(|m| {
                     m.acquire_store(|_, mut store| {
                         if let v @ 1.. = store.data().epoch_timeout {
-                            store.set_epoch_deadline(v);
+                            set_epoch_deadline(&mut store, v);
                         }
                         Ok(())
                     })
@@ -1056,6 +3691,28 @@ impl WasmInstance {
         }
     }
 
+    /// Registers multiple values and returns their indices. Only usable with object registry.
+    #[func]
+    fn register_objects(&self, _objs: VariantArray) -> PackedInt64Array {
+        cfg_if! {
+            if #[cfg(feature = "object-registry-compat")] {
+                self.unwrap_data(|m| {
+                    if _objs.iter_shared().any(|v| v.is_nil()) {
+                        bail_with_site!("Value is null!");
+                    }
+                    m.acquire_store(|_, mut store| {
+                        let reg = store.data_mut().get_registry_mut()?;
+                        Ok(_objs.iter_shared().map(|v| reg.register(v) as i64).collect())
+                    })
+                })
+                .unwrap_or_default()
+            } else {
+                godot_error!("Feature object-registry-compat not enabled!");
+                PackedInt64Array::new()
+            }
+        }
+    }
+
     /// Gets registered value in index. Only usable with object registry.
     #[func]
     fn registry_get(&self, _ix: i64) -> Variant {
@@ -1076,6 +3733,42 @@ impl WasmInstance {
         }
     }
 
+    /// Describes all registered values, for debugging. Only usable with object registry.
+    ///
+    /// Returns list of `{ "index", "type", "summary" }`.
+    #[func]
+    fn registry_describe(&self) -> Array<Dictionary> {
+        cfg_if! {
+            if #[cfg(feature = "object-registry-compat")] {
+                self.unwrap_data(|m| {
+                    m.acquire_store(|_, store| {
+                        let entries = store.data().get_registry()?.entries();
+                        Ok(entries
+                            .into_iter()
+                            .map(|(ix, v)| {
+                                let mut summary = v.to_string();
+                                if let Some((i, _)) = summary.char_indices().nth(REGISTRY_SUMMARY_LEN) {
+                                    summary.truncate(i);
+                                    summary.push_str("...");
+                                }
+
+                                let mut d = Dictionary::new();
+                                d.set(StringName::from(c"index"), ix as i64);
+                                d.set(StringName::from(c"type"), type_string(v.get_type().ord() as _));
+                                d.set(StringName::from(c"summary"), summary);
+                                d
+                            })
+                            .collect())
+                    })
+                })
+                .unwrap_or_default()
+            } else {
+                godot_error!("Feature object-registry-compat not enabled!");
+                Array::new()
+            }
+        }
+    }
+
     /// Sets registered value in index. Only usable with object registry.
     #[func]
     fn registry_set(&self, _ix: i64, _obj: Variant) -> Variant {
@@ -1128,7 +3821,7 @@ impl WasmInstance {
     /// Returns `true` if exported memory exists.
     #[func]
     fn has_memory(&self) -> bool {
-        self.unwrap_data(|m| m.acquire_store(|_, _| Ok(self.memory.is_some())))
+        self.unwrap_data(|m| m.acquire_store(|_, _| Ok(self.memory.read().is_some())))
             .unwrap_or_default()
     }
 
@@ -1137,28 +3830,31 @@ impl WasmInstance {
     /// Returns `true` if memory exists.
     ///
     /// Default exported memory name is `"memory"`.
+    /// Imported memory can be used with `"module.name"` as name.
+    /// Memory watches are re-synced to the new memory.
     #[func]
     fn memory_set_name(&self, name: GString) -> bool {
-        self.unwrap_data(|m| {
-            m.acquire_store(|m, store| {
-                // SAFETY: Nobody else can access memory
-                unsafe {
-                    *(ptr::addr_of!(self.memory) as *mut Option<MemoryType>) = match &m.instance {
-                        InstanceType::Core(inst) => match inst.get_export(store, &name.to_string())
-                        {
-                            Some(Extern::Memory(mem)) => Some(MemoryType::Memory(mem)),
-                            #[cfg(feature = "wasm-threads")]
-                            Some(Extern::SharedMemory(mem)) => Some(MemoryType::SharedMemory(mem)),
-                            _ => None,
-                        },
+        let r = self
+            .unwrap_data(|m| {
+                m.acquire_store(|m, store| {
+                    let memory = match &m.instance {
+                        InstanceType::Core(inst) => {
+                            lookup_memory(inst, store, &m.memory_imports, &name.to_string())
+                        }
                         #[allow(unreachable_patterns)]
                         _ => None,
                     };
-                }
-                Ok(self.memory.is_some())
+                    let ret = memory.is_some();
+                    *self.memory.write() = memory;
+                    self.memory_warned.store(false, Ordering::Relaxed);
+                    Ok(ret)
+                })
             })
-        })
-        .unwrap_or_default()
+            .unwrap_or_default();
+        if r {
+            self.resync_memory_state();
+        }
+        r
     }
 
     /// Inserts a line to stdin. Only usable with WASI.
@@ -1178,6 +3874,49 @@ impl WasmInstance {
         }
     }
 
+    /// Feeds content of a file to stdin, then closes it. Only usable with WASI.
+    ///
+    /// File is read up to its end at the time it is reached.
+    /// Returns `false` if file cannot be opened, or stdin is closed or already fed from a file.
+    #[func]
+    fn stdin_from_file(&self, _path: GString) -> bool {
+        cfg_if! {
+            if #[cfg(feature = "wasi")] {
+                self.unwrap_data(|m| {
+                    let Some(stdin) = &m.wasi_stdin else {
+                        bail_with_site!("Stdin is not bound to instance")
+                    };
+                    let Some(file) = FileAccess::open(_path.clone(), ModeFlags::READ) else {
+                        bail_with_site!("Cannot open file {_path} ({:?})", FileAccess::get_open_error())
+                    };
+
+                    stdin.set_reader(Box::new(FileReader(SendSyncWrapper::new(file))))
+                })
+                .is_some()
+            } else {
+                godot_error!("Feature wasi not enabled!");
+                false
+            }
+        }
+    }
+
+    /// Emits any output still held in buffered stdout/stderr pipes. Only usable with WASI.
+    #[func]
+    fn flush_wasi(&self) {
+        cfg_if! {
+            if #[cfg(feature = "wasi")] {
+                self.unwrap_data(|m| {
+                    for p in &m.wasi_buffers {
+                        p.flush_buffer();
+                    }
+                    Ok(())
+                });
+            } else {
+                godot_error!("Feature wasi not enabled!");
+            }
+        }
+    }
+
     /// Closes stdin. Only usable with WASI.
     #[func]
     fn stdin_close(&self) {
@@ -1195,10 +3934,72 @@ impl WasmInstance {
         }
     }
 
-    /// Returns memory size.
+    /// Returns memory size.
+    #[func]
+    fn memory_size(&self) -> i64 {
+        self.get_memory(|data| Ok(data.len() as i64))
+            .unwrap_or_default()
+    }
+
+    /// Returns declared maximum memory size in pages, or -1 if unbounded.
+    #[func]
+    fn memory_max_pages(&self) -> i64 {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, store| {
+                let max = match self.memory() {
+                    Some(MemoryType::Memory(mem)) => mem.ty(&store).maximum(),
+                    #[cfg(feature = "wasm-threads")]
+                    Some(MemoryType::SharedMemory(mem)) => mem.ty().maximum(),
+                    None => bail_with_site!("No memory exported"),
+                };
+                Ok(max.map_or(-1, |v| v.min(i64::MAX as u64) as i64))
+            })
+        })
+        .unwrap_or(-1)
+    }
+
+    /// Returns sizes of all exported memories, keyed by export name.
     #[func]
-    fn memory_size(&self) -> i64 {
-        self.get_memory(|data| Ok(data.len() as i64))
+    fn memory_sizes(&self) -> Dictionary {
+        self.unwrap_data(|m| {
+            m.acquire_store(|m, mut store| {
+                let mut ret = Dictionary::new();
+                let InstanceType::Core(inst) = &m.instance else {
+                    return Ok(ret);
+                };
+
+                let mems = inst
+                    .exports(&mut store)
+                    .filter_map(|e| {
+                        let name = e.name().to_string();
+                        match e.into_extern() {
+                            Extern::Memory(mem) => Some((name, MemoryType::Memory(mem))),
+                            #[cfg(feature = "wasm-threads")]
+                            Extern::SharedMemory(mem) => {
+                                Some((name, MemoryType::SharedMemory(mem)))
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                for (name, mem) in mems {
+                    let size = match mem {
+                        MemoryType::Memory(mem) => mem.data_size(&store),
+                        #[cfg(feature = "wasm-threads")]
+                        MemoryType::SharedMemory(mem) => mem.data_size(),
+                    };
+                    ret.set(name, size as i64);
+                }
+                Ok(ret)
+            })
+        })
+        .unwrap_or_default()
+    }
+
+    /// Gets copy of the whole memory.
+    #[func]
+    fn get_memory_bytes(&self) -> PackedByteArray {
+        self.get_memory(|data| Ok(PackedByteArray::from(&*data)))
             .unwrap_or_default()
     }
 
@@ -1220,6 +4021,87 @@ impl WasmInstance {
         .is_some()
     }
 
+    /// Copies memory into existing image, keeping it's size and format.
+    ///
+    /// Memory must contain raw image data, with the same size as image data.
+    /// Compressed formats are not supported.
+    #[func]
+    fn blit_to_image(&self, i: i64, mut img: Gd<Image>) -> bool {
+        let (w, h) = (img.get_width(), img.get_height());
+        let (mipmaps, format) = (img.has_mipmaps(), img.get_format());
+        let Some(n) =
+            image_pixel_size(format).and_then(|p| image_data_size(w as _, h as _, mipmaps, p))
+        else {
+            godot_error!("Unsupported image format {format:?}");
+            return false;
+        };
+        let Some(data) = self.read_memory(i as _, n, |s| Ok(PackedByteArray::from(s))) else {
+            return false;
+        };
+
+        img.set_data(w, h, mipmaps, format, data);
+        true
+    }
+
+    /// Watches a chunk of memory for changes.
+    ///
+    /// Returns watch ID, or -1 if failed.
+    #[func]
+    fn watch_memory(&self, i: i64, n: i64) -> i64 {
+        let (i, n) = (i as usize, n as usize);
+        self.get_memory_and_data(|data, store| {
+            let w = Some(MemoryWatch::new(data, i, n)?);
+
+            let watches = &mut store.memory_watches;
+            let id = match watches.iter().position(Option::is_none) {
+                Some(id) => {
+                    watches[id] = w;
+                    id
+                }
+                None => {
+                    watches.push(w);
+                    watches.len() - 1
+                }
+            };
+            Ok(id as i64)
+        })
+        .unwrap_or(-1)
+    }
+
+    /// Removes memory watch.
+    #[func]
+    fn unwatch_memory(&self, id: i64) -> bool {
+        self.unwrap_data(|m| {
+            m.acquire_store(|_, mut store| {
+                let watches = &mut store.data_mut().memory_watches;
+                match usize::try_from(id).ok().and_then(|id| watches.get_mut(id)) {
+                    Some(v @ Some(_)) => {
+                        *v = None;
+                        Ok(true)
+                    }
+                    _ => Ok(false),
+                }
+            })
+        })
+        .unwrap_or_default()
+    }
+
+    /// Returns IDs of watched memory that changed since last check.
+    #[func]
+    fn check_watches(&self) -> PackedInt64Array {
+        self.get_memory_and_data(|data, store| {
+            let mut ret = PackedInt64Array::new();
+            for (id, w) in store.memory_watches.iter_mut().enumerate() {
+                let Some(w) = w else { continue };
+                if w.changed(data)? {
+                    ret.push(id as _);
+                }
+            }
+            Ok(ret)
+        })
+        .unwrap_or_default()
+    }
+
     /// Reads an unsigned 8-bit integer.
     #[func]
     fn get_8(&self, i: i64) -> i64 {
@@ -1327,66 +4209,209 @@ impl WasmInstance {
     }
 
     /// Writes a `PackedArray`. Does not support `PackedStringArray`.
+    ///
+    /// `Array` of `Quaternion` is written as xyzw 32-bit float.
     #[func]
     fn put_array(&self, i: i64, v: Variant) -> bool {
         fn f<const N: usize, T: Sync>(
             d: &mut [u8],
             i: usize,
+            min_len: usize,
             s: &[T],
             f: impl Fn(&T, &mut [u8; N]) + Send + Sync,
-        ) -> AnyResult<()> {
-            let e = i + s.len() * N;
-            let Some(d) = d.get_mut(i..e) else {
+        ) -> AnyResult<usize> {
+            let e = i.wrapping_add(s.len() * N);
+            let Some(d) = i.checked_add(s.len() * N).and_then(|e| d.get_mut(i..e)) else {
                 bail_with_site!("Index out of range ({i}..{e})");
             };
 
             s.par_iter()
                 .zip(d.par_chunks_exact_mut(N))
+                .with_min_len(min_len)
                 .for_each(|(s, d)| f(s, d.try_into().unwrap()));
 
-            Ok(())
+            Ok(d.len())
         }
 
-        self.get_memory(|data| {
+        self.get_memory_and_data(|data, store| {
             let i = i as usize;
-            variant_dispatch!(v {
-                PACKED_BYTE_ARRAY => {
-                    let s = v.as_slice();
-                    let e = i + s.len();
-                    let Some(d) = data.get_mut(i..e) else {
-                        bail_with_site!("Index out of range ({i}..{e})");
-                    };
+            let m = min_chunk_len(store.single_threaded_memory_ops);
+            let n = match v.get_type() {
+                #[cfg(feature = "packed-vector4")]
+                VariantType::PACKED_VECTOR4_ARRAY => f(
+                    data,
+                    i,
+                    m,
+                    v.to::<PackedVector4Array>().as_slice(),
+                    <_ as StructPacking<f32>>::write_array,
+                ),
+                _ => variant_dispatch!(v {
+                    PACKED_BYTE_ARRAY => {
+                        let s = v.as_slice();
+                        let e = i.wrapping_add(s.len());
+                        let Some(d) = i.checked_add(s.len()).and_then(|e| data.get_mut(i..e)) else {
+                            bail_with_site!("Index out of range ({i}..{e})");
+                        };
 
-                    d.copy_from_slice(s);
-                    Ok(())
-                },
-                PACKED_INT32_ARRAY => f(data, i, v.as_slice(), |s, d| *d = s.to_le_bytes()),
-                PACKED_INT64_ARRAY => f(data, i, v.as_slice(), |s, d| *d = s.to_le_bytes()),
-                PACKED_FLOAT32_ARRAY => f(data, i, v.as_slice(), |s, d| *d = s.to_le_bytes()),
-                PACKED_FLOAT64_ARRAY => f(data, i, v.as_slice(), |s, d| *d = s.to_le_bytes()),
-                PACKED_VECTOR2_ARRAY => f(data, i, v.as_slice(), <_ as StructPacking<f32>>::write_array),
-                PACKED_VECTOR3_ARRAY => f(data, i, v.as_slice(), <_ as StructPacking<f32>>::write_array),
-                PACKED_COLOR_ARRAY => f(data, i, v.as_slice(), <_ as StructPacking<f32>>::write_array),
+                        d.copy_from_slice(s);
+                        Ok(s.len())
+                    },
+                    PACKED_INT32_ARRAY => f(data, i, m, v.as_slice(), |s, d| *d = s.to_le_bytes()),
+                    PACKED_INT64_ARRAY => f(data, i, m, v.as_slice(), |s, d| *d = s.to_le_bytes()),
+                    PACKED_FLOAT32_ARRAY => f(data, i, m, v.as_slice(), |s, d| *d = s.to_le_bytes()),
+                    PACKED_FLOAT64_ARRAY => f(data, i, m, v.as_slice(), |s, d| *d = s.to_le_bytes()),
+                    PACKED_VECTOR2_ARRAY => f(data, i, m, v.as_slice(), <_ as StructPacking<f32>>::write_array),
+                    PACKED_VECTOR3_ARRAY => f(data, i, m, v.as_slice(), <_ as StructPacking<f32>>::write_array),
+                    PACKED_COLOR_ARRAY => f(data, i, m, v.as_slice(), <_ as StructPacking<f32>>::write_array),
+                    ARRAY => {
+                        let s = v
+                            .iter_shared()
+                            .map(|v| site_context!(v.try_to::<Quaternion>()))
+                            .collect::<AnyResult<Vec<_>>>()?;
+                        f(data, i, m, &s, <_ as StructPacking<f32>>::write_array)
+                    },
+                    _ => bail_with_site!("Unknown value type {:?}", v.get_type()),
+                }),
+            }?;
+            MemoryStats::record(&mut store.memory_stats, n, true);
+            Ok(())
+        })
+        .is_some()
+    }
+
+    /// Writes a numeric `PackedArray`, converting each element to `target_type`.
+    ///
+    /// Target types: 0 = i8, 1 = u8, 2 = i16, 3 = u16, 4 = i32, 5 = u32,
+    /// 6 = i64, 7 = u64, 8 = f32, 9 = f64.
+    #[func]
+    fn put_array_as(&self, i: i64, v: Variant, target_type: i64) -> bool {
+        #[derive(Clone, Copy)]
+        enum Num {
+            Int(i64),
+            Float(f64),
+        }
+
+        impl Num {
+            /// Saturating conversion to integer. Floats are truncated, NaN becomes 0.
+            fn to_int(self, min: i64, max: i64) -> i64 {
+                match self {
+                    Self::Int(v) => v.clamp(min, max),
+                    Self::Float(v) => (v as i64).clamp(min, max),
+                }
+            }
+
+            fn to_u64(self) -> u64 {
+                match self {
+                    Self::Int(v) => v.max(0) as u64,
+                    Self::Float(v) => v as u64,
+                }
+            }
+
+            fn to_float(self) -> f64 {
+                match self {
+                    Self::Int(v) => v as f64,
+                    Self::Float(v) => v,
+                }
+            }
+        }
+
+        fn f<const N: usize, T: Sync>(
+            d: &mut [u8],
+            i: usize,
+            s: &[T],
+            min_len: usize,
+            conv: impl Fn(&T) -> Num + Send + Sync,
+            write: impl Fn(Num) -> [u8; N] + Send + Sync,
+        ) -> AnyResult<usize> {
+            let e = i.wrapping_add(s.len() * N);
+            let Some(d) = i.checked_add(s.len() * N).and_then(|e| d.get_mut(i..e)) else {
+                bail_with_site!("Index out of range ({i}..{e})");
+            };
+
+            s.par_iter()
+                .zip(d.par_chunks_exact_mut(N))
+                .with_min_len(min_len)
+                .for_each(|(s, d)| d.copy_from_slice(&write(conv(s))));
+
+            Ok(d.len())
+        }
+
+        fn g<T: Sync>(
+            d: &mut [u8],
+            i: usize,
+            s: &[T],
+            t: i64,
+            min_len: usize,
+            conv: impl Fn(&T) -> Num + Send + Sync,
+        ) -> AnyResult<usize> {
+            match t {
+                0 => f(d, i, s, min_len, conv, |v| {
+                    (v.to_int(i8::MIN as _, i8::MAX as _) as i8).to_le_bytes()
+                }),
+                1 => f(d, i, s, min_len, conv, |v| {
+                    (v.to_int(u8::MIN as _, u8::MAX as _) as u8).to_le_bytes()
+                }),
+                2 => f(d, i, s, min_len, conv, |v| {
+                    (v.to_int(i16::MIN as _, i16::MAX as _) as i16).to_le_bytes()
+                }),
+                3 => f(d, i, s, min_len, conv, |v| {
+                    (v.to_int(u16::MIN as _, u16::MAX as _) as u16).to_le_bytes()
+                }),
+                4 => f(d, i, s, min_len, conv, |v| {
+                    (v.to_int(i32::MIN as _, i32::MAX as _) as i32).to_le_bytes()
+                }),
+                5 => f(d, i, s, min_len, conv, |v| {
+                    (v.to_int(u32::MIN as _, u32::MAX as _) as u32).to_le_bytes()
+                }),
+                6 => f(d, i, s, min_len, conv, |v| {
+                    v.to_int(i64::MIN, i64::MAX).to_le_bytes()
+                }),
+                7 => f(d, i, s, min_len, conv, |v| v.to_u64().to_le_bytes()),
+                8 => f(d, i, s, min_len, conv, |v| {
+                    (v.to_float() as f32).to_le_bytes()
+                }),
+                9 => f(d, i, s, min_len, conv, |v| v.to_float().to_le_bytes()),
+                _ => bail_with_site!("Unknown target type {t}"),
+            }
+        }
+
+        self.get_memory_and_data(|data, store| {
+            let i = i as usize;
+            let t = target_type;
+            let m = min_chunk_len(store.single_threaded_memory_ops);
+            let n = variant_dispatch!(v {
+                PACKED_BYTE_ARRAY => g(data, i, v.as_slice(), t, m, |&v| Num::Int(v as _)),
+                PACKED_INT32_ARRAY => g(data, i, v.as_slice(), t, m, |&v| Num::Int(v as _)),
+                PACKED_INT64_ARRAY => g(data, i, v.as_slice(), t, m, |&v| Num::Int(v)),
+                PACKED_FLOAT32_ARRAY => g(data, i, v.as_slice(), t, m, |&v| Num::Float(v as _)),
+                PACKED_FLOAT64_ARRAY => g(data, i, v.as_slice(), t, m, |&v| Num::Float(v)),
                 _ => bail_with_site!("Unknown value type {:?}", v.get_type()),
-            })
+            })?;
+            MemoryStats::record(&mut store.memory_stats, n, true);
+            Ok(())
         })
         .is_some()
     }
 
     /// Reads a `PackedArray`. Does not support `PackedStringArray`.
+    ///
+    /// Type `QUATERNION` reads `Array[Quaternion]` of xyzw 32-bit float.
     #[func]
     fn get_array(&self, i: i64, n: i64, t: VariantType) -> Variant {
         fn f<const N: usize, R>(
             s: &[u8],
             i: usize,
             n: usize,
+            min_len: usize,
             f: impl Fn(&[u8; N]) -> R::Elem + Send + Sync,
-        ) -> AnyResult<Variant>
+        ) -> AnyResult<(Variant, usize)>
         where
             R: PackedArrayLike + ToGodot,
             R::Elem: Send,
         {
-            let e = i + n * N;
+            let Some(e) = n.checked_mul(N).and_then(|v| v.checked_add(i)) else {
+                bail_with_site!("Index out of range ({i}+{n}*{N})");
+            };
             let Some(s) = s.get(i..e) else {
                 bail_with_site!("Index out of range ({i}..{e})");
             };
@@ -1395,59 +4420,636 @@ impl WasmInstance {
             r.resize(n);
             s.par_chunks_exact(N)
                 .zip(r.as_mut_slice())
+                .with_min_len(min_len)
                 .for_each(|(s, d)| *d = f(s.try_into().unwrap()));
 
-            Ok(r.to_variant())
+            Ok((r.to_variant(), s.len()))
         }
 
-        option_to_variant(self.get_memory(|data| {
+        option_to_variant(self.get_memory_and_data(|data, store| {
             let data = &*data;
             let (i, n) = (i as usize, n as usize);
-            match t {
+            let m = min_chunk_len(store.single_threaded_memory_ops);
+            if let Some(max) = store.max_results {
+                if n > max {
+                    bail_with_site!("Too many elements (maximum {max}, got {n})");
+                }
+            }
+            let (ret, len) = match t {
                 VariantType::PACKED_BYTE_ARRAY => {
-                    let e = i + n;
-                    let Some(s) = data.get(i..e) else {
+                    let e = i.wrapping_add(n);
+                    let Some(s) = i.checked_add(n).and_then(|e| data.get(i..e)) else {
                         bail_with_site!("Index out of range ({i}..{e})");
                     };
 
-                    Ok(PackedByteArray::from(s).to_variant())
+                    Ok((PackedByteArray::from(s).to_variant(), n))
                 }
                 VariantType::PACKED_INT32_ARRAY => {
-                    f::<4, PackedInt32Array>(data, i, n, |s| i32::from_le_bytes(*s))
+                    f::<4, PackedInt32Array>(data, i, n, m, |s| i32::from_le_bytes(*s))
                 }
                 VariantType::PACKED_INT64_ARRAY => {
-                    f::<8, PackedInt64Array>(data, i, n, |s| i64::from_le_bytes(*s))
+                    f::<8, PackedInt64Array>(data, i, n, m, |s| i64::from_le_bytes(*s))
                 }
                 VariantType::PACKED_FLOAT32_ARRAY => {
-                    f::<4, PackedFloat32Array>(data, i, n, |s| f32::from_le_bytes(*s))
+                    f::<4, PackedFloat32Array>(data, i, n, m, |s| f32::from_le_bytes(*s))
                 }
                 VariantType::PACKED_FLOAT64_ARRAY => {
-                    f::<8, PackedFloat64Array>(data, i, n, |s| f64::from_le_bytes(*s))
+                    f::<8, PackedFloat64Array>(data, i, n, m, |s| f64::from_le_bytes(*s))
                 }
                 VariantType::PACKED_VECTOR2_ARRAY => {
-                    f::<8, PackedVector2Array>(data, i, n, <_ as StructPacking<f32>>::read_array)
-                }
-                VariantType::PACKED_VECTOR3_ARRAY => {
-                    f::<12, PackedVector3Array>(data, i, n, <_ as StructPacking<f32>>::read_array)
+                    f::<8, PackedVector2Array>(data, i, n, m, <_ as StructPacking<f32>>::read_array)
                 }
+                VariantType::PACKED_VECTOR3_ARRAY => f::<12, PackedVector3Array>(
+                    data,
+                    i,
+                    n,
+                    m,
+                    <_ as StructPacking<f32>>::read_array,
+                ),
                 VariantType::PACKED_COLOR_ARRAY => {
-                    f::<16, PackedColorArray>(data, i, n, <_ as StructPacking<f32>>::read_array)
+                    f::<16, PackedColorArray>(data, i, n, m, <_ as StructPacking<f32>>::read_array)
                 }
+                #[cfg(feature = "packed-vector4")]
+                VariantType::PACKED_VECTOR4_ARRAY => f::<16, PackedVector4Array>(
+                    data,
+                    i,
+                    n,
+                    m,
+                    <_ as StructPacking<f32>>::read_array,
+                ),
+                VariantType::QUATERNION => read_quaternions(data, i, 16, n, m),
                 _ => bail_with_site!("Unsupported type ID {t:?}"),
+            }?;
+            MemoryStats::record(&mut store.memory_stats, len, false);
+            Ok(ret)
+        }))
+    }
+
+    /// Reads multiple strided `PackedArray` at once.
+    ///
+    /// Stream `j` reads `count` elements of type `types[j]`,
+    /// starting at `bases[j]` and `strides[j]` bytes apart.
+    #[func]
+    fn read_soa(
+        &self,
+        bases: PackedInt64Array,
+        strides: PackedInt32Array,
+        count: i64,
+        types: PackedInt32Array,
+    ) -> Variant {
+        option_to_variant(self.get_memory_and_data(|data, store| {
+            let data = &*data;
+            let streams = soa_streams(bases.as_slice(), strides.as_slice(), types.as_slice())?;
+            let n = count as usize;
+            if let Some(max) = store.max_results {
+                if n > max {
+                    bail_with_site!("Too many elements (maximum {max}, got {n})");
+                }
+            }
+
+            let m = min_chunk_len(store.single_threaded_memory_ops);
+            let mut ret = VariantArray::new();
+            let mut len = 0;
+            for (i, stride, t) in streams {
+                let (v, l) = read_strided(data, i, stride, n, t, m)?;
+                ret.push(v);
+                len += l;
+            }
+            MemoryStats::record(&mut store.memory_stats, len, false);
+            Ok(ret)
+        }))
+    }
+
+    /// Reads a `PackedArray`, with elements `stride` bytes apart.
+    #[func]
+    fn get_array_strided(&self, i: i64, n: i64, t: VariantType, stride: i64) -> Variant {
+        option_to_variant(self.get_memory_and_data(|data, store| {
+            let (i, n) = (i as usize, n as usize);
+            if let Some(max) = store.max_results {
+                if n > max {
+                    bail_with_site!("Too many elements (maximum {max}, got {n})");
+                }
             }
+            let (ret, len) = read_strided(
+                data,
+                i,
+                stride as usize,
+                n,
+                t,
+                min_chunk_len(store.single_threaded_memory_ops),
+            )?;
+            MemoryStats::record(&mut store.memory_stats, len, false);
+            Ok(ret)
         }))
     }
 
+    /// Reads a `PackedArray` of the same type as `template`.
+    ///
+    /// Content of `template` is ignored.
+    #[func]
+    fn get_array_typed(&self, i: i64, n: i64, template: Variant) -> Variant {
+        self.get_array(i, n, template.get_type())
+    }
+
+    /// Reads `n` bits starting at bit offset `i`, one byte (0 or 1) per bit.
+    ///
+    /// Bits are numbered from the least significant bit of each byte.
+    #[func]
+    fn get_bitset(&self, i: i64, n: i64) -> PackedByteArray {
+        let (Ok(i), Ok(n)) = (usize::try_from(i), usize::try_from(n)) else {
+            godot_error!("Invalid bit range {i}-{}", i.wrapping_add(n));
+            return PackedByteArray::new();
+        };
+        let Some(end) = i.checked_add(n) else {
+            godot_error!("Invalid bit range {i}-{}", i.wrapping_add(n));
+            return PackedByteArray::new();
+        };
+        let (start, shift) = (i / 8, i % 8);
+        self.read_memory(start, end.div_ceil(8) - start, |s| {
+            Ok(PackedByteArray::from(&*unpack_bits(s, shift, n)))
+        })
+        .unwrap_or_default()
+    }
+
+    /// Writes bits starting at bit offset `i`, one byte per bit (nonzero is set).
+    ///
+    /// Other bits of the covering bytes are preserved.
+    #[func]
+    fn put_bitset(&self, i: i64, a: PackedByteArray) -> bool {
+        let a = a.as_slice();
+        let Ok(i) = usize::try_from(i) else {
+            godot_error!("Invalid bit offset {i}");
+            return false;
+        };
+        let Some(end) = i.checked_add(a.len()) else {
+            godot_error!("Invalid bit range {i}-{}", i.wrapping_add(a.len()));
+            return false;
+        };
+        let (start, shift) = (i / 8, i % 8);
+        self.write_memory(start, end.div_ceil(8) - start, |s| {
+            pack_bits(s, shift, a);
+            Ok(())
+        })
+        .is_some()
+    }
+
     /// Reads a structured data.
     #[func]
     fn read_struct(&self, format: GString, p: i64) -> Variant {
-        option_to_variant(self.get_memory(|data| read_struct(data, p as _, format.chars())))
+        option_to_variant(self.get_memory_and_data(|data, store| {
+            read_struct(data, p as _, format.chars(), store.max_struct_elements)
+        }))
+    }
+
+    /// Reads a structured data, along with the number of bytes consumed.
+    ///
+    /// Returns `{ "values", "size" }`. Size includes padding, so `p + size` is the next record.
+    #[func]
+    fn read_struct_sized(&self, format: GString, p: i64) -> Dictionary {
+        self.get_memory_and_data(|data, store| {
+            let size = struct_size(format.chars())?;
+            // Trailing padding is not checked while reading
+            if (p as usize)
+                .checked_add(size)
+                .map_or(true, |e| e > data.len())
+            {
+                bail_with_site!("Index out of range ({p}+{size})");
+            }
+            let values = read_struct(data, p as _, format.chars(), store.max_struct_elements)?;
+
+            let mut ret = Dictionary::new();
+            ret.set(StringName::from(c"values"), values);
+            ret.set(StringName::from(c"size"), size as i64);
+            Ok(ret)
+        })
+        .unwrap_or_default()
     }
 
     /// Writes a structured data.
     #[func]
     fn write_struct(&self, format: GString, p: i64, arr: VariantArray) -> i64 {
-        self.get_memory(|data| write_struct(data, p as _, format.chars(), arr))
-            .unwrap_or_default() as _
+        self.get_memory_and_data(|data, store| {
+            write_struct(data, p as _, format.chars(), arr, store.max_struct_elements)
+        })
+        .unwrap_or_default() as _
+    }
+
+    /// Writes a structured data from dictionary.
+    ///
+    /// Each value of `format` is taken from `d`, keyed by the corresponding entry of `keys`.
+    #[func]
+    fn write_dict_struct(
+        &self,
+        p: i64,
+        format: GString,
+        keys: PackedStringArray,
+        d: Dictionary,
+    ) -> i64 {
+        self.get_memory_and_data(|data, store| {
+            let arr = keys
+                .as_slice()
+                .iter()
+                .map(|k| match d.get(k.clone()) {
+                    Some(v) => Ok(v),
+                    None => bail_with_site!("Key {k} does not exist"),
+                })
+                .collect::<AnyResult<VariantArray>>()?;
+            write_struct(data, p as _, format.chars(), arr, store.max_struct_elements)
+        })
+        .unwrap_or_default() as _
+    }
+
+    /// Writes a value serialized with `var_to_bytes()`.
+    ///
+    /// Returns number of bytes written.
+    #[func]
+    fn write_variant_bytes(&self, i: i64, v: Variant) -> i64 {
+        let v = var_to_bytes(v);
+        let a = v.as_slice();
+        self.write_memory(i as _, a.len(), |s| {
+            s.copy_from_slice(a);
+            Ok(a.len() as i64)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Writes a scalar value with 1-byte type tag.
+    ///
+    /// Returns number of bytes written.
+    #[func]
+    fn put_tagged(&self, i: i64, v: Variant) -> i64 {
+        let Some(v) = self.unwrap_data(|_| encode_tagged(&v)) else {
+            return 0;
+        };
+        self.write_memory(i as _, v.len(), |s| {
+            s.copy_from_slice(&v);
+            Ok(v.len() as i64)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Reads a scalar value written by `put_tagged()`.
+    #[func]
+    fn get_tagged(&self, i: i64) -> Variant {
+        self.get_memory(|data| decode_tagged(data, i as _))
+            .unwrap_or_default()
+    }
+
+    /// Reads a value serialized with `var_to_bytes()`.
+    #[func]
+    fn read_variant_bytes(&self, i: i64, n: i64) -> Variant {
+        self.read_memory(i as _, n as _, |s| Ok(PackedByteArray::from(s)))
+            .map(bytes_to_var)
+            .unwrap_or_default()
+    }
+
+    /// Reads a little-endian UTF-16 string of `n` code units.
+    ///
+    /// Invalid sequences are replaced with U+FFFD.
+    #[func]
+    fn read_utf16(&self, p: i64, n: i64) -> GString {
+        let Some(len) = usize::try_from(n).ok().and_then(|n| n.checked_mul(2)) else {
+            godot_error!("Invalid string length {n}");
+            return GString::new();
+        };
+        self.read_memory(p as _, len, |s| Ok(gstring_from_utf16_le(s)))
+            .unwrap_or_default()
+    }
+
+    /// Reads an AssemblyScript string.
+    ///
+    /// Byte length is stored as 32-bit integer right before the string.
+    #[func]
+    fn read_as_string(&self, p: i64) -> GString {
+        self.get_memory(|data| Ok(gstring_from_utf16_le(as_string_bytes(data, p)?)))
+            .unwrap_or_default()
+    }
+
+    /// Writes a string as little-endian UTF-16.
+    ///
+    /// Returns number of code units written.
+    #[func]
+    fn write_utf16(&self, p: i64, v: GString) -> i64 {
+        let v = utf16_le_from_str(&v.to_string());
+        self.write_memory(p as _, v.len(), |s| {
+            s.copy_from_slice(&v);
+            Ok((v.len() / 2) as i64)
+        })
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "fuel")]
+    #[test]
+    fn test_fuel_limit() {
+        assert_eq!(fuel_limit(None, 1.0), u64::MAX);
+        assert_eq!(fuel_limit(Some(1000), 1.0), 1000);
+        assert_eq!(fuel_limit(Some(1000), 2.0), 500);
+        assert_eq!(fuel_limit(Some(1000), 0.5), 2000);
+        assert_eq!(fuel_limit(Some(1000), 0.0), u64::MAX);
+        assert_eq!(fuel_limit(Some(1000), f64::INFINITY), 0);
+        assert_eq!(fuel_limit(Some(u64::MAX), 0.25), u64::MAX);
+    }
+
+    #[test]
+    fn test_host_call_reentry() {
+        let mut data = StoreData {
+            max_host_reentry: Some(2),
+            ..StoreData::default()
+        };
+        assert_eq!(data.host_call(|| Ok(1)).unwrap(), 1);
+        assert_eq!(data.host_depth, 0);
+
+        data.host_depth = 2;
+        let e = data.host_call(|| Ok(())).unwrap_err();
+        assert!(e.to_string().contains("maximum 2"), "{e}");
+        assert_eq!(data.host_depth, 2);
+
+        data.host_depth = 1;
+        assert!(data
+            .host_call(|| -> AnyResult<()> { bail!("fail") })
+            .is_err());
+        assert_eq!(data.host_depth, 1);
+
+        data.max_host_reentry = None;
+        data.host_depth = 100;
+        assert!(data.host_call(|| Ok(())).is_ok());
+        assert_eq!(data.host_depth, 100);
+    }
+
+    #[test]
+    fn test_memory_switch() {
+        const WAT: &str = r#"
+        (module
+          (import "env" "mem" (memory 1))
+          (memory (export "memory") 1))
+        "#;
+
+        let engine = wasmtime::Engine::default();
+        let module = Module::new(&engine, WAT).unwrap();
+        let mut store = Store::new(&engine, ());
+        let imported = Memory::new(&mut store, wasmtime::MemoryType::new(1, None)).unwrap();
+        let inst = InstanceWasm::new(&mut store, &module, &[imported.into()]).unwrap();
+        let imports = HashMap::from([("env.mem".to_string(), Extern::from(imported))]);
+
+        let Some(MemoryType::Memory(old)) = lookup_memory(&inst, &mut store, &imports, "memory")
+        else {
+            panic!("exported memory not found")
+        };
+        let Some(MemoryType::Memory(new)) = lookup_memory(&inst, &mut store, &imports, "env.mem")
+        else {
+            panic!("imported memory not found")
+        };
+        assert!(lookup_memory(&inst, &mut store, &imports, "mem").is_none());
+        assert_eq!(new.data_ptr(&store), imported.data_ptr(&store));
+
+        old.data_mut(&mut store)[..4].copy_from_slice(&[1, 2, 3, 4]);
+        new.data_mut(&mut store)[..4].copy_from_slice(&[5, 6, 7, 8]);
+        let mut w = MemoryWatch::new(old.data(&store), 0, 4).unwrap();
+
+        // After switching, watch is rehashed and reads come from new memory.
+        assert!(w.resync(new.data(&store)));
+        assert_eq!(memory_slice(new.data(&store), 0, 4).unwrap(), [5, 6, 7, 8]);
+        assert!(!w.changed(new.data(&store)).unwrap());
+        old.data_mut(&mut store)[0] = 0;
+        assert!(!w.changed(new.data(&store)).unwrap());
+        new.data_mut(&mut store)[0] = 0;
+        assert!(w.changed(new.data(&store)).unwrap());
+        assert!(!w.changed(new.data(&store)).unwrap());
+
+        let mut w = MemoryWatch::new(new.data(&store), 65534, 2).unwrap();
+        assert!(!w.resync(&[0; 16]));
+        assert!(w.changed(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_nested_call_depth() {
+        const WAT: &str = r#"
+        (module
+          (import "host" "rec" (func $rec (param i32) (result i32)))
+          (func (export "rec") (param i32) (result i32)
+            local.get 0
+            i32.eqz
+            if (result i32)
+              i32.const 0
+            else
+              local.get 0
+              i32.const 1
+              i32.sub
+              call $rec
+              i32.const 1
+              i32.add
+            end))
+        "#;
+
+        fn call_rec(mut store: StoreContextMut<'_, StoreData>, f: Func, n: i32) -> AnyResult<i32> {
+            nested_call(store.as_context_mut(), |mut store| {
+                f.typed::<i32, i32>(&store)?.call(&mut store, n)
+            })
+        }
+
+        let engine = wasmtime::Engine::default();
+        let module = Module::new(&engine, WAT).unwrap();
+        let mut store = Store::new(&engine, StoreData::default());
+        // Host import recurses back into guest.
+        let host = Func::wrap(
+            &mut store,
+            |mut caller: wasmtime::Caller<'_, StoreData>, n: i32| -> AnyResult<i32> {
+                let f = caller
+                    .get_export("rec")
+                    .and_then(Extern::into_func)
+                    .unwrap();
+                call_rec(caller.as_context_mut(), f, n)
+            },
+        );
+        let inst = InstanceWasm::new(&mut store, &module, &[host.into()]).unwrap();
+        let f = inst.get_func(&mut store, "rec").unwrap();
+
+        let mut run = |max, n| {
+            store.data_mut().max_call_depth = max;
+            let r = call_rec(store.as_context_mut(), f, n);
+            assert_eq!(store.data().call_depth, 0);
+            r
+        };
+        assert_eq!(run(None, 10).unwrap(), 10);
+        assert_eq!(run(Some(3), 2).unwrap(), 2);
+        let e = run(Some(3), 3).unwrap_err();
+        assert!(
+            format!("{e:?}").contains("Call depth limit exceeded (maximum 3)"),
+            "{e:?}"
+        );
+        assert!(run(Some(0), 0).is_err());
+    }
+
+    #[test]
+    fn test_memory_slice() {
+        let mut data = [0u8, 1, 2, 3];
+        assert_eq!(memory_slice(&data, 1, 2).unwrap(), [1, 2]);
+        assert!(memory_slice(&data, 4, 0).unwrap().is_empty());
+        assert!(memory_slice(&data, 3, 2).is_err());
+        assert!(memory_slice(&data, usize::MAX, 2).is_err());
+        assert!(memory_slice_mut(&mut data, 2, usize::MAX).is_err());
+        memory_slice_mut(&mut data, 2, 2).unwrap().fill(9);
+        assert_eq!(data, [0, 1, 9, 9]);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_memory_slice_memory64() {
+        const WAT: &str = r#"
+        (module
+          (memory (export "memory") i64 65537)
+          (func (export "load") (param i64) (result i32)
+            local.get 0
+            i32.load))
+        "#;
+        const OFFSET: usize = 0x1_0000_0008;
+
+        let mut config = wasmtime::Config::new();
+        config.wasm_memory64(true);
+        let engine = wasmtime::Engine::new(&config).unwrap();
+        let module = Module::new(&engine, WAT).unwrap();
+        let mut store = Store::new(&engine, ());
+        let inst = InstanceWasm::new(&mut store, &module, &[]).unwrap();
+        let mem = inst.get_memory(&mut store, "memory").unwrap();
+
+        // Offset past 4 GiB must not be truncated to 32 bits.
+        let data = mem.data_mut(&mut store);
+        assert_eq!(data.len(), 65537 << 16);
+        memory_slice_mut(data, OFFSET, 4)
+            .unwrap()
+            .copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(memory_slice(data, OFFSET & 0xffff_ffff, 4).unwrap(), [0; 4]);
+        assert!(memory_slice(data, data.len() - 2, 4).is_err());
+
+        let load = inst.get_typed_func::<i64, i32>(&mut store, "load").unwrap();
+        assert_eq!(load.call(&mut store, OFFSET as i64).unwrap(), 0x0403_0201);
+    }
+
+    #[test]
+    fn test_soa_streams() {
+        let v = soa_streams(
+            &[0, 16],
+            &[8, 12],
+            &[
+                VariantType::PACKED_FLOAT32_ARRAY.ord(),
+                VariantType::PACKED_VECTOR3_ARRAY.ord(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            v,
+            [
+                (0, 8, VariantType::PACKED_FLOAT32_ARRAY),
+                (16, 12, VariantType::PACKED_VECTOR3_ARRAY),
+            ]
+        );
+        assert!(soa_streams(&[], &[], &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_soa_streams_error() {
+        let t = VariantType::PACKED_BYTE_ARRAY.ord();
+        assert!(soa_streams(&[0, 1], &[1], &[t, t]).is_err());
+        assert!(soa_streams(&[0], &[1], &[t, t]).is_err());
+        assert!(soa_streams(&[0], &[1], &[-1]).is_err());
+    }
+
+    #[test]
+    fn test_strided_slice() {
+        let s = (0..32).collect::<Vec<u8>>();
+        assert_eq!(strided_slice(&s, 2, 8, 3, 4).unwrap(), &s[2..22]);
+        assert_eq!(strided_slice(&s, 0, 16, 2, 16).unwrap(), &s[..]);
+        assert!(strided_slice(&s, 40, 4, 0, 4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_strided_slice_error() {
+        let s = [0u8; 32];
+        // Overlapping elements.
+        assert!(strided_slice(&s, 0, 2, 4, 4).is_err());
+        assert!(strided_slice(&s, 0, 2, 0, 4).is_err());
+        // Out of bounds.
+        assert!(strided_slice(&s, 1, 16, 2, 16).is_err());
+        assert!(strided_slice(&s, 0, usize::MAX, 3, 4).is_err());
+        assert!(strided_slice(&s, usize::MAX, 4, 1, 4).is_err());
+    }
+
+    #[test]
+    fn test_unpack_bits() {
+        let s = [0b1010_0101u8, 0b0000_1111];
+        assert_eq!(unpack_bits(&s, 0, 8), [1, 0, 1, 0, 0, 1, 0, 1]);
+        assert_eq!(unpack_bits(&s, 6, 6), [0, 1, 1, 1, 1, 1]);
+        assert!(unpack_bits(&s, 3, 0).is_empty());
+    }
+
+    #[test]
+    fn test_pack_bits() {
+        let mut s = [0b1111_0000u8, 0b1111_0000];
+        pack_bits(&mut s, 6, &[1, 0, 2, 0]);
+        assert_eq!(s, [0b0111_0000, 0b1111_0001]);
+
+        let mut s = [0u8; 2];
+        let a = [1, 1, 0, 1, 0, 0, 1, 0, 1, 1];
+        pack_bits(&mut s, 3, &a);
+        assert_eq!(unpack_bits(&s, 3, a.len()), a);
+        assert_eq!(s[0] & 0b111, 0);
+    }
+
+    #[test]
+    fn test_thread_safe_source() {
+        for s in [
+            "rand",
+            "event",
+            "constants",
+            "epoch",
+            "memory",
+            "module:123",
+        ] {
+            assert!(is_thread_safe_source(s), "{s}");
+        }
+        for s in [
+            "host",
+            "wasi",
+            "fallback",
+            "objregistry",
+            "externref",
+            "time",
+            "yield",
+        ] {
+            assert!(!is_thread_safe_source(s), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_as_string_bytes() {
+        let mut data = vec![0xffu8; 4];
+        data.extend(6u32.to_le_bytes());
+        data.extend(b"a\0b\0c\0");
+        data.extend(0u32.to_le_bytes());
+
+        assert_eq!(as_string_bytes(&data, 8).unwrap(), b"a\0b\0c\0");
+        assert_eq!(as_string_bytes(&data, 18).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_as_string_bytes_error() {
+        let mut data = 9u32.to_le_bytes().to_vec();
+        data.extend(b"abcd");
+        data.extend((-1i32).to_le_bytes());
+
+        // Pointer before header.
+        assert!(as_string_bytes(&data, 2).is_err());
+        assert!(as_string_bytes(&data, -4).is_err());
+        // Header out of bounds.
+        assert!(as_string_bytes(&data, 100).is_err());
+        // Length past end of memory.
+        assert!(as_string_bytes(&data, 4).is_err());
+        // Negative length.
+        assert!(as_string_bytes(&data, 12).is_err());
     }
 }
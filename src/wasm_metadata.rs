@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use anyhow::Result as AnyResult;
+#[cfg(feature = "module-metadata")]
+use wasmparser::{
+    ConstExpr, DataKind, ExternalKind, KnownCustom, Name, NameSectionReader, Operator, Parser,
+    Payload, ProducersSectionReader,
+};
+
+use crate::bail_with_site;
+#[cfg(feature = "module-metadata")]
+use crate::site_context;
+
+/// Extra module data that wasmtime does not retain after compilation.
+///
+/// It is parsed from the module binary, so it's only available
+/// if module is compiled from source (not deserialized) with `module-metadata` feature.
+#[derive(Default, Clone, Debug)]
+pub struct ModuleMetadata {
+    pub memory_exports: HashMap<String, u32>,
+    pub func_exports: HashMap<String, u32>,
+    pub data_segments: Vec<DataSegment>,
+    /// Local names of exported functions, from the "name" section.
+    pub local_names: HashMap<u32, HashMap<u32, String>>,
+    /// Fields of "producers" section, each is a list of (name, version).
+    pub producers: Vec<(String, Vec<(String, String)>)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DataSegment {
+    pub memory: u32,
+    /// `None` if offset is not a constant (eg. uses a global).
+    pub offset: Option<u64>,
+    pub len: u64,
+}
+
+#[cfg(feature = "module-metadata")]
+impl ModuleMetadata {
+    pub fn parse(bytes: &[u8]) -> AnyResult<Self> {
+        let mut ret = Self::default();
+
+        for payload in Parser::new(0).parse_all(bytes) {
+            match site_context!(payload)? {
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = site_context!(export)?;
+                        match export.kind {
+                            ExternalKind::Memory => {
+                                ret.memory_exports
+                                    .insert(export.name.to_string(), export.index);
+                            }
+                            ExternalKind::Func => {
+                                ret.func_exports
+                                    .insert(export.name.to_string(), export.index);
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                Payload::DataSection(reader) => {
+                    for data in reader {
+                        let data = site_context!(data)?;
+                        let DataKind::Active {
+                            memory_index,
+                            offset_expr,
+                        } = data.kind
+                        else {
+                            continue;
+                        };
+
+                        ret.data_segments.push(DataSegment {
+                            memory: memory_index,
+                            offset: const_offset(&offset_expr)?,
+                            len: data.data.len() as _,
+                        });
+                    }
+                }
+                Payload::CustomSection(reader) => match reader.as_known() {
+                    // Malformed custom sections should not prevent loading module.
+                    KnownCustom::Name(reader) => {
+                        let _ = ret.parse_names(reader);
+                    }
+                    KnownCustom::Producers(reader) => {
+                        let _ = ret.parse_producers(reader);
+                    }
+                    _ => (),
+                },
+                _ => (),
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn parse_names(&mut self, reader: NameSectionReader<'_>) -> AnyResult<()> {
+        for name in reader {
+            let Name::Local(map) = site_context!(name)? else {
+                continue;
+            };
+
+            for f in map {
+                let f = site_context!(f)?;
+                if !self.func_exports.values().any(|&i| i == f.index) {
+                    continue;
+                }
+
+                let names = f
+                    .names
+                    .into_iter()
+                    .map(|n| n.map(|n| (n.index, n.name.to_string())))
+                    .collect::<Result<_, _>>();
+                self.local_names.insert(f.index, site_context!(names)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_producers(&mut self, reader: ProducersSectionReader<'_>) -> AnyResult<()> {
+        for field in reader {
+            let field = site_context!(field)?;
+            let values = field
+                .values
+                .into_iter()
+                .map(|v| v.map(|v| (v.name.to_string(), v.version.to_string())))
+                .collect::<Result<_, _>>();
+            self.producers
+                .push((field.name.to_string(), site_context!(values)?));
+        }
+
+        Ok(())
+    }
+}
+
+/// Gets value of constant offset expression.
+///
+/// Returns `None` unless it is a single `i32.const` or `i64.const`,
+/// as other expressions (eg. using global) cannot be known before instantiation.
+#[cfg(feature = "module-metadata")]
+fn const_offset(expr: &ConstExpr<'_>) -> AnyResult<Option<u64>> {
+    let ops = site_context!(expr
+        .get_operators_reader()
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>())?;
+    Ok(match ops[..] {
+        [Operator::I32Const { value }, Operator::End] => Some(value as u32 as u64),
+        [Operator::I64Const { value }, Operator::End] => Some(value as u64),
+        _ => None,
+    })
+}
+
+impl ModuleMetadata {
+    /// Gets parameter names of exported function.
+    pub fn param_names(&self, name: &str, n_params: usize) -> AnyResult<Vec<&str>> {
+        let Some(names) = self
+            .func_exports
+            .get(name)
+            .and_then(|i| self.local_names.get(i))
+        else {
+            bail_with_site!("No parameter names for function {name}")
+        };
+
+        (0..n_params as u32)
+            .map(|i| match names.get(&i) {
+                Some(v) => Ok(&**v),
+                None => bail_with_site!("Parameter {i} of function {name} is unnamed"),
+            })
+            .collect()
+    }
+
+    pub fn segments_of_memory<'a>(
+        &'a self,
+        name: &str,
+    ) -> impl Iterator<Item = &'a DataSegment> + 'a {
+        let ix = self.memory_exports.get(name).copied();
+        self.data_segments
+            .iter()
+            .filter(move |s| Some(s.memory) == ix)
+    }
+}
+
+#[cfg(all(test, feature = "module-metadata"))]
+mod tests {
+    use super::*;
+
+    const WAT: &str = r#"
+(module
+  (import "host" "base" (global $base i32))
+  (memory (export "memory") 1)
+  (memory (export "memory64") i64 1)
+  (memory 1)
+  (data (i32.const 16) "abcd")
+  (data (i32.const -1) "x")
+  (data (memory 1) (i64.const 32) "ef")
+  (data (global.get $base) "ghi")
+  (data (memory 2) (i32.const 0) "j")
+  (data "passive")
+  (func (export "add") (param $a i32) (param $b i32) (result i32)
+    (local $c i32)
+    local.get $a
+    local.get $b
+    i32.add)
+  (func (export "anon") (param i32))
+)
+"#;
+
+    fn parse() -> ModuleMetadata {
+        ModuleMetadata::parse(&wat::parse_str(WAT).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_data_segments() {
+        let m = parse();
+        let v = m
+            .data_segments
+            .iter()
+            .map(|s| (s.memory, s.offset, s.len))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            v,
+            [
+                (0, Some(16), 4),
+                (0, Some(0xffff_ffff), 1),
+                (1, Some(32), 2),
+                (0, None, 3),
+                (2, Some(0), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_segments_of_memory() {
+        let m = parse();
+        let f = |name| {
+            m.segments_of_memory(name)
+                .map(|s| s.offset)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(f("memory"), [Some(16), Some(0xffff_ffff), None]);
+        assert_eq!(f("memory64"), [Some(32)]);
+        assert!(f("unknown").is_empty());
+    }
+
+    #[test]
+    fn test_param_names() {
+        let m = parse();
+        assert_eq!(m.param_names("add", 2).unwrap(), ["a", "b"]);
+        assert_eq!(m.param_names("add", 3).unwrap(), ["a", "b", "c"]);
+        assert!(m.param_names("anon", 1).is_err());
+        assert!(m.param_names("unknown", 0).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Command buffer for batched object calls.
+//!
+//! Buffer is a sequence of commands, all integers are little-endian `u32`:
+//! - `0x00` : End of buffer (optional).
+//! - `0x01 obj name_len name[name_len] argc args[argc]` : Call method, discard result.
+//! - `0x02 obj name_len name[name_len] argc args[argc] dst` : Call method,
+//!   replace registry slot `dst` with result.
+//! - `0x03 obj name_len name[name_len] value` : Set property.
+//!
+//! Opcodes are single bytes. Objects and values are object registry indices.
+
+use std::str::from_utf8;
+
+use anyhow::Result as AnyResult;
+use godot::prelude::*;
+use wasmtime::{Caller, Extern, Func, StoreContextMut};
+
+use crate::wasm_instance::StoreData;
+use crate::{bail_with_site, func_registry, site_context};
+
+const OP_END: u8 = 0x00;
+const OP_CALL: u8 = 0x01;
+const OP_CALL_STORE: u8 = 0x02;
+const OP_SET: u8 = 0x03;
+
+#[derive(Debug, PartialEq)]
+enum Command<'a> {
+    Call {
+        obj: u32,
+        name: &'a str,
+        args: Vec<u32>,
+        dst: Option<u32>,
+    },
+    Set {
+        obj: u32,
+        name: &'a str,
+        value: u32,
+    },
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let (&v, rest) = self.buf.split_first()?;
+        self.buf = rest;
+        Some(v)
+    }
+
+    fn u32(&mut self) -> AnyResult<u32> {
+        match self.bytes(4) {
+            Ok(v) => Ok(u32::from_le_bytes(v.try_into().unwrap())),
+            Err(_) => bail_with_site!("Unexpected end of command buffer"),
+        }
+    }
+
+    fn bytes(&mut self, n: usize) -> AnyResult<&'a [u8]> {
+        if n > self.buf.len() {
+            bail_with_site!("Unexpected end of command buffer");
+        }
+        let (v, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(v)
+    }
+
+    fn name(&mut self) -> AnyResult<&'a str> {
+        let n = self.u32()? as usize;
+        site_context!(from_utf8(self.bytes(n)?))
+    }
+
+    /// Decodes next command, returns `None` at end of buffer.
+    fn command(&mut self) -> AnyResult<Option<Command<'a>>> {
+        let op = match self.u8() {
+            None | Some(OP_END) => return Ok(None),
+            Some(v) => v,
+        };
+        Ok(Some(match op {
+            OP_CALL | OP_CALL_STORE => {
+                let obj = self.u32()?;
+                let name = self.name()?;
+                let argc = self.u32()? as usize;
+                let args = (0..argc)
+                    .map(|_| self.u32())
+                    .collect::<AnyResult<Vec<_>>>()?;
+                let dst = if op == OP_CALL_STORE {
+                    Some(self.u32()?)
+                } else {
+                    None
+                };
+                Command::Call {
+                    obj,
+                    name,
+                    args,
+                    dst,
+                }
+            }
+            OP_SET => Command::Set {
+                obj: self.u32()?,
+                name: self.name()?,
+                value: self.u32()?,
+            },
+            op => bail_with_site!("Unknown command opcode {op:#04x}"),
+        }))
+    }
+}
+
+func_registry! {
+    (CommandFuncs, ""),
+    flush_commands => |mut ctx: Caller<'_, T>, p: u32, n: u32| -> AnyResult<u32> {
+        let mem = match ctx.get_export("memory") {
+            Some(Extern::Memory(v)) => v,
+            _ => bail_with_site!("No memory exported"),
+        };
+
+        let (p, n) = (p as usize, n as usize);
+        // Copy buffer, as calls may reenter WASM.
+        let buf = match p.checked_add(n).and_then(|e| mem.data(&ctx).get(p..e)) {
+            Some(v) => v.to_vec(),
+            None => bail_with_site!("Invalid memory bounds ({}-{})", p, p.wrapping_add(n)),
+        };
+
+        let mut r = Reader { buf: &buf };
+        let mut ret = 0u32;
+        while let Some(cmd) = r.command()? {
+            match cmd {
+                Command::Call {
+                    obj,
+                    name,
+                    args,
+                    dst,
+                } => {
+                    let reg = ctx.data().as_ref().get_registry()?;
+                    let mut obj = site_context!(reg.get_or_nil(obj as _).try_to::<Gd<Object>>())?;
+                    let args = args
+                        .into_iter()
+                        .map(|i| reg.get_or_nil(i as _))
+                        .collect::<Vec<_>>();
+
+                    let name = StringName::from(name);
+                    let v = ctx.data_mut().as_mut().release_store(|| obj.try_call(name, &args));
+                    let v = site_context!(v)?;
+
+                    if let Some(dst) = dst {
+                        ctx.data_mut().as_mut().get_registry_mut()?.replace(dst as _, v);
+                    }
+                }
+                Command::Set { obj, name, value } => {
+                    let reg = ctx.data().as_ref().get_registry()?;
+                    let mut obj = site_context!(reg.get_or_nil(obj as _).try_to::<Gd<Object>>())?;
+                    let v = reg.get_or_nil(value as _);
+
+                    let name = StringName::from(name);
+                    ctx.data_mut().as_mut().release_store(|| obj.set(name, v));
+                }
+            }
+            ret += 1;
+        }
+
+        Ok(ret)
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(buf: &mut Vec<u8>, s: &str) {
+        buf.extend((s.len() as u32).to_le_bytes());
+        buf.extend(s.as_bytes());
+    }
+
+    fn u32s(buf: &mut Vec<u8>, v: &[u32]) {
+        for i in v {
+            buf.extend(i.to_le_bytes());
+        }
+    }
+
+    fn decode(buf: &[u8]) -> AnyResult<Vec<Command<'_>>> {
+        let mut r = Reader { buf };
+        let mut ret = Vec::new();
+        while let Some(v) = r.command()? {
+            ret.push(v);
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn test_decode_commands() {
+        let mut buf = vec![OP_CALL];
+        u32s(&mut buf, &[1]);
+        name(&mut buf, "foo");
+        u32s(&mut buf, &[2, 3, 4]);
+        buf.push(OP_CALL_STORE);
+        u32s(&mut buf, &[5]);
+        name(&mut buf, "bar");
+        u32s(&mut buf, &[0, 6]);
+        buf.push(OP_SET);
+        u32s(&mut buf, &[7]);
+        name(&mut buf, "baz");
+        u32s(&mut buf, &[8]);
+
+        assert_eq!(
+            decode(&buf).unwrap(),
+            [
+                Command::Call {
+                    obj: 1,
+                    name: "foo",
+                    args: vec![3, 4],
+                    dst: None,
+                },
+                Command::Call {
+                    obj: 5,
+                    name: "bar",
+                    args: vec![],
+                    dst: Some(6),
+                },
+                Command::Set {
+                    obj: 7,
+                    name: "baz",
+                    value: 8,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_end() {
+        let mut buf = vec![OP_SET];
+        u32s(&mut buf, &[1]);
+        name(&mut buf, "a");
+        u32s(&mut buf, &[2]);
+        buf.extend([OP_END, 0xff]);
+
+        assert_eq!(decode(&buf).unwrap().len(), 1);
+        assert!(decode(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decode_error() {
+        // Truncated argument list.
+        let mut buf = vec![OP_CALL];
+        u32s(&mut buf, &[1]);
+        name(&mut buf, "foo");
+        u32s(&mut buf, &[2, 3]);
+        assert!(decode(&buf).is_err());
+
+        // Name length out of bounds.
+        let mut buf = vec![OP_SET];
+        u32s(&mut buf, &[1, 100]);
+        assert!(decode(&buf).is_err());
+
+        // Invalid UTF-8 name.
+        let mut buf = vec![OP_SET];
+        u32s(&mut buf, &[1, 1]);
+        buf.push(0xff);
+        u32s(&mut buf, &[2]);
+        assert!(decode(&buf).is_err());
+
+        // Unknown opcode.
+        assert!(decode(&[0x04]).is_err());
+    }
+}
@@ -1,58 +1,91 @@
+mod commands;
 mod funcs;
 
 use std::mem;
+use std::sync::Arc;
 
 use godot::prelude::*;
+use parking_lot::Mutex;
 use slab::Slab;
 
+pub use commands::CommandFuncs;
 pub use funcs::Funcs;
 
 use crate::godot_util::SendSyncWrapper;
 
+/// Object registry, mapping indices into Godot values.
+///
+/// Cloning it shares the underlying storage.
+#[derive(Clone)]
 pub struct ObjectRegistry {
-    slab: Slab<SendSyncWrapper<Variant>>,
+    slab: Arc<Mutex<Slab<SendSyncWrapper<Variant>>>>,
+    max_entries: Option<usize>,
+    on_overflow: Option<Arc<dyn Fn(usize) + Send + Sync>>,
 }
 
 impl Default for ObjectRegistry {
     #[inline]
     fn default() -> Self {
-        Self { slab: Slab::new() }
+        Self {
+            slab: Arc::new(Mutex::new(Slab::new())),
+            max_entries: None,
+            on_overflow: None,
+        }
     }
 }
 
 impl ObjectRegistry {
+    /// Limits number of entries, calling `f` with the refused index on overflow.
+    ///
+    /// `f` is called synchronously, possibly with store locked and in any thread.
+    pub fn set_limit(&mut self, max: usize, f: impl Fn(usize) + Send + Sync + 'static) {
+        self.max_entries = Some(max);
+        self.on_overflow = Some(Arc::new(f));
+    }
+
     #[inline]
     pub fn get(&self, ix: usize) -> Option<Variant> {
         match ix.checked_sub(1) {
-            Some(ix) => self.slab.get(ix).map(|v| &**v).cloned(),
+            Some(ix) => self.slab.lock().get(ix).map(|v| &**v).cloned(),
             None => None,
         }
     }
 
+    /// Registers value. Returns 0 if value is nil or registry is full.
     #[inline]
-    pub fn register(&mut self, v: Variant) -> usize {
+    pub fn register(&self, v: Variant) -> usize {
         if v.is_nil() {
-            0
-        } else {
-            self.slab.insert(SendSyncWrapper::new(v)) + 1
+            return 0;
+        }
+
+        let mut slab = self.slab.lock();
+        if self.max_entries.is_some_and(|max| slab.len() >= max) {
+            let ix = slab.vacant_key() + 1;
+            drop(slab);
+            if let Some(f) = &self.on_overflow {
+                f(ix);
+            }
+            return 0;
         }
+        slab.insert(SendSyncWrapper::new(v)) + 1
     }
 
     #[inline]
-    pub fn unregister(&mut self, ix: usize) -> Option<Variant> {
+    pub fn unregister(&self, ix: usize) -> Option<Variant> {
         match ix.checked_sub(1) {
-            Some(ix) => self.slab.try_remove(ix).map(|v| v.into_inner()),
+            Some(ix) => self.slab.lock().try_remove(ix).map(|v| v.into_inner()),
             None => None,
         }
     }
 
     #[inline]
-    pub fn replace(&mut self, ix: usize, v: Variant) -> Option<Variant> {
+    pub fn replace(&self, ix: usize, v: Variant) -> Option<Variant> {
         if v.is_nil() {
             return self.unregister(ix);
         }
+        let mut slab = self.slab.lock();
         ix.checked_sub(1)
-            .and_then(|ix| self.slab.get_mut(ix))
+            .and_then(|ix| slab.get_mut(ix))
             .map(|p| mem::replace(p, SendSyncWrapper::new(v)).into_inner())
     }
 
@@ -60,4 +93,44 @@ impl ObjectRegistry {
     pub fn get_or_nil(&self, ix: usize) -> Variant {
         self.get(ix).unwrap_or_default()
     }
+
+    /// Gets snapshot of all registered values with their indices.
+    pub fn entries(&self) -> Vec<(usize, Variant)> {
+        self.slab
+            .lock()
+            .iter()
+            .map(|(ix, v)| (ix + 1, (**v).clone()))
+            .collect()
+    }
+}
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init, tool)]
+/// Object registry that can be shared between multiple instances.
+///
+/// Pass it with `extern.sharedRegistry` config.
+pub struct WasmObjectRegistry {
+    base: Base<RefCounted>,
+    pub registry: ObjectRegistry,
+}
+
+#[godot_api]
+impl WasmObjectRegistry {
+    /// Registers a value, returning it's index.
+    #[func]
+    fn register_object(&self, obj: Variant) -> i64 {
+        self.registry.register(obj) as _
+    }
+
+    /// Gets registered value.
+    #[func]
+    fn registry_get(&self, id: i64) -> Variant {
+        self.registry.get_or_nil(id as _)
+    }
+
+    /// Unregisters a value, returning it.
+    #[func]
+    fn unregister_object(&self, id: i64) -> Variant {
+        self.registry.unregister(id as _).unwrap_or_default()
+    }
 }
@@ -0,0 +1,86 @@
+use godot::classes::file_access::ModeFlags;
+use godot::classes::{FileAccess, IResourceFormatLoader, ResourceFormatLoader, ResourceLoader};
+use godot::global::Error as GError;
+use godot::prelude::*;
+use parking_lot::Mutex;
+
+use crate::godot_util::SendSyncWrapper;
+use crate::wasm_engine::WasmModule;
+
+static LOADER: Mutex<Option<SendSyncWrapper<Gd<WasmModuleLoader>>>> = Mutex::new(None);
+
+pub fn register_loader() {
+    let mut guard = LOADER.lock();
+    if guard.is_none() {
+        let loader = WasmModuleLoader::new_gd();
+        ResourceLoader::singleton().add_resource_format_loader(loader.clone().upcast());
+        *guard = Some(SendSyncWrapper::new(loader));
+    }
+}
+
+pub fn unregister_loader() {
+    if let Some(loader) = LOADER.lock().take() {
+        ResourceLoader::singleton().remove_resource_format_loader(loader.into_inner().upcast());
+    }
+}
+
+#[derive(GodotClass)]
+#[class(base=Resource, init, tool)]
+/// Resource containing `WasmModule` loaded from file.
+///
+/// It is returned by `load()` for `.wasm` and `.wat` files.
+/// Module is compiled every time the file is (re)loaded.
+pub struct WasmModuleResource {
+    base: Base<Resource>,
+
+    /// The compiled module.
+    #[var]
+    module: Option<Gd<WasmModule>>,
+}
+
+#[derive(GodotClass)]
+#[class(base=ResourceFormatLoader, init, tool)]
+/// Loader for `.wasm` and `.wat` files.
+struct WasmModuleLoader {
+    base: Base<ResourceFormatLoader>,
+}
+
+#[godot_api]
+impl IResourceFormatLoader for WasmModuleLoader {
+    fn get_recognized_extensions(&self) -> PackedStringArray {
+        PackedStringArray::from(&[GString::from("wasm"), GString::from("wat")])
+    }
+
+    fn handles_type(&self, ty: StringName) -> bool {
+        ty == StringName::from(c"WasmModuleResource") || ty == StringName::from(c"Resource")
+    }
+
+    fn get_resource_type(&self, path: GString) -> GString {
+        match path.get_extension().to_lower().chars() {
+            ['w', 'a', 's', 'm'] | ['w', 'a', 't'] => GString::from("WasmModuleResource"),
+            _ => GString::new(),
+        }
+    }
+
+    fn load(
+        &self,
+        path: GString,
+        _original_path: GString,
+        _use_sub_threads: bool,
+        _cache_mode: i32,
+    ) -> Variant {
+        let Some(file) = FileAccess::open(path.clone(), ModeFlags::READ) else {
+            godot_error!("Cannot open file {path}");
+            return GError::ERR_FILE_CANT_OPEN.to_variant();
+        };
+
+        let module = WasmModule::new_gd();
+        if !module.bind()._initialize(file.to_variant(), None) {
+            return GError::ERR_PARSE_ERROR.to_variant();
+        }
+
+        let mut ret = WasmModuleResource::new_gd();
+        ret.bind_mut().module = Some(module);
+        ret.to_variant()
+    }
+}
@@ -1,35 +1,48 @@
 use std::borrow::Borrow;
 use std::cell::{Cell, UnsafeCell};
+#[cfg(feature = "object-registry-extern")]
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::ffi::CStr;
+use std::fmt;
 use std::ops::{Deref, DerefMut};
+use std::ptr;
 use std::rc::Rc;
 use std::slice;
 #[cfg(feature = "epoch-timeout")]
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+#[cfg(feature = "epoch-timeout")]
 use std::time;
+use std::time::Duration;
 
-use anyhow::{Error, Result as AnyResult};
+use anyhow::{Context, Error, Result as AnyResult};
 
-use godot::classes::WeakRef;
+use godot::classes::{Engine, Os, SceneTree, Time, WeakRef};
+use godot::global::MethodFlags;
 use godot::prelude::*;
+use parking_lot::Mutex;
 
-#[cfg(feature = "epoch-timeout")]
-use wasmtime::UpdateDeadline;
+use wasmtime::HeapType;
 use wasmtime::{
-    AsContextMut, Caller, Extern, Func, FuncType, Linker, RootScope, Store, ValRaw, ValType,
+    AsContext, AsContextMut, Caller, Extern, ExternType, Func, FuncType, Linker, RootScope, Store,
+    ValRaw, ValType,
 };
 #[cfg(feature = "object-registry-extern")]
-use wasmtime::{ExternRef, HeapType, RefType};
+use wasmtime::{ExternRef, RefType};
+#[cfg(feature = "epoch-timeout")]
+use wasmtime::{Trap, UpdateDeadline};
 
 use crate::godot_util::{from_var_any, SendSyncWrapper};
 use crate::variant_dispatch;
 use crate::wasm_config::Config;
 use crate::wasm_engine::get_engine;
 #[cfg(feature = "epoch-timeout")]
-use crate::wasm_engine::start_epoch;
+use crate::wasm_engine::{get_epoch, start_epoch};
 #[cfg(feature = "object-registry-extern")]
 use crate::wasm_externref::{externref_to_variant, variant_to_externref};
-#[cfg(feature = "memory-limiter")]
-use crate::wasm_instance::MemoryLimit;
-use crate::wasm_instance::StoreData;
+use crate::wasm_instance::{MemoryLimit, StoreData};
 
 #[cfg(all(feature = "epoch-timeout", feature = "more-precise-timer"))]
 pub const EPOCH_MULTIPLIER: u64 = 1000;
@@ -51,6 +64,12 @@ pub const FILE_DIR: u32 = 2;
 pub const FILE_LINK: u32 = 3;
 */
 
+/// Maximum time waiting for deferred host call to be run by main thread.
+pub const DEFERRED_CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of funcrefs created from callables per store.
+pub const MAX_FUNCREFS: usize = 4096;
+
 pub const TYPE_I32: i64 = 1;
 pub const TYPE_I64: i64 = 2;
 pub const TYPE_F32: i64 = 3;
@@ -66,6 +85,12 @@ pub const OBJREGISTRY_MODULE: &str = "godot_object_v1";
 pub const EXTERNREF_MODULE: &str = "godot_object_v2";
 
 pub const MEMORY_EXPORT: &str = "memory";
+/// Module of built-in host imports (time, yield, rand, event, command buffer, epoch).
+pub const ENV_MODULE: &str = "env";
+pub const CONSTANTS_MODULE: &str = "constants";
+
+pub const DEFAULT_MAX_STRUCT_ELEMENTS: usize = 1 << 20;
+pub const DEFAULT_MAX_EVENTS: usize = 1024;
 
 #[macro_export]
 macro_rules! bail_with_site {
@@ -99,6 +124,24 @@ pub fn add_site(e: Error, site: Site<'static>) -> Error {
 }
 */
 
+/// Trap requested by host function via `signal_error`.
+#[derive(Debug, Clone)]
+pub struct HostTrap {
+    pub msg: String,
+    pub code: Option<i64>,
+}
+
+impl fmt::Display for HostTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{} (code {code})", self.msg),
+            None => f.write_str(&self.msg),
+        }
+    }
+}
+
+impl StdError for HostTrap {}
+
 #[macro_export]
 macro_rules! func_registry{
     ($head:literal, $($t:tt)*) => {
@@ -124,6 +167,121 @@ macro_rules! func_registry{
     };
 }
 
+func_registry! {
+    (TimeFuncs, ""),
+    delta => |_: Caller<'_, T>| -> f64 {
+        Engine::singleton()
+            .get_main_loop()
+            .and_then(|v| v.try_cast::<SceneTree>().ok())
+            .and_then(|v| v.get_root())
+            .map_or(0.0, |v| v.get_process_delta_time())
+    },
+    time => |_: Caller<'_, T>| -> f64 {
+        Time::singleton().get_ticks_usec() as f64 / 1e6
+    },
+}
+
+/// Provides `yield()` import.
+///
+/// Written by hand, as `yield` is a keyword and cannot be used with `func_registry!`.
+#[derive(Default)]
+pub struct YieldFuncs {
+    yield_: Option<Func>,
+}
+
+impl YieldFuncs {
+    pub fn get_func<T>(&mut self, store: &mut StoreContextMut<'_, T>, name: &str) -> Option<Func>
+    where
+        T: AsRef<StoreData> + AsMut<StoreData>,
+    {
+        match name {
+            "yield" => Some(
+                self.yield_
+                    .get_or_insert_with(move || {
+                        Func::wrap(store, |mut ctx: Caller<'_, T>| {
+                            ctx.data_mut().as_mut().yield_requested = true;
+                        })
+                    })
+                    .clone(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+func_registry! {
+    (RandFuncs, ""),
+    rand_stream => |mut ctx: Caller<'_, T>, id: i32| -> f64 {
+        let data = ctx.data_mut().as_mut();
+        let seed = data.rand_seed;
+        data.rand_streams
+            .entry(id)
+            .or_insert_with(|| RandStream::new(seed, id))
+            .next_f64()
+    },
+}
+
+func_registry! {
+    (EventFuncs, ""),
+    emit_event => |mut ctx: Caller<'_, T>, t: i32, p: u32, n: u32| -> AnyResult<()> {
+        let data = ctx.data().as_ref();
+        if data.events.len() >= data.max_events {
+            if data.event_overflow_error {
+                bail_with_site!("Event queue is full ({} events)", data.max_events);
+            }
+            return Ok(());
+        }
+
+        let mem = match ctx.get_export(MEMORY_EXPORT) {
+            Some(Extern::Memory(v)) => v,
+            _ => bail_with_site!("No memory exported"),
+        };
+        let (p, n) = (p as usize, n as usize);
+        let v = match p.checked_add(n).and_then(|e| mem.data(&ctx).get(p..e)) {
+            Some(v) => v.to_vec(),
+            None => bail_with_site!("Invalid memory bounds ({}-{})", p, p.wrapping_add(n)),
+        };
+        ctx.data_mut().as_mut().events.push_back((t, v));
+        Ok(())
+    },
+}
+
+/// Deterministic random stream (SplitMix64).
+#[derive(Clone, Copy, Debug)]
+pub struct RandStream(u64);
+
+impl RandStream {
+    pub fn new(seed: u64, id: i32) -> Self {
+        let mut ret = Self(seed ^ (id as u32 as u64).wrapping_mul(0xd1b5_4a32_d192_ed03));
+        ret.next_u64();
+        ret
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Generates number in range `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(feature = "epoch-timeout")]
+func_registry! {
+    (EpochFuncs, ""),
+    epoch_remaining => |ctx: Caller<'_, T>| -> i64 {
+        match ctx.data().as_ref() {
+            StoreData { epoch_timeout: 0, .. } => -1,
+            data => data.epoch_deadline.saturating_sub(get_epoch()) as _,
+        }
+    },
+}
+
 pub fn from_signature(sig: &FuncType) -> (PackedByteArray, PackedByteArray) {
     fn f(v: ValType) -> u8 {
         (match v {
@@ -189,7 +347,14 @@ pub fn to_signature(params: Variant, results: Variant) -> AnyResult<FuncType> {
 }
 
 // Mark this unsafe for future proofing
-pub unsafe fn to_raw(mut _store: impl AsContextMut, t: ValType, v: &Variant) -> AnyResult<ValRaw> {
+pub unsafe fn to_raw<T>(
+    mut _store: impl AsContextMut<Data = T>,
+    t: ValType,
+    v: &Variant,
+) -> AnyResult<ValRaw>
+where
+    T: AsRef<StoreData> + AsMut<StoreData>,
+{
     Ok(match t {
         ValType::I32 => ValRaw::i32(site_context!(from_var_any(v))?),
         ValType::I64 => ValRaw::i64(site_context!(from_var_any(v))?),
@@ -230,10 +395,81 @@ pub unsafe fn to_raw(mut _store: impl AsContextMut, t: ValType, v: &Variant) ->
                 None => bail_with_site!("Converting null into non-nullable WASM type"),
             })
         }
+        ValType::Ref(r) if matches!(r.heap_type(), HeapType::Func | HeapType::ConcreteFunc(_)) => {
+            ValRaw::funcref(match variant_to_funcref(&mut _store, r.heap_type(), v)? {
+                Some(f) => f.to_raw(_store),
+                None if r.is_nullable() => ptr::null_mut(),
+                None => bail_with_site!("Converting null into non-nullable WASM type"),
+            })
+        }
         _ => bail_with_site!("Unsupported WASM type conversion {}", t),
     })
 }
 
+/// Demangles Rust or C++ symbol name. Returns `None` if it's not mangled.
+#[cfg(feature = "demangle")]
+pub fn demangle_name(name: &str) -> Option<String> {
+    if let Ok(v) = rustc_demangle::try_demangle(name) {
+        return Some(format!("{v:#}"));
+    }
+    cpp_demangle::Symbol::new(name)
+        .ok()?
+        .demangle(&Default::default())
+        .ok()
+}
+
+/// Converts `Callable` (or host function dictionary) into funcref.
+///
+/// Plain `Callable` requires concrete function type, as it has no signature.
+/// The function lives as long as the store. Functions of object methods are cached per signature,
+/// other callables (lambdas, bound callables) create a new function every time.
+/// At most [`MAX_FUNCREFS`] functions can be created per store.
+fn variant_to_funcref<T>(
+    store: impl AsContextMut<Data = T>,
+    ty: &HeapType,
+    v: &Variant,
+) -> AnyResult<Option<Func>>
+where
+    T: AsRef<StoreData> + AsMut<StoreData>,
+{
+    let (sig, callable) = match v.get_type() {
+        VariantType::NIL => return Ok(None),
+        VariantType::CALLABLE => {
+            let HeapType::ConcreteFunc(sig) = ty else {
+                bail_with_site!("Cannot convert callable into untyped funcref, use host function dictionary instead")
+            };
+            (sig.clone(), CallableEnum::Callable(v.to()))
+        }
+        VariantType::DICTIONARY => process_func(v.to())?,
+        t => bail_with_site!("Cannot convert {t:?} into funcref"),
+    };
+
+    if let HeapType::ConcreteFunc(t) = ty {
+        if !FuncType::eq(&sig, t) {
+            bail_with_site!("Function type mismatch (expected {t}, got {sig})");
+        }
+    }
+
+    let mut store = store.as_context_mut();
+    let key = callable
+        .cache_key()
+        .map(|(id, method)| (id, method, sig.to_string()));
+    let data = store.data_mut().as_mut();
+    if let Some(f) = key.as_ref().and_then(|k| data.funcref_cache.get(k)) {
+        return Ok(Some(*f));
+    }
+    if data.funcref_count >= MAX_FUNCREFS {
+        bail_with_site!("Too many funcrefs created (max {MAX_FUNCREFS})");
+    }
+    data.funcref_count += 1;
+
+    let f = wrap_godot_method(&mut store, sig, callable);
+    if let Some(k) = key {
+        store.data_mut().as_mut().funcref_cache.insert(k, f);
+    }
+    Ok(Some(f))
+}
+
 // Mark this unsafe for future proofing
 pub unsafe fn from_raw(mut _store: impl AsContextMut, t: ValType, v: ValRaw) -> AnyResult<Variant> {
     Ok(match t {
@@ -336,13 +572,14 @@ thread_local! {
     }));
 }
 
-pub unsafe fn raw_call<It>(
-    ctx: impl AsContextMut,
+pub unsafe fn raw_call<T, It>(
+    mut ctx: impl AsContextMut<Data = T>,
     f: &Func,
     ty: &FuncType,
     args: It,
 ) -> AnyResult<VariantArray>
 where
+    T: AsRef<StoreData> + AsMut<StoreData>,
     It: IntoIterator,
     It::Item: Borrow<Variant>,
 {
@@ -351,68 +588,244 @@ where
     let pl = pi.len();
     let l = pl.max(ri.len());
 
+    if let Some(max) = ctx.as_context().data().as_ref().max_results {
+        if ri.len() > max {
+            bail_with_site!("Too many results (maximum {max}, got {})", ri.len())
+        }
+    }
+
     let v = PARAM_CACHE.with(|v| {
         // SAFETY: We have exclusive right to value.
         unsafe { ParamCache::get(&mut *v.get(), l) }
     });
     let mut v = v.get_data(l);
 
-    let mut ctx = RootScope::new(ctx);
-    ctx.as_context_mut().gc();
+    let r = {
+        let mut ctx = RootScope::new(&mut ctx);
+        ctx.as_context_mut().gc();
 
-    let mut args = args.into_iter();
-    for (p, (i, o)) in pi.zip(v.iter_mut().enumerate()) {
-        let Some(v) = args.next() else {
-            bail_with_site!("Too few parameters (expected {pl}, got {i})")
-        };
-        *o = to_raw(&mut ctx, p, v.borrow())?;
-    }
-    drop(args);
+        let mut args = args.into_iter();
+        for (p, (i, o)) in pi.zip(v.iter_mut().enumerate()) {
+            let Some(v) = args.next() else {
+                bail_with_site!("Too few parameters (expected {pl}, got {i})")
+            };
+            *o = to_raw(&mut ctx, p, v.borrow())?;
+        }
+        if ctx.as_context().data().as_ref().strict_arity {
+            let n = args.count();
+            if n > 0 {
+                bail_with_site!("Too many parameters (expected {pl}, got {})", pl + n)
+            }
+        } else {
+            drop(args);
+        }
 
-    f.call_unchecked(&mut ctx, v.as_mut_ptr(), v.len())?;
+        f.call_unchecked(&mut ctx, v.as_mut_ptr(), v.len())?;
 
-    ri.zip(v.iter())
-        .map(|(t, v)| from_raw(&mut ctx, t, *v))
-        .collect()
+        ri.zip(v.iter())
+            .map(|(t, v)| from_raw(&mut ctx, t, *v))
+            .collect::<AnyResult<VariantArray>>()
+    };
+
+    #[cfg(feature = "object-registry-extern")]
+    gc_after_call(ctx);
+
+    r
+}
+
+/// Runs garbage collection if `extern.gcAfterCall` is set.
+///
+/// Results are no longer rooted, so short-lived externrefs can be collected.
+#[cfg(feature = "object-registry-extern")]
+fn gc_after_call<T>(mut ctx: impl AsContextMut<Data = T>)
+where
+    T: AsRef<StoreData>,
+{
+    if ctx.as_context().data().as_ref().gc_after_call {
+        ctx.as_context_mut().gc();
+    }
 }
 
+#[derive(Clone)]
 enum CallableEnum {
     ObjectMethod(Gd<Object>, StringName),
     Callable(Callable),
 }
 
-fn wrap_godot_method<T>(
-    store: impl AsContextMut<Data = T>,
-    ty: FuncType,
-    callable: CallableEnum,
-) -> Func
+impl CallableEnum {
+    fn call(&self, p: Vec<Variant>) -> AnyResult<Variant> {
+        site_context!(match self {
+            Self::ObjectMethod(obj, method) => {
+                match obj.clone().try_cast::<WeakRef>() {
+                    Ok(obj) => site_context!(from_var_any(obj.get_ref()))?,
+                    Err(obj) => obj,
+                }
+                .try_call(method.clone(), &p)
+            }
+            Self::Callable(c) => Ok(c.callv(p.into_iter().collect())),
+        })
+    }
+
+    /// Gets object and method identifying the callable, if it has one.
+    fn cache_key(&self) -> Option<(InstanceId, String)> {
+        match self {
+            Self::ObjectMethod(obj, method) => Some((obj.instance_id(), method.to_string())),
+            Self::Callable(c) if c.is_custom() => None,
+            Self::Callable(c) => Some((c.object()?.instance_id(), c.method_name()?.to_string())),
+        }
+    }
+
+    /// Gets minimum and maximum argument count, if it can be known.
+    fn arity(&self) -> AnyResult<Option<(usize, usize)>> {
+        let (obj, method) = match self {
+            // Weak reference target may change later.
+            Self::ObjectMethod(obj, _) if obj.clone().try_cast::<WeakRef>().is_ok() => {
+                return Ok(None)
+            }
+            Self::ObjectMethod(obj, method) => (obj.clone(), method.clone()),
+            // Custom callables (lambdas, bound callables) have no inspectable signature.
+            Self::Callable(c) if c.is_custom() => return Ok(None),
+            Self::Callable(c) => match (c.object(), c.method_name()) {
+                (Some(obj), Some(method)) => (obj, method),
+                _ => return Ok(None),
+            },
+        };
+
+        if !obj.has_method(method.clone()) {
+            bail_with_site!("Object {obj} has no method {method}");
+        }
+
+        for d in obj.get_method_list().iter_shared() {
+            if d.get(StringName::from(c"name"))
+                .map_or(true, |v| v.to_string() != method.to_string())
+            {
+                continue;
+            }
+
+            let flags = d
+                .get(StringName::from(c"flags"))
+                .map_or(0, |v| v.try_to::<i64>().unwrap_or(0));
+            if flags & (MethodFlags::VARARG.ord() as i64) != 0 {
+                return Ok(None);
+            }
+
+            let len = |k: &CStr| {
+                d.get(StringName::from(k))
+                    .map_or(0, |v| v.try_to::<VariantArray>().map_or(0, |v| v.len()))
+            };
+            let max = len(c"args");
+            return Ok(Some((max.saturating_sub(len(c"default_args")), max)));
+        }
+
+        Ok(None)
+    }
+}
+
+/// Checks host function type against imported function type.
+fn check_signature(ty: &FuncType, import_ty: &FuncType) -> AnyResult<()> {
+    if !FuncType::eq(ty, import_ty) {
+        bail_with_site!(
+            "Signature mismatch (import has {} parameters and {} results, host has {} parameters and {} results)",
+            import_ty.params().len(),
+            import_ty.results().len(),
+            ty.params().len(),
+            ty.results().len(),
+        );
+    }
+    Ok(())
+}
+
+/// Checks host function against imported function type.
+fn check_host_func(ty: &FuncType, import_ty: &FuncType, callable: &CallableEnum) -> AnyResult<()> {
+    check_signature(ty, import_ty)?;
+
+    if let Some((min, max)) = callable.arity()? {
+        let n = import_ty.params().len();
+        if n < min || n > max {
+            bail_with_site!(
+                "Host function accepts {min} to {max} arguments, but import has {n} parameters"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs function in main thread and wait for it's result.
+///
+/// If it's already in main thread, run it immediately.
+/// Otherwise it's run with `call_deferred()`, which means it has to wait
+/// until main thread process deferred calls (usually at the end of frame).
+/// Store must be released before calling it, so main thread can use the instance.
+/// Fails if main thread does not run it within [`DEFERRED_CALL_TIMEOUT`].
+/// Function is discarded on timeout, so a late deferred call does nothing.
+/// It may still run if main thread picked it up right before timing out.
+fn call_on_main_thread<F>(f: F) -> AnyResult<Variant>
 where
-    T: AsRef<StoreData> + AsMut<StoreData>,
+    F: FnOnce() -> AnyResult<Variant> + 'static,
 {
-    let callable = SendSyncWrapper::new(callable);
-    let ty_cloned = ty.clone();
-    let f = move |mut ctx: Caller<T>, args: &mut [ValRaw]| -> AnyResult<()> {
+    let os = Os::singleton();
+    if os.get_thread_caller_id() == os.get_main_thread_id() {
+        return f();
+    }
+
+    let (tx, rx) = mpsc::sync_channel(1);
+    let f = Arc::new(Mutex::new(Some(SendSyncWrapper::new(f))));
+    let f_ = f.clone();
+    Callable::from_fn("deferred_host_call", move |_| {
+        // Take it out first, so the lock is not held while running it.
+        let f = f_.lock().take();
+        if let Some(f) = f {
+            let _ = tx.send(SendSyncWrapper::new(f.into_inner()()));
+        }
+        Ok(Variant::nil())
+    })
+    .call_deferred(&[]);
+
+    match rx.recv_timeout(DEFERRED_CALL_TIMEOUT) {
+        Ok(v) => v.into_inner(),
+        Err(RecvTimeoutError::Timeout) => {
+            drop(f.lock().take());
+            bail_with_site!(
+                "Deferred host call timed out after {}s (is main thread blocked?)",
+                DEFERRED_CALL_TIMEOUT.as_secs()
+            )
+        }
+        Err(RecvTimeoutError::Disconnected) => bail_with_site!("Deferred host call is dropped"),
+    }
+}
+
+/// Godot method callable from WASM. It does not depend on store.
+struct GodotMethod {
+    ty: FuncType,
+    callable: SendSyncWrapper<CallableEnum>,
+}
+
+impl GodotMethod {
+    fn call<T>(&self, mut ctx: Caller<'_, T>, args: &mut [ValRaw]) -> AnyResult<()>
+    where
+        T: AsRef<StoreData> + AsMut<StoreData>,
+    {
+        let Self { ty, callable } = self;
         let pi = ty.params();
         let mut p = Vec::with_capacity(pi.len());
         for (ix, t) in pi.enumerate() {
             p.push(unsafe { from_raw(&mut ctx, t, args[ix])? });
         }
 
-        let r = ctx.data_mut().as_mut().release_store(|| {
-            site_context!(match &*callable {
-                CallableEnum::ObjectMethod(obj, method) => {
-                    match obj.clone().try_cast::<WeakRef>() {
-                        Ok(obj) => site_context!(from_var_any(obj.get_ref()))?,
-                        Err(obj) => obj,
-                    }
-                    .try_call(method.clone(), &p)
-                }
-                CallableEnum::Callable(c) => Ok(c.callv(p.into_iter().collect())),
-            })
+        let data = ctx.data_mut().as_mut();
+        let defer = data.defer_host_calls;
+        let r = data.host_call(|| {
+            if defer {
+                let callable = (**callable).clone();
+                call_on_main_thread(move || callable.call(p))
+            } else {
+                callable.call(p)
+            }
         })?;
 
-        if let Some(msg) = ctx.data_mut().as_mut().error_signal.take() {
-            return Err(Error::msg(msg));
+        if let Some(e) = ctx.data_mut().as_mut().error_signal.take() {
+            return Err(e.into());
         }
 
         let mut ri = ty.results();
@@ -438,13 +851,26 @@ where
             ..
         } = *ctx.data().as_ref()
         {
-            ctx.as_context_mut().set_epoch_deadline(v);
+            set_epoch_deadline(&mut ctx, v);
         }
 
         Ok(())
-    };
+    }
+}
 
-    unsafe { Func::new_unchecked(store, ty_cloned, f) }
+fn wrap_godot_method<T>(
+    store: impl AsContextMut<Data = T>,
+    ty: FuncType,
+    callable: CallableEnum,
+) -> Func
+where
+    T: AsRef<StoreData> + AsMut<StoreData>,
+{
+    let m = GodotMethod {
+        ty: ty.clone(),
+        callable: SendSyncWrapper::new(callable),
+    };
+    unsafe { Func::new_unchecked(store, ty, move |ctx, args| m.call(ctx, args)) }
 }
 
 fn process_func(dict: Dictionary) -> AnyResult<(FuncType, CallableEnum)> {
@@ -478,17 +904,61 @@ fn process_func(dict: Dictionary) -> AnyResult<(FuncType, CallableEnum)> {
     Ok((to_signature(params, results)?, callable))
 }
 
+/// Asks fallback resolver for a function to bind to an otherwise unresolved import.
+///
+/// Resolver is called with `(module, name, params, results)` and returns either
+/// a `Callable` to bind or `null` to reject the import.
+pub fn resolve_fallback_import<T>(
+    store: impl AsContextMut<Data = T>,
+    resolver: &Callable,
+    module: &str,
+    name: &str,
+    ty: &FuncType,
+) -> AnyResult<Option<Func>>
+where
+    T: AsRef<StoreData> + AsMut<StoreData>,
+{
+    let (params, results) = from_signature(ty);
+    let v = resolver.callv(
+        [
+            module.to_variant(),
+            name.to_variant(),
+            params.to_variant(),
+            results.to_variant(),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    let callable = match v.get_type() {
+        VariantType::NIL => return Ok(None),
+        VariantType::CALLABLE => v.to::<Callable>(),
+        t => bail_with_site!("Fallback resolver returned {t:?} for import {module}.{name}"),
+    };
+    if !callable.is_valid() {
+        bail_with_site!("Fallback resolver returned invalid callable for import {module}.{name}");
+    }
+
+    Ok(Some(wrap_godot_method(
+        store,
+        ty.clone(),
+        CallableEnum::Callable(callable),
+    )))
+}
+
 pub struct HostModuleCache<T> {
-    cache: Linker<T>,
+    /// Host functions are store-independent, so it can be shared between instances.
+    cache: Arc<Mutex<Linker<T>>>,
     host: Dictionary,
 }
 
 impl<T: AsRef<StoreData> + AsMut<StoreData>> HostModuleCache<T> {
     pub fn new(host: Dictionary) -> AnyResult<Self> {
-        Ok(Self {
-            cache: Linker::new(&site_context!(get_engine())?),
-            host,
-        })
+        Ok(Self::with_linker(host, new_host_linker()?))
+    }
+
+    /// Uses linker from previous instantiation with the same host.
+    pub fn with_linker(host: Dictionary, cache: Arc<Mutex<Linker<T>>>) -> Self {
+        Self { cache, host }
     }
 
     pub fn get_extern<S: AsContextMut<Data = T>>(
@@ -496,8 +966,10 @@ impl<T: AsRef<StoreData> + AsMut<StoreData>> HostModuleCache<T> {
         store: &mut S,
         module: &str,
         name: &str,
+        ty: &ExternType,
     ) -> AnyResult<Option<Extern>> {
-        if let r @ Some(_) = self.cache.get(&mut *store, module, name) {
+        let mut cache = self.cache.lock();
+        if let r @ Some(_) = cache.get(&mut *store, module, name) {
             Ok(r)
         } else if let Some(data) = self
             .host
@@ -507,16 +979,47 @@ impl<T: AsRef<StoreData> + AsMut<StoreData>> HostModuleCache<T> {
             .and_then(|d| d.get(name))
         {
             let (sig, callable) = process_func(site_context!(from_var_any::<Dictionary>(data))?)?;
+            if let ExternType::Func(t) = ty {
+                check_host_func(&sig, t, &callable).with_context(|| {
+                    format!("Cannot use host function for import {module}.{name}")
+                })?;
+            }
 
-            let v = Extern::from(wrap_godot_method(&mut *store, sig, callable));
-            self.cache.define(store, module, name, v.clone())?;
-            Ok(Some(v))
+            let m = GodotMethod {
+                ty: sig.clone(),
+                callable: SendSyncWrapper::new(callable),
+            };
+            unsafe {
+                cache.func_new_unchecked(module, name, sig, move |ctx, args| m.call(ctx, args))?;
+            }
+            Ok(cache.get(store, module, name))
         } else {
             Ok(None)
         }
     }
 }
 
+/// Creates empty linker for [`HostModuleCache`].
+pub fn new_host_linker<T>() -> AnyResult<Arc<Mutex<Linker<T>>>> {
+    Ok(Arc::new(Mutex::new(Linker::new(&site_context!(
+        get_engine()
+    )?))))
+}
+
+/// Sets epoch deadline, recording it for `epoch_remaining` import.
+#[cfg(feature = "epoch-timeout")]
+pub fn set_epoch_deadline<T>(mut store: impl AsContextMut<Data = T>, v: u64)
+where
+    T: AsMut<StoreData>,
+{
+    let mut store = store.as_context_mut();
+    let now = get_epoch();
+    let data = store.data_mut().as_mut();
+    data.epoch_deadline = now.saturating_add(v);
+    let v = data.next_deadline(now);
+    store.set_epoch_deadline(v);
+}
+
 #[cfg(feature = "epoch-timeout")]
 pub fn config_store_epoch<T>(store: &mut Store<T>, config: &Config) -> AnyResult<()> {
     if config.with_epoch {
@@ -529,6 +1032,41 @@ pub fn config_store_epoch<T>(store: &mut Store<T>, config: &Config) -> AnyResult
     Ok(())
 }
 
+/// Sets epoch deadline handler of instance store.
+///
+/// It traps if interrupted, or if epoch timeout or call budget is reached.
+#[cfg(feature = "epoch-timeout")]
+pub fn config_store_deadline<T>(store: &mut Store<T>)
+where
+    T: AsRef<StoreData> + AsMut<StoreData>,
+{
+    store.epoch_deadline_callback(|mut ctx| {
+        let now = get_epoch();
+        let data = ctx.data_mut().as_mut();
+        if data
+            .interrupt
+            .as_ref()
+            .map_or(false, |v| v.load(Ordering::Acquire))
+            || (data.epoch_timeout > 0 && now >= data.epoch_deadline)
+        {
+            return Err(Trap::Interrupt.into());
+        }
+        if data.budget_deadline.map_or(false, |v| now >= v) {
+            data.budget_exceeded = true;
+            return Err(Trap::Interrupt.into());
+        }
+        Ok(UpdateDeadline::Continue(data.next_deadline(now)))
+    });
+    let v = store.data().as_ref().next_deadline(get_epoch());
+    store.set_epoch_deadline(v);
+}
+
+/// Fills store with unlimited fuel, as the engine always consumes it.
+#[cfg(feature = "fuel")]
+pub fn config_store_fuel<T>(store: &mut Store<T>) -> AnyResult<()> {
+    store.set_fuel(u64::MAX)
+}
+
 pub fn config_store_common<T>(_store: &mut Store<T>, _config: &Config) -> AnyResult<()>
 where
     T: AsRef<StoreData> + AsMut<StoreData>,
@@ -543,13 +1081,148 @@ where
             0
         };
         data.epoch_autoreset = _config.epoch_autoreset;
+        data.epoch_deadline = get_epoch().saturating_add(_config.epoch_timeout);
+        data.interrupt = _config.epoch_interrupt.then(Arc::default);
+        data.budget_deadline = None;
+        if _config.epoch_interrupt {
+            // Interrupt is checked every tick.
+            site_context!(start_epoch())?;
+        }
+        config_store_deadline(&mut *_store);
     }
 
-    #[cfg(feature = "memory-limiter")]
+    #[cfg(feature = "fuel")]
+    config_store_fuel(&mut *_store)?;
+
+    _store.data_mut().as_mut().memory_limits = MemoryLimit::from_config(_config);
+    _store.limiter(|data| &mut data.as_mut().memory_limits);
+
+    let data = _store.data_mut().as_mut();
+    data.max_results = _config.max_results;
+    data.strict_arity = _config.strict_arity;
+    data.max_host_reentry = _config.max_host_reentry;
+    data.max_call_depth = _config.max_call_depth;
+    #[cfg(feature = "fuel")]
+    {
+        data.fuel_budget = _config.fuel_budget;
+        data.fuel_multipliers = _config.export_fuel_multipliers.clone();
+    }
+    #[cfg(feature = "object-registry-compat")]
+    {
+        data.auto_unwrap_registry = _config.auto_unwrap_registry;
+    }
+    data.max_struct_elements = _config
+        .max_struct_elements
+        .unwrap_or(DEFAULT_MAX_STRUCT_ELEMENTS);
+    data.single_threaded_memory_ops = _config.single_threaded_memory_ops;
+    data.alloc_func = _config.alloc_func.clone();
+    data.free_func = _config.free_func.clone();
+    data.defer_host_calls = _config.defer_host_calls;
+    data.yield_requested = false;
+    data.rand_seed = _config.wasi_random_seed.unwrap_or_default();
+    data.rand_streams.clear();
+    data.events.clear();
+    data.max_events = _config.max_events.unwrap_or(DEFAULT_MAX_EVENTS);
+    data.event_overflow_error = _config.event_overflow_error;
+    data.profile = _config.profile.then(Default::default);
+    data.trace = _config.trace_calls.then(Default::default);
+    data.memory_stats = _config.collect_stats.then(Default::default);
+    #[cfg(feature = "object-registry-extern")]
     {
-        _store.data_mut().as_mut().memory_limits = MemoryLimit::from_config(_config);
-        _store.limiter(|data| &mut data.as_mut().memory_limits);
+        data.externref_identity = _config.extern_identity.then(HashMap::new);
+        data.gc_after_call = _config.gc_after_call;
+        data.max_externrefs = _config.max_externrefs;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_signature() {
+        let engine = wasmtime::Engine::default();
+        let import_ty = FuncType::new(&engine, [ValType::I32, ValType::I64], [ValType::F32]);
+        check_signature(&import_ty.clone(), &import_ty).unwrap();
+
+        let ty = FuncType::new(&engine, [ValType::I32], []);
+        let e = check_signature(&ty, &import_ty).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Signature mismatch (import has 2 parameters and 1 results, host has 1 parameters and 0 results)"
+        );
+
+        // Same counts, but different types.
+        let ty = FuncType::new(&engine, [ValType::I64, ValType::I64], [ValType::F32]);
+        let e = check_signature(&ty, &import_ty).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Signature mismatch (import has 2 parameters and 1 results, host has 2 parameters and 1 results)"
+        );
+    }
+
+    #[cfg(feature = "object-registry-extern")]
+    #[test]
+    fn test_gc_after_call() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const WAT: &str = r#"
+        (module
+          (import "host" "make" (func $make (result externref)))
+          (func (export "churn") (param i32)
+            (block $done
+              (loop $l
+                local.get 0
+                i32.eqz
+                br_if $done
+                call $make
+                drop
+                local.get 0
+                i32.const 1
+                i32.sub
+                local.set 0
+                br $l))))
+        "#;
+
+        struct Counted(Arc<AtomicUsize>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        // Counts externrefs left alive after a call that creates and drops 64 of them.
+        fn live_after_call(gc: bool) -> usize {
+            let live = Arc::new(AtomicUsize::new(0));
+            let engine = wasmtime::Engine::default();
+            let module = wasmtime::Module::new(&engine, WAT).unwrap();
+            let mut store = Store::new(
+                &engine,
+                StoreData {
+                    gc_after_call: gc,
+                    ..StoreData::default()
+                },
+            );
+            let live_ = live.clone();
+            let make = Func::wrap(
+                &mut store,
+                move |mut caller: Caller<'_, StoreData>| -> AnyResult<_> {
+                    live_.fetch_add(1, Ordering::SeqCst);
+                    ExternRef::new(&mut caller, Counted(live_.clone())).map(Some)
+                },
+            );
+            let inst = wasmtime::Instance::new(&mut store, &module, &[make.into()]).unwrap();
+            let churn = inst.get_typed_func::<i32, ()>(&mut store, "churn").unwrap();
+
+            churn.call(&mut store, 64).unwrap();
+            gc_after_call(&mut store);
+            live.load(Ordering::SeqCst)
+        }
+
+        assert!(live_after_call(false) > 0);
+        assert_eq!(live_after_call(true), 0);
+    }
+}